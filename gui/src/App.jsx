@@ -14,7 +14,7 @@ import { DmoDataPage } from './DmoData';
 import { LibraryPage } from './Library';
 
 import { CurrentPage, EditorsLayout, NewProjectTemplateString } from './Helpers';
-import type { ServerMsg, DmoData, Shader, ShaderEditors, ViewState } from './Helpers';
+import type { ServerMsg, DmoData, Shader, ShaderEditors, ViewState, ProfileEvent, SelectedObject } from './Helpers';
 
 const PLAZMA_SERVER_PORT = 8080;
 
@@ -35,6 +35,8 @@ type AppState = {
     view: ViewState,
     current_page: number,
     current_time: number,
+    profile_events: ProfileEvent[],
+    selected_object: ?SelectedObject,
     preview_is_open: bool,
     sentUpdateSinceChange: bool,
     updatesToSend: AppUpdates,
@@ -79,6 +81,8 @@ class App extends Component<{}, AppState> {
             },
             current_page: CurrentPage.Shaders,
             current_time: 0.0,
+            profile_events: [],
+            selected_object: null,
             preview_is_open: false,
             sentUpdateSinceChange: true,
             updatesToSend: {
@@ -247,6 +251,16 @@ class App extends Component<{}, AppState> {
             case 'GetDmoTime':
                 break;
 
+            case 'ProfileResults':
+                let profile = JSON.parse(msg.data);
+                this.setState({ profile_events: profile.events });
+                break;
+
+            case 'SelectedObject':
+                let selected: ?SelectedObject = JSON.parse(msg.data);
+                this.setState({ selected_object: selected });
+                break;
+
             case 'PreviewOpened':
                 // clear possible old errors from shaders
                 shaders = this.state.shaders.map((i) => { i.error_data = null; return i; });
@@ -363,6 +377,16 @@ class App extends Component<{}, AppState> {
         this.sendMsgOnSocket(msg);
     }
 
+    onClick_CaptureTrace = () =>
+    {
+        let msg: ServerMsg = {
+            data_type: 'StartTraceCapture',
+            data: JSON.stringify({ frames: 120, path: null }),
+        };
+        console.log('Sending server: StartTraceCapture');
+        this.sendMsgOnSocket(msg);
+    }
+
     onChange_Metadata = (msg: ServerMsg) =>
     {
         if (msg.data_type === 'SetMetadata') {
@@ -650,6 +674,9 @@ class App extends Component<{}, AppState> {
                             dmoData={this.state.dmo_data}
                             onChange_Metadata={this.onChange_Metadata}
                             onChange_Settings={this.onChange_Settings}
+                            profileEvents={this.state.profile_events}
+                            selectedObject={this.state.selected_object}
+                            onClick_CaptureTrace={this.onClick_CaptureTrace}
                         />;
                     break;
 
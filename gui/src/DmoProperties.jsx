@@ -1,8 +1,8 @@
 // @flow
 import React from 'react';
-import { Column, Columns, Panel, PanelHeading, Field, Label, Control, Input, Checkbox, Title } from 'bloomer';
+import { Column, Columns, Panel, PanelHeading, Field, Label, Control, Input, Checkbox, Title, Table, Button } from 'bloomer';
 import { CurrentPage } from './Helpers';
-import type { ServerMsg, DmoData, InputEvent } from './Helpers';
+import type { ServerMsg, DmoData, InputEvent, ProfileEvent, SelectedObject } from './Helpers';
 
 type DPP_Props = {
     currentPage: number,
@@ -121,6 +121,18 @@ class SettingsSection extends React.Component<SSec_Props> {
                         </Control>
                     </Field>
 
+                    <Field>
+                        <Control>
+                            <Checkbox
+                                name='loop_at_end'
+                                checked={this.props.dmoData.settings.loop_at_end}
+                                onChange={this.onChangeLocal}
+                            >
+                                Loop at the end
+                            </Checkbox>
+                        </Control>
+                    </Field>
+
                 </Column>
             </Columns>
         );
@@ -256,10 +268,87 @@ class MetadataSection extends React.Component<MSec_Props> {
     }
 }
 
+type PSec_Props = {
+    profileEvents: ProfileEvent[],
+    onClick_CaptureTrace: () => void,
+};
+
+class ProfileSection extends React.Component<PSec_Props> {
+    render() {
+        let rows = this.props.profileEvents.map((i, idx) => {
+            return (
+                <tr key={idx}>
+                    <td>{i.name}</td>
+                    <td>{i.gpu_ms.toFixed(3)}</td>
+                    <td>{i.cpu_ms.toFixed(3)}</td>
+                </tr>
+            );
+        });
+
+        return (
+            <Columns isMultiline={true}>
+                <Column isSize='full'>
+                    <Title>Profile</Title>
+                </Column>
+
+                <Column isSize='full'>
+                    <Table isNarrow={true}>
+                        <thead>
+                            <tr>
+                                <th>Label</th>
+                                <th>GPU ms</th>
+                                <th>CPU ms since frame start</th>
+                            </tr>
+                        </thead>
+                        <tbody>
+                            {rows}
+                        </tbody>
+                    </Table>
+                </Column>
+
+                <Column isSize='full'>
+                    <Button onClick={this.props.onClick_CaptureTrace}>
+                        Capture Trace
+                    </Button>
+                    <p>Saves the next frames of the preview as trace.json in the project folder. Open it with chrome://tracing or Perfetto.</p>
+                </Column>
+            </Columns>
+        );
+    }
+}
+
+type SSec_Props = {
+    selectedObject: ?SelectedObject,
+};
+
+class SelectionSection extends React.Component<SSec_Props> {
+    render() {
+        let selected = this.props.selectedObject;
+
+        return (
+            <Columns isMultiline={true}>
+                <Column isSize='full'>
+                    <Title>Selected Object</Title>
+                </Column>
+
+                <Column isSize='full'>
+                    {selected
+                        ? <p><strong>{selected.object}</strong> in {selected.scene}</p>
+                        : <p>Nothing is selected.</p>}
+                    <p>Right-click an object in the preview to select it, or press Tab to select the next one.</p>
+                </Column>
+            </Columns>
+        );
+    }
+}
+
 type PP_Props = {
     dmoData: DmoData,
     onChange_Metadata: (ServerMsg) => void,
     onChange_Settings: (ServerMsg) => void,
+    profileEvents: ProfileEvent[],
+    selectedObject: ?SelectedObject,
+    onClick_CaptureTrace: () => void,
 };
 
 export class PropertiesPage extends React.Component<PP_Props> {
@@ -275,6 +364,15 @@ export class PropertiesPage extends React.Component<PP_Props> {
                     dmoData={this.props.dmoData}
                     onChangeLift={this.props.onChange_Settings}
                 />
+
+                <ProfileSection
+                    profileEvents={this.props.profileEvents}
+                    onClick_CaptureTrace={this.props.onClick_CaptureTrace}
+                />
+
+                <SelectionSection
+                    selectedObject={this.props.selectedObject}
+                />
             </div>
         );
     }
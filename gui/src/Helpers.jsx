@@ -92,6 +92,17 @@ export type ServerMsg = {
     data: string,
 };
 
+export type ProfileEvent = {
+    name: string,
+    gpu_ms: number,
+    cpu_ms: number,
+};
+
+export type SelectedObject = {
+    scene: string,
+    object: string,
+};
+
 export type PixelFormat = "NOOP" | "RED_u8" | "RGB_u8" | "RGBA_u8";
 
 export type BufferKind = "NOOP" | "Empty_Texture" | "Image_Texture";
@@ -135,6 +146,14 @@ export type BuiltIn =
     | "Light_Linear_Falloff"
     | "Light_Quadratic_Falloff"
     | "Light_Cutoff_Angle"
+    | "Block_Time"
+    | "Block_Progress"
+    | "Block_Fade"
+    | "Beat"
+    | "Bar"
+    | "Beat_Phase"
+    | "Frame_Index"
+    | "Delta_Time"
     | [ string ];
 
 export type UniformMapping =
@@ -142,35 +161,56 @@ export type UniformMapping =
     | { Float: [number, BuiltIn] }
     | { Vec2: [number, BuiltIn, BuiltIn] }
     | { Vec3: [number, BuiltIn, BuiltIn, BuiltIn] }
-    | { Vec4: [number, BuiltIn, BuiltIn, BuiltIn, BuiltIn] };
+    | { Vec4: [number, BuiltIn, BuiltIn, BuiltIn, BuiltIn] }
+    | { LightSpaceMatrix: [number, number] };
 
 export type BufferMapping =
     | "NOOP"
-    | { Sampler2D: [number, string] };
+    | { Sampler2D: [number, string] }
+    | { ShadowMap: [number, number] };
 
 export type QuadScene = {
     name: string,
     vert_src_path: string,
     frag_src_path: string,
     layout_to_vars: UniformMapping[],
-    binding_to_buffers: BufferMapping[]
+    binding_to_buffers: BufferMapping[],
+    bind_uniforms_by_name: boolean,
 };
 
 export type SceneObject = {
     name: string,
+    id: ?string,
+    parent: ?string,
+    instances: ?Instances,
+    casts_shadow: boolean,
+    frustum_culling: boolean,
     position: string,// ValueVec3, TODO union
     euler_rotation: string,// ValueVec3, TODO
+    quaternion_rotation: ?string,// ValueVec4, TODO
     scale: string,// ValueFloat, TODO
     layout_to_vars: any[],// UniformMapping[], TODO
     binding_to_buffers: any[],// BufferMapping[], TODO
+    bind_uniforms_by_name: boolean,
 };
 
+export type Instances = {
+    layout: InstanceLayout,
+    count: ?string,// ValueFloat, TODO
+};
+
+export type InstanceLayout =
+    | { Grid: { columns: number, rows: number, layers: number, spacing: number } }
+    | { Ring: { count: number, radius: number } }
+    | { Random: { count: number, seed: number, extent: number[], rotate: boolean } }
+    | { Data: { path: string } };
+
 export type PolygonScene = {
     name: string,
     scene_objets: SceneObject[],
 };
 
-export type ModelType = "NOOP" | "Cube" | "Obj";
+export type ModelType = "NOOP" | "Cube" | "Obj" | "Gltf" | "Primitive";
 
 export type Model = {
     name: string,
@@ -178,10 +218,64 @@ export type Model = {
     vert_src_path: string,
     frag_src_path: string,
     obj_path: string,
+    gltf_path: string,
+    primitive: ?Primitive,
+    flip_faces: boolean,
+    computed_normals: ComputedNormals,
 };
 
+export type Primitive =
+    | { UvSphere: { radius: number, segments: number, rings: number } }
+    | { Icosphere: { radius: number, subdivisions: number } }
+    | { Plane: { width: number, depth: number, segments_x: number, segments_z: number } }
+    | { Torus: { major_radius: number, minor_radius: number, segments: number, sides: number } }
+    | { Cylinder: { radius: number, height: number, segments: number } }
+    | { Cone: { radius: number, height: number, segments: number } };
+
+export type ComputedNormals = "Smooth" | "Flat";
+
 export type PolygonContext = {
     models: Model[],
+    lights: Light[],
+    camera: CameraMode,
+};
+
+export type CameraMode =
+    | "Sync_Front"
+    | "Sync_LookAt"
+    | { Path: CameraPath };
+
+export type CameraPath = {
+    points: CameraPathPoint[],
+    closed: boolean,
+    progress: string,// ValueFloat, TODO
+};
+
+export type CameraPathPoint = {
+    position: number[],
+    look_at: number[],
+};
+
+export type LightType = "Point" | "Directional" | "Spot";
+
+export type Light = {
+    light_type: LightType,
+    position: ?string,// ValueVec3, TODO
+    direction: ?string,// ValueVec3, TODO
+    color: ?string,// ValueVec3, TODO
+    strength: ?string,// ValueFloat, TODO
+    falloff: ?string,// ValueVec3, TODO
+    cutoff_angle: ?string,// ValueFloat, TODO
+    outer_cutoff_angle: ?string,// ValueFloat, TODO
+    shadow: ?Shadow,
+};
+
+export type Shadow = {
+    resolution: number,
+    bias: number,
+    extent: ?number,
+    znear: ?number,
+    zfar: ?number,
 };
 
 // BTreeMap<String, usize>
@@ -213,18 +307,78 @@ export type ContextData = {
     index: DataIndex,
 };
 
+// Seconds, or bars and beats resolved with the project bpm.
+export type TimePos =
+    | number
+    | { bar?: number, beat?: number };
+
 export type SceneBlock = {
-    start: number,
-    end: number,
+    start: TimePos,
+    end: TimePos,
     draw_ops: string[],// DrawOp[], TODO enum
+    fade_in: number,
+    fade_out: number,
+    transition: Transition,
+    enabled: bool,
+    muted: bool,
+    solo: bool,
 };
 
+export type Transition =
+    | "Crossfade"
+    | "Wipe"
+    | { Custom: string };
+
 export type TimeTrack = {
     scene_blocks: SceneBlock[],
+    instances: CompositionInstance[],
+    enabled: bool,
+    muted: bool,
+    solo: bool,
+};
+
+export type TimelineFlags = {
+    enabled: bool,
+    muted: bool,
+    solo: bool,
+};
+
+// Data of the SetTimelineFlags message. Sets the flags of the block when
+// block_idx is given, otherwise of the track.
+export type SetTimelineFlagsMsg = {
+    composition: ?string,
+    track_idx: number,
+    block_idx: ?number,
+    flags: TimelineFlags,
+};
+
+export type Composition = {
+    name: string,
+    tracks: TimeTrack[],
+};
+
+export type CompositionInstance = {
+    composition: string,
+    start: TimePos,
+    time_scale: ?number,
+    duration: ?TimePos,
+};
+
+export type Marker = {
+    name: string,
+    time: TimePos,
+};
+
+export type LoopRegion = {
+    start: TimePos,
+    end: TimePos,
 };
 
 export type Timeline = {
     tracks: TimeTrack[],
+    compositions: Composition[],
+    markers: Marker[],
+    loop_region: ?LoopRegion,
 };
 
 export type DmoData = {
@@ -243,6 +397,8 @@ export type DmoData = {
         mouse_sensitivity: number,
         movement_sensitivity: number,
         total_length: number,
+        bpm: ?number,
+        loop_at_end: bool,
         [string]: mixed,
     },
     context: ContextData,
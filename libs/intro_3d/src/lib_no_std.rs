@@ -8,7 +8,7 @@
 
 // http://mathworld.wolfram.com/RotationMatrix.html
 
-use core::intrinsics::{cosf32, cosf64, sinf32, sinf64, sqrtf32};
+use core::intrinsics::{cosf32, cosf64, fabsf32, sinf32, sinf64, sqrtf32};
 use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
 
 pub const PI: f64 = 3.14159265358979323846;
@@ -72,6 +72,10 @@ impl Vector3 {
 
         Vector3::new(ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx)
     }
+
+    pub fn dot(&self, b: &Vector3) -> f32 {
+        self.x * b.x + self.y * b.y + self.z * b.z
+    }
 }
 
 // === Vector4 =================================================================
@@ -334,6 +338,32 @@ impl Matrix4 {
         res
     }
 
+    /// Builds a right-handed orthographic projection matrix of the box between the planes.
+    pub fn new_orthographic(
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+        znear: f32,
+        zfar: f32,
+    ) -> Matrix4 {
+        if right - left == 0.0 || top - bottom == 0.0 || zfar - znear == 0.0 {
+            panic! {"The opposite planes must not be superimposed."};
+        }
+
+        let mut res = Matrix4::identity();
+
+        res.data[0][0] = 2.0 / (right - left);
+        res.data[1][1] = 2.0 / (top - bottom);
+        res.data[2][2] = -2.0 / (zfar - znear);
+
+        res.data[0][3] = -(right + left) / (right - left);
+        res.data[1][3] = -(top + bottom) / (top - bottom);
+        res.data[2][3] = -(zfar + znear) / (zfar - znear);
+
+        res
+    }
+
     pub fn new_homogeneous(translation: &Vector3, euler_rotation: &Vector3, scale: f32) -> Matrix4 {
         let mut m = Matrix4::identity();
         m.apply_scale(&Vector3::new(scale, scale, scale));
@@ -342,6 +372,552 @@ impl Matrix4 {
 
         m
     }
+
+    /// Creates a new rotation from a quaternion, which is expected to be normalized.
+    pub fn new_rotation_quaternion(q: &Quaternion) -> Matrix4 {
+        let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+
+        let data: [[f32; 4]; 4] = [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        Matrix4::from_row_slice(data)
+    }
+
+    pub fn rotate_quaternion(&mut self, q: &Quaternion) {
+        let a = Matrix4::from_row_slice(self.data);
+        *self = a * Matrix4::new_rotation_quaternion(q);
+    }
+
+    pub fn new_homogeneous_quaternion(
+        translation: &Vector3,
+        rotation: &Quaternion,
+        scale: f32,
+    ) -> Matrix4 {
+        let mut m = Matrix4::identity();
+        m.apply_scale(&Vector3::new(scale, scale, scale));
+        m.rotate_quaternion(rotation);
+        m.apply_translation(translation);
+
+        m
+    }
+
+    /// Transforms the point with `w = 1.0`, and divides by the resulting `w` unless it is zero.
+    pub fn transform_point(&self, p: &Vector3) -> Vector3 {
+        let d = &self.data;
+        let mut c: [f32; 4] = [0.0; 4];
+        for (c, row) in c.iter_mut().zip(d.iter()) {
+            *c = row[0] * p.x + row[1] * p.y + row[2] * p.z + row[3];
+        }
+        let v = Vector3::new(c[0], c[1], c[2]);
+        if c[3] == 0.0 {
+            v
+        } else {
+            v / c[3]
+        }
+    }
+}
+
+// === Quaternion ==============================================================
+
+/// A rotation as the quaternion `w + xi + yj + zk`. Rotations are unit quaternions.
+pub struct Quaternion {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Quaternion {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quaternion {
+        Quaternion {
+            x: x,
+            y: y,
+            z: z,
+            w: w,
+        }
+    }
+
+    pub fn clone(&self) -> Quaternion {
+        Quaternion::new(self.x, self.y, self.z, self.w)
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    /// Creates a rotation by `angle` radians around `axis`.
+    pub fn from_axis_angle(axis: &Vector3, angle: f32) -> Quaternion {
+        let a = axis.normalize();
+        let (s, c) = unsafe { (sinf32(angle * 0.5), cosf32(angle * 0.5)) };
+        Quaternion::new(a.x * s, a.y * s, a.z * s, c)
+    }
+
+    /// Creates a rotation from Euler angles, the same as `Matrix4::new_rotation_euler()`.
+    ///
+    /// The primitive rotations are applied in order: 1 roll − 2 pitch − 3 yaw.
+    pub fn from_euler(roll: f32, pitch: f32, yaw: f32) -> Quaternion {
+        let (sr, cr) = unsafe { (sinf32(roll * 0.5), cosf32(roll * 0.5)) };
+        let (sp, cp) = unsafe { (sinf32(pitch * 0.5), cosf32(pitch * 0.5)) };
+        let (sy, cy) = unsafe { (sinf32(yaw * 0.5), cosf32(yaw * 0.5)) };
+
+        Quaternion::new(
+            sr * cp * cy - cr * sp * sy,
+            cr * sp * cy + sr * cp * sy,
+            cr * cp * sy - sr * sp * cy,
+            cr * cp * cy + sr * sp * sy,
+        )
+    }
+
+    /// Returns the axis and the angle in radians of the rotation. The axis is X when there is
+    /// no rotation.
+    pub fn to_axis_angle(&self) -> (Vector3, f32) {
+        let q = self.normalize();
+        let angle = 2.0 * acosf32(q.w.clamp(-1.0, 1.0));
+        let s = unsafe { sqrtf32(1.0 - q.w * q.w) };
+        if s < 1e-6 {
+            (Vector3::new(1.0, 0.0, 0.0), angle)
+        } else {
+            (Vector3::new(q.x / s, q.y / s, q.z / s), angle)
+        }
+    }
+
+    /// Returns the Euler angles of the rotation as (roll, pitch, yaw), see `from_euler()`.
+    pub fn to_euler(&self) -> Vector3 {
+        let q = self.normalize();
+
+        let roll = atan2f32(
+            2.0 * (q.w * q.x + q.y * q.z),
+            1.0 - 2.0 * (q.x * q.x + q.y * q.y),
+        );
+        let pitch = asinf32((2.0 * (q.w * q.y - q.z * q.x)).clamp(-1.0, 1.0));
+        let yaw = atan2f32(
+            2.0 * (q.w * q.z + q.x * q.y),
+            1.0 - 2.0 * (q.y * q.y + q.z * q.z),
+        );
+
+        Vector3::new(roll, pitch, yaw)
+    }
+
+    pub fn as_slice(&self) -> [f32; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+
+    pub fn dot(&self, b: &Quaternion) -> f32 {
+        self.x * b.x + self.y * b.y + self.z * b.z + self.w * b.w
+    }
+
+    pub fn norm(&self) -> f32 {
+        unsafe { sqrtf32(self.dot(self)) }
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let n = self.norm();
+        Quaternion::new(self.x / n, self.y / n, self.z / n, self.w / n)
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn inverse(&self) -> Quaternion {
+        let n = self.dot(self);
+        let c = self.conjugate();
+        Quaternion::new(c.x / n, c.y / n, c.z / n, c.w / n)
+    }
+
+    /// Rotates the vector, expecting a unit quaternion.
+    pub fn rotate_vector(&self, v: &Vector3) -> Vector3 {
+        // v + 2w(u × v) + 2u × (u × v), where u is the vector part.
+        let u = Vector3::new(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+        let a = &t * self.w;
+        let b = u.cross(&t);
+        Vector3::new(v.x + a.x + b.x, v.y + a.y + b.y, v.z + a.z + b.z)
+    }
+
+    pub fn to_matrix4(&self) -> Matrix4 {
+        Matrix4::new_rotation_quaternion(self)
+    }
+
+    /// Interpolates linearly and normalizes the result. Faster than `slerp()`, but the speed of
+    /// the rotation is not constant. Takes the shorter way around.
+    pub fn nlerp(&self, b: &Quaternion, t: f32) -> Quaternion {
+        let b = if self.dot(b) < 0.0 { -b } else { b.clone() };
+        Quaternion::new(
+            self.x + (b.x - self.x) * t,
+            self.y + (b.y - self.y) * t,
+            self.z + (b.z - self.z) * t,
+            self.w + (b.w - self.w) * t,
+        )
+        .normalize()
+    }
+
+    /// Spherical linear interpolation, rotating at a constant speed from `self` at 0.0 to `b` at
+    /// 1.0. Takes the shorter way around.
+    pub fn slerp(&self, b: &Quaternion, t: f32) -> Quaternion {
+        let mut d = self.dot(b);
+        let b = if d < 0.0 {
+            d = -d;
+            -b
+        } else {
+            b.clone()
+        };
+
+        // Close rotations would divide by almost zero.
+        if d > 0.9995 {
+            return self.nlerp(&b, t);
+        }
+
+        let theta = acosf32(d);
+        let (sin_theta, sin_a, sin_b) =
+            unsafe { (sinf32(theta), sinf32((1.0 - t) * theta), sinf32(t * theta)) };
+        let wa = sin_a / sin_theta;
+        let wb = sin_b / sin_theta;
+
+        Quaternion::new(
+            self.x * wa + b.x * wb,
+            self.y * wa + b.y * wb,
+            self.z * wa + b.z * wb,
+            self.w * wa + b.w * wb,
+        )
+    }
+}
+
+// === Bounding volumes ========================================================
+
+/// An axis-aligned bounding box.
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3, max: Vector3) -> Aabb {
+        Aabb { min: min, max: max }
+    }
+
+    /// The box around the points, or `None` when there are no points.
+    pub fn from_points(points: &[[f32; 3]]) -> Option<Aabb> {
+        let (first, rest) = points.split_first()?;
+        let mut a = Aabb::new(Vector3::from_slice(first), Vector3::from_slice(first));
+        for p in rest.iter() {
+            a.extend(&Vector3::from_slice(p));
+        }
+        Some(a)
+    }
+
+    pub fn clone(&self) -> Aabb {
+        Aabb::new(self.min.clone(), self.max.clone())
+    }
+
+    /// Grows the box to contain the point.
+    pub fn extend(&mut self, p: &Vector3) {
+        self.min = Vector3::new(
+            self.min.x.min(p.x),
+            self.min.y.min(p.y),
+            self.min.z.min(p.z),
+        );
+        self.max = Vector3::new(
+            self.max.x.max(p.x),
+            self.max.y.max(p.y),
+            self.max.z.max(p.z),
+        );
+    }
+
+    /// The box around both boxes.
+    pub fn union(&self, b: &Aabb) -> Aabb {
+        let mut a = self.clone();
+        a.extend(&b.min);
+        a.extend(&b.max);
+        a
+    }
+
+    pub fn center(&self) -> Vector3 {
+        (&self.min + &self.max) * 0.5
+    }
+
+    /// Half of the size along each axis.
+    pub fn half_extents(&self) -> Vector3 {
+        (&self.max - &self.min) * 0.5
+    }
+
+    pub fn contains_point(&self, p: &Vector3) -> bool {
+        (self.min.x..=self.max.x).contains(&p.x)
+            && (self.min.y..=self.max.y).contains(&p.y)
+            && (self.min.z..=self.max.z).contains(&p.z)
+    }
+
+    /// The box around this box after the affine transformation `m`. It is larger than the
+    /// transformed box when `m` rotates it.
+    #[allow(clippy::needless_range_loop)]
+    pub fn transform(&self, m: &Matrix4) -> Aabb {
+        // Each term of the matrix row is smallest at either the minimum or the maximum corner,
+        // so the sums of the smaller and the larger terms are the new extents.
+        let d = m.as_row_slice();
+        let min = self.min.as_slice();
+        let max = self.max.as_slice();
+        let mut new_min = [d[0][3], d[1][3], d[2][3]];
+        let mut new_max = new_min;
+        for i in 0..3 {
+            for j in 0..3 {
+                let a = d[i][j] * min[j];
+                let b = d[i][j] * max[j];
+                new_min[i] += a.min(b);
+                new_max[i] += a.max(b);
+            }
+        }
+        Aabb::new(Vector3::from_slice(&new_min), Vector3::from_slice(&new_max))
+    }
+}
+
+/// A sphere around a set of points. Quicker to test than a box, but usually larger.
+pub struct BoundingSphere {
+    pub center: Vector3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    pub fn new(center: Vector3, radius: f32) -> BoundingSphere {
+        BoundingSphere {
+            center: center,
+            radius: radius,
+        }
+    }
+
+    /// The sphere at the center of the bounding box of the points, reaching to the furthest
+    /// point. `None` when there are no points.
+    pub fn from_points(points: &[[f32; 3]]) -> Option<BoundingSphere> {
+        let center = Aabb::from_points(points)?.center();
+        let mut r2: f32 = 0.0;
+        for p in points.iter() {
+            let d = &Vector3::from_slice(p) - &center;
+            r2 = r2.max(d.dot(&d));
+        }
+        Some(BoundingSphere::new(center, unsafe { sqrtf32(r2) }))
+    }
+
+    /// The sphere through the corners of the box.
+    pub fn from_aabb(a: &Aabb) -> BoundingSphere {
+        BoundingSphere::new(a.center(), a.half_extents().norm())
+    }
+
+    pub fn clone(&self) -> BoundingSphere {
+        BoundingSphere::new(self.center.clone(), self.radius)
+    }
+
+    /// The sphere around this sphere after the affine transformation `m`. The radius is scaled
+    /// by the largest scale of the matrix axes.
+    pub fn transform(&self, m: &Matrix4) -> BoundingSphere {
+        let d = m.as_row_slice();
+        let scale = (0..3)
+            .map(|j| Vector3::new(d[0][j], d[1][j], d[2][j]).norm())
+            .fold(0.0, f32::max);
+        BoundingSphere::new(m.transform_point(&self.center), self.radius * scale)
+    }
+
+    pub fn contains_point(&self, p: &Vector3) -> bool {
+        let d = p - &self.center;
+        d.dot(&d) <= self.radius * self.radius
+    }
+}
+
+// === Frustum =================================================================
+
+/// The points `p` where `normal · p + d = 0`.
+pub struct Plane {
+    pub normal: Vector3,
+    pub d: f32,
+}
+
+impl Plane {
+    pub fn new(normal: Vector3, d: f32) -> Plane {
+        Plane {
+            normal: normal,
+            d: d,
+        }
+    }
+
+    /// Scales the plane to a unit normal, so that the distances are in world units.
+    pub fn normalize(&self) -> Plane {
+        let n = self.normal.norm();
+        Plane::new(self.normal.clone() / n, self.d / n)
+    }
+
+    /// Signed distance of the point, positive on the side which the normal points to.
+    pub fn distance(&self, p: &Vector3) -> f32 {
+        self.normal.dot(p) + self.d
+    }
+}
+
+/// The view volume as six planes with the normals pointing inside, in the order left, right,
+/// bottom, top, near and far.
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes of a `projection * view` matrix, which are then in world space. With
+    /// `projection * view * model` they are in the object space of the model.
+    ///
+    /// A point is inside when its clip coordinates are within `-w..w`, and each of these
+    /// conditions is a plane from the sum or the difference of two matrix rows (Gribb and
+    /// Hartmann).
+    pub fn from_matrix(m: &Matrix4) -> Frustum {
+        let d = m.as_row_slice();
+        let plane = |row: usize, sign: f32| -> Plane {
+            Plane::new(
+                Vector3::new(
+                    d[3][0] + sign * d[row][0],
+                    d[3][1] + sign * d[row][1],
+                    d[3][2] + sign * d[row][2],
+                ),
+                d[3][3] + sign * d[row][3],
+            )
+            .normalize()
+        };
+        Frustum {
+            planes: [
+                plane(0, 1.0),
+                plane(0, -1.0),
+                plane(1, 1.0),
+                plane(1, -1.0),
+                plane(2, 1.0),
+                plane(2, -1.0),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, p: &Vector3) -> bool {
+        self.planes.iter().all(|x| x.distance(p) >= 0.0)
+    }
+
+    /// False when the sphere is entirely outside.
+    pub fn intersects_sphere(&self, s: &BoundingSphere) -> bool {
+        self.planes
+            .iter()
+            .all(|x| x.distance(&s.center) >= -s.radius)
+    }
+
+    /// False when the box is entirely outside one of the planes. A box near the edges of the
+    /// frustum can be outside of it and still pass, which is enough for culling.
+    pub fn intersects_aabb(&self, a: &Aabb) -> bool {
+        self.planes.iter().all(|x| {
+            // The corner which is the furthest along the normal.
+            let n = &x.normal;
+            let p = Vector3::new(
+                if n.x >= 0.0 { a.max.x } else { a.min.x },
+                if n.y >= 0.0 { a.max.y } else { a.min.y },
+                if n.z >= 0.0 { a.max.z } else { a.min.z },
+            );
+            x.distance(&p) >= 0.0
+        })
+    }
+}
+
+// === Ray =====================================================================
+
+/// Below this the ray is taken to be parallel with a plane.
+const RAY_EPSILON: f32 = 1e-7;
+
+/// A half-line from the origin, such as from the camera through a pixel.
+pub struct Ray {
+    pub origin: Vector3,
+    pub direction: Vector3,
+}
+
+impl Ray {
+    /// The direction is normalized, so that the distances along the ray are in world units.
+    pub fn new(origin: Vector3, direction: Vector3) -> Ray {
+        Ray {
+            origin: origin,
+            direction: direction.normalize(),
+        }
+    }
+
+    pub fn point_at(&self, t: f32) -> Vector3 {
+        &self.origin + &(&self.direction * t)
+    }
+
+    /// The distance to where the ray enters the box, 0.0 when it starts inside, or `None` when
+    /// it misses.
+    #[allow(clippy::needless_range_loop)]
+    pub fn intersect_aabb(&self, a: &Aabb) -> Option<f32> {
+        // The ray is in the box where it is between all three pairs of planes.
+        let o = self.origin.as_slice();
+        let d = self.direction.as_slice();
+        let min = a.min.as_slice();
+        let max = a.max.as_slice();
+
+        let mut t_near: f32 = 0.0;
+        let mut t_far = f32::INFINITY;
+        for i in 0..3 {
+            if unsafe { fabsf32(d[i]) } < RAY_EPSILON {
+                if !(min[i]..=max[i]).contains(&o[i]) {
+                    return None;
+                }
+            } else {
+                let t1 = (min[i] - o[i]) / d[i];
+                let t2 = (max[i] - o[i]) / d[i];
+                t_near = t_near.max(t1.min(t2));
+                t_far = t_far.min(t1.max(t2));
+                if t_near > t_far {
+                    return None;
+                }
+            }
+        }
+        Some(t_near)
+    }
+
+    /// The distance to where the ray hits the triangle from either side, or `None` when it
+    /// misses. Uses the Möller–Trumbore algorithm.
+    pub fn intersect_triangle(&self, a: &Vector3, b: &Vector3, c: &Vector3) -> Option<f32> {
+        let e1 = b - a;
+        let e2 = c - a;
+        let p = self.direction.cross(&e2);
+        let det = e1.dot(&p);
+        if unsafe { fabsf32(det) } < RAY_EPSILON {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        // Barycentric coordinates of the hit.
+        let s = &self.origin - a;
+        let u = s.dot(&p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(&e1);
+        let v = self.direction.dot(&q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = e2.dot(&q) * inv_det;
+        if t > RAY_EPSILON {
+            Some(t)
+        } else {
+            None
+        }
+    }
 }
 
 // === Ops for Vector3 =========================================================
@@ -605,6 +1181,46 @@ impl Mul<f32> for Matrix4 {
     }
 }
 
+// === Ops for Quaternion ======================================================
+
+impl Mul<Quaternion> for Quaternion {
+    type Output = Quaternion;
+
+    /// The rotation of `rhs` followed by the rotation of `self`.
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        &self * &rhs
+    }
+}
+
+impl<'a> Mul<&'a Quaternion> for &'a Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: &Quaternion) -> Quaternion {
+        let (a, b) = (self, rhs);
+        Quaternion::new(
+            a.w * b.x + a.x * b.w + a.y * b.z - a.z * b.y,
+            a.w * b.y - a.x * b.z + a.y * b.w + a.z * b.x,
+            a.w * b.z + a.x * b.y - a.y * b.x + a.z * b.w,
+            a.w * b.w - a.x * b.x - a.y * b.y - a.z * b.z,
+        )
+    }
+}
+
+impl Neg for Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        Quaternion::new(-self.x, -self.y, -self.z, -self.w)
+    }
+}
+
+impl Neg for &Quaternion {
+    type Output = Quaternion;
+    fn neg(self) -> Quaternion {
+        let q = self;
+        Quaternion::new(-q.x, -q.y, -q.z, -q.w)
+    }
+}
+
 // === Helper functions ========================================================
 
 /// Calculates tangent from sin cos, no direct function in core::intrinsics.
@@ -615,6 +1231,43 @@ pub fn tanf32(a: f32) -> f32 {
     }
 }
 
+/// Approximates arctangent with a polynomial, no direct function in core::intrinsics. The error
+/// is about 1e-5 radians.
+pub fn atan2f32(y: f32, x: f32) -> f32 {
+    if x == 0.0 && y == 0.0 {
+        return 0.0;
+    }
+
+    let (ax, ay) = unsafe { (fabsf32(x), fabsf32(y)) };
+    // The polynomial is for the range -1.0..1.0, so it takes the smaller over the larger.
+    let a = if ax > ay { ay / ax } else { ax / ay };
+    let a2 = a * a;
+    let mut r = a
+        * (0.99997726
+            + a2 * (-0.33262347
+                + a2 * (0.19354346 + a2 * (-0.11643287 + a2 * (0.05265332 - a2 * 0.01172120)))));
+
+    let half_pi = (PI / 2.0) as f32;
+    if ay > ax {
+        r = half_pi - r;
+    }
+    if x < 0.0 {
+        r = (PI as f32) - r;
+    }
+    if y < 0.0 {
+        r = -r;
+    }
+    r
+}
+
+pub fn asinf32(a: f32) -> f32 {
+    unsafe { atan2f32(a, sqrtf32(1.0 - a * a)) }
+}
+
+pub fn acosf32(a: f32) -> f32 {
+    unsafe { atan2f32(sqrtf32(1.0 - a * a), a) }
+}
+
 pub fn to_radians(degree: f32) -> f32 {
     ((degree as f64) * DEG_TO_RAD) as f32
 }
@@ -1,6 +1,8 @@
 extern crate intro_3d;
 
-use intro_3d::lib::{to_radians, Matrix4, Vector3, Vector4};
+use intro_3d::lib::{
+    to_radians, Aabb, BoundingSphere, Frustum, Matrix4, Quaternion, Ray, Vector3, Vector4,
+};
 
 #[test]
 fn add_vector3_and_vector3() {
@@ -138,6 +140,20 @@ fn perspective_matrix() {
     assert_eq! {format!{"{:?}", res.as_column_slice()}, format!{"{:?}", expect}};
 }
 
+#[test]
+fn orthographic_matrix() {
+    let res = Matrix4::new_orthographic(-10.0, 10.0, -5.0, 5.0, 0.5, 50.0);
+    // column major
+    let expect = [
+        [0.1, 0.0, 0.0, 0.0],
+        [0.0, 0.2, 0.0, 0.0],
+        [0.0, 0.0, -0.04040404, 0.0],
+        [-0.0, -0.0, -1.020202, 1.0],
+    ];
+
+    assert_eq! {format!{"{:?}", res.as_column_slice()}, format!{"{:?}", expect}};
+}
+
 #[test]
 fn convert_f32_to_radians() {
     let mut deg: f32 = 13.7;
@@ -190,3 +206,181 @@ fn add_assign_vec3() {
 
     assert_eq! {format!{"{:?}", res.as_slice()}, format!{"{:?}", expect}};
 }
+
+fn assert_approx_eq(res: &[f32], expect: &[f32]) {
+    assert_eq! {res.len(), expect.len()};
+    for (a, b) in res.iter().zip(expect.iter()) {
+        assert! {(a - b).abs() < 1e-5, "{:?} != {:?}", res, expect};
+    }
+}
+
+#[test]
+fn quaternion_from_euler() {
+    let (roll, pitch, yaw) = (to_radians(12.0), to_radians(-25.2), to_radians(55.9));
+    let res = Matrix4::new_rotation_quaternion(&Quaternion::from_euler(roll, pitch, yaw));
+    let expect = Matrix4::new_rotation_euler(roll, pitch, yaw);
+
+    let res: Vec<f32> = res.as_row_slice().iter().flatten().cloned().collect();
+    let expect: Vec<f32> = expect.as_row_slice().iter().flatten().cloned().collect();
+    assert_approx_eq(&res, &expect);
+}
+
+#[test]
+fn quaternion_to_euler() {
+    let q = Quaternion::from_euler(to_radians(12.0), to_radians(-25.2), to_radians(55.9));
+    let res = q.to_euler();
+    let expect = [to_radians(12.0), to_radians(-25.2), to_radians(55.9)];
+
+    assert_approx_eq(&res.as_slice(), &expect);
+}
+
+#[test]
+fn quaternion_axis_angle() {
+    let q = Quaternion::from_axis_angle(&Vector3::new(0.0, 0.0, 2.0), to_radians(90.0));
+
+    let res = q.rotate_vector(&Vector3::new(1.0, 0.0, 0.0));
+    assert_approx_eq(&res.as_slice(), &[0.0, 1.0, 0.0]);
+
+    let (axis, angle) = q.to_axis_angle();
+    assert_approx_eq(&axis.as_slice(), &[0.0, 0.0, 1.0]);
+    assert_approx_eq(&[angle], &[to_radians(90.0)]);
+}
+
+#[test]
+fn mul_quaternion_by_quaternion() {
+    let a = Quaternion::from_axis_angle(&Vector3::new(0.0, 1.0, 0.0), to_radians(90.0));
+    let b = Quaternion::from_axis_angle(&Vector3::new(1.0, 0.0, 0.0), to_radians(90.0));
+    let v = Vector3::new(0.3, -1.2, 2.5);
+
+    // b first, then a
+    let res = (&a * &b).rotate_vector(&v);
+    let expect = a.rotate_vector(&b.rotate_vector(&v));
+    assert_approx_eq(&res.as_slice(), &expect.as_slice());
+
+    let res = (a * b).to_matrix4() * Vector4::new(v.x, v.y, v.z, 1.0);
+    assert_approx_eq(&res.as_slice(), &[expect.x, expect.y, expect.z, 1.0]);
+}
+
+#[test]
+fn quaternion_slerp() {
+    let axis = Vector3::new(0.0, 1.0, 0.0);
+    let a = Quaternion::identity();
+    let b = Quaternion::from_axis_angle(&axis, to_radians(90.0));
+
+    let res = a.slerp(&b, 0.25);
+    let expect = Quaternion::from_axis_angle(&axis, to_radians(22.5));
+    assert_approx_eq(&res.as_slice(), &expect.as_slice());
+
+    let res = a.nlerp(&b, 0.5);
+    let expect = Quaternion::from_axis_angle(&axis, to_radians(45.0));
+    assert_approx_eq(&res.as_slice(), &expect.as_slice());
+
+    // The shorter way around, from the negated quaternion of the same rotation.
+    let res = a.slerp(&-b, 0.5);
+    assert_approx_eq(&res.as_slice(), &expect.as_slice());
+}
+
+#[test]
+fn aabb_from_points_and_transform() {
+    let a = Aabb::from_points(&[[1.0, -2.0, 0.5], [-1.0, 3.0, 0.0], [0.0, 0.0, 2.0]]).unwrap();
+    assert_approx_eq(&a.min.as_slice(), &[-1.0, -2.0, 0.0]);
+    assert_approx_eq(&a.max.as_slice(), &[1.0, 3.0, 2.0]);
+    assert!(Aabb::from_points(&[]).is_none());
+
+    // Rotated 90 degrees around Y, then translated.
+    let mut m = Matrix4::new_translation(&Vector3::new(10.0, 0.0, 0.0));
+    m.rotate_euler(0.0, to_radians(90.0), 0.0);
+    let res = a.transform(&m);
+    let corners = [
+        m.transform_point(&a.min),
+        m.transform_point(&a.max),
+        m.transform_point(&Vector3::new(a.min.x, a.max.y, a.max.z)),
+    ];
+    for p in corners.iter() {
+        assert!(res.contains_point(p));
+    }
+    assert_approx_eq(&res.min.as_slice(), &[10.0, -2.0, -1.0]);
+    assert_approx_eq(&res.max.as_slice(), &[12.0, 3.0, 1.0]);
+}
+
+#[test]
+fn bounding_sphere_from_points() {
+    let s =
+        BoundingSphere::from_points(&[[-1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.5, 0.0]]).unwrap();
+    assert_approx_eq(&s.center.as_slice(), &[0.0, 0.25, 0.0]);
+    assert_approx_eq(&[s.radius], &[f32::sqrt(1.0 + 0.25 * 0.25)]);
+
+    let mut m = Matrix4::new_translation(&Vector3::new(0.0, 0.0, 5.0));
+    m.apply_scale(&Vector3::new(2.0, 2.0, 2.0));
+    let res = s.transform(&m);
+    assert_approx_eq(&res.center.as_slice(), &[0.0, 0.5, 5.0]);
+    assert_approx_eq(&[res.radius], &[2.0 * s.radius]);
+}
+
+#[test]
+fn frustum_from_perspective() {
+    let projection = Matrix4::new_perspective(1.0, to_radians(45.0), 1.0, 100.0);
+    let view = Matrix4::look_at_rh(
+        &Vector3::new(0.0, 0.0, 10.0),
+        &Vector3::new(0.0, 0.0, 0.0),
+        &Vector3::new(0.0, 1.0, 0.0),
+    );
+    let f = Frustum::from_matrix(&(projection * view));
+
+    assert!(f.contains_point(&Vector3::new(0.0, 0.0, 0.0)));
+    // Behind the camera and beyond the far plane.
+    assert!(!f.contains_point(&Vector3::new(0.0, 0.0, 20.0)));
+    assert!(!f.contains_point(&Vector3::new(0.0, 0.0, -100.0)));
+    // At the distance of 10.0 the view reaches 10.0 to the sides.
+    assert!(f.contains_point(&Vector3::new(3.0, 0.0, 0.0)));
+    assert!(!f.contains_point(&Vector3::new(11.0, 0.0, 0.0)));
+
+    let a = Aabb::new(Vector3::new(3.5, -1.0, -1.0), Vector3::new(12.0, 1.0, 1.0));
+    assert!(f.intersects_aabb(&a));
+    let a = Aabb::new(Vector3::new(12.0, -1.0, -1.0), Vector3::new(13.0, 1.0, 1.0));
+    assert!(!f.intersects_aabb(&a));
+
+    assert!(f.intersects_sphere(&BoundingSphere::new(Vector3::new(0.0, -5.0, 0.0), 1.5)));
+    assert!(!f.intersects_sphere(&BoundingSphere::new(Vector3::new(0.0, -13.0, 0.0), 1.5)));
+}
+
+#[test]
+fn ray_intersect_aabb() {
+    let a = Aabb::new(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0));
+
+    let r = Ray::new(Vector3::new(0.0, 0.5, 5.0), Vector3::new(0.0, 0.0, -2.0));
+    assert_approx_eq(&[r.intersect_aabb(&a).unwrap()], &[4.0]);
+    assert_approx_eq(&r.point_at(4.0).as_slice(), &[0.0, 0.5, 1.0]);
+
+    // Starting inside.
+    let r = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 0.0));
+    assert_approx_eq(&[r.intersect_aabb(&a).unwrap()], &[0.0]);
+
+    // Pointing away, and passing by.
+    let r = Ray::new(Vector3::new(0.0, 0.0, 5.0), Vector3::new(0.0, 0.0, 1.0));
+    assert!(r.intersect_aabb(&a).is_none());
+    let r = Ray::new(Vector3::new(0.0, 2.0, 5.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(r.intersect_aabb(&a).is_none());
+}
+
+#[test]
+fn ray_intersect_triangle() {
+    let a = Vector3::new(-1.0, -1.0, 0.0);
+    let b = Vector3::new(1.0, -1.0, 0.0);
+    let c = Vector3::new(0.0, 1.0, 0.0);
+
+    let r = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, -1.0));
+    assert_approx_eq(&[r.intersect_triangle(&a, &b, &c).unwrap()], &[3.0]);
+
+    // From the back side.
+    let r = Ray::new(Vector3::new(0.0, 0.0, -2.0), Vector3::new(0.0, 0.0, 1.0));
+    assert_approx_eq(&[r.intersect_triangle(&a, &b, &c).unwrap()], &[2.0]);
+
+    // Outside the edges, parallel, and behind the origin.
+    let r = Ray::new(Vector3::new(0.9, 0.9, 3.0), Vector3::new(0.0, 0.0, -1.0));
+    assert!(r.intersect_triangle(&a, &b, &c).is_none());
+    let r = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(1.0, 0.0, 0.0));
+    assert!(r.intersect_triangle(&a, &b, &c).is_none());
+    let r = Ray::new(Vector3::new(0.0, 0.0, 3.0), Vector3::new(0.0, 0.0, 1.0));
+    assert!(r.intersect_triangle(&a, &b, &c).is_none());
+}
@@ -1,5 +1,14 @@
 use intro_3d::lib::{Matrix4, Vector3};
 
+/// How the mouse and the keys move the explore mode camera.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CameraControl {
+    /// Drag to look around, keys to move.
+    FreeFly,
+    /// Drag to rotate around the target, wheel to zoom, middle-drag to pan.
+    Orbit,
+}
+
 pub struct Camera {
     pub fovy_angle: f32,
     pub aspect: f32,
@@ -15,6 +24,12 @@ pub struct Camera {
     pub world_up: Vector3,
     pub pitch: f32,
     pub yaw: f32,
+
+    pub control: CameraControl,
+    /// The point which the orbit camera rotates around and looks at.
+    pub target: Vector3,
+    /// Distance of the orbit camera from the target.
+    pub distance: f32,
 }
 
 impl Camera {
@@ -48,6 +63,10 @@ impl Camera {
             right: Vector3::new(0.0, 0.0, 0.0),
             pitch,
             yaw,
+
+            control: CameraControl::FreeFly,
+            target: Vector3::new(0.0, 0.0, 0.0),
+            distance: 10.0,
         };
 
         if front.is_some() {
@@ -175,4 +194,53 @@ impl Camera {
         self.front = front;
         self.update_view();
     }
+
+    /// Switches between free-fly and orbit control. The orbit target is set in front of the
+    /// camera at the orbit distance, so that the view doesn't jump.
+    pub fn set_control(&mut self, control: CameraControl) {
+        if control == CameraControl::Orbit && self.control != CameraControl::Orbit {
+            self.target = &self.position + &(&self.front * self.distance);
+        }
+        self.control = control;
+    }
+
+    /// Rotates the orbit camera around the target.
+    pub fn orbit_from_mouse_delta(&mut self, dx: f32, dy: f32) {
+        self.do_pitch_and_yaw_from_mouse_delta(dx, dy);
+        self.update_orbit_position();
+    }
+
+    /// Moves the orbit camera towards the target with a positive `dy`, in steps of 10% of the
+    /// distance.
+    pub fn orbit_zoom(&mut self, dy: f32) {
+        self.distance = (self.distance * 0.9_f32.powf(dy)).max(0.01);
+        self.update_orbit_position();
+    }
+
+    /// Moves the orbit camera and its target sideways, faster when further from the target.
+    pub fn orbit_pan(&mut self, dx: f32, dy: f32) {
+        let speed = self.distance * 0.002;
+        let offset = &(&self.right * (-dx * speed)) + &(&self.up * (-dy * speed));
+        self.target += offset;
+        self.update_orbit_position();
+    }
+
+    /// Sets the orbit target to the center of the box and the distance to see all of it.
+    pub fn frame_bounds(&mut self, min: &Vector3, max: &Vector3) {
+        self.target = (min + max) * 0.5;
+        let radius = ((max - min).norm() * 0.5).max(0.01);
+
+        // The smaller of the vertical and horizontal field of view.
+        let half_fovy = (self.fovy_angle * 0.5).to_radians();
+        let half_fovx = (half_fovy.tan() * self.aspect).atan();
+        let half_fov = half_fovy.min(half_fovx);
+
+        self.distance = radius / half_fov.sin();
+        self.update_orbit_position();
+    }
+
+    pub fn update_orbit_position(&mut self) {
+        self.position = &self.target - &(&self.front * self.distance);
+        self.update_view();
+    }
 }
@@ -0,0 +1,117 @@
+use crate::error::RuntimeError;
+use crate::sync_vars::BuiltIn::*;
+use crate::sync_vars::SyncVars;
+use crate::types::ValueFloat;
+
+/// Where the polygon scene camera takes its view from.
+pub enum CameraMode {
+    /// `Camera_Pos_*` and `Camera_Front_*`, the default.
+    Sync_Front,
+    /// `Camera_Pos_*`, `Camera_LookAt_*` and `Camera_Up_*`.
+    Sync_LookAt,
+    /// Position and look-at along a spline, which then sets the camera sync vars.
+    Path(CameraPath),
+}
+
+/// A Catmull-Rom spline through control points of the camera position and look-at target,
+/// followed by its progress.
+pub struct CameraPath {
+    pub positions: Vec<[f32; 3]>,
+    /// The look-at targets, one for each position.
+    pub look_ats: Vec<[f32; 3]>,
+    /// Continue from the last point to the first.
+    pub closed: bool,
+
+    /// From 0.0 at the first point to 1.0 at the last, or back at the first when closed.
+    pub progress: f32,
+    pub progress_var: ValueFloat,
+}
+
+impl CameraPath {
+    pub fn new(positions: Vec<[f32; 3]>, look_ats: Vec<[f32; 3]>, closed: bool) -> CameraPath {
+        CameraPath {
+            positions,
+            look_ats,
+            closed,
+            progress: 0.0,
+            progress_var: ValueFloat::NOOP,
+        }
+    }
+
+    /// Updates the progress and sets the camera position, look-at and up sync vars from the
+    /// path. The up vector is the world up.
+    pub fn update(&mut self, sync_vars: &mut SyncVars) -> Result<(), RuntimeError> {
+        match self.progress_var {
+            ValueFloat::NOOP => {}
+            ValueFloat::Sync(x) => self.progress = sync_vars.get_index(x as usize)? as f32,
+            ValueFloat::Fixed(x) => self.progress = x,
+        }
+
+        let p = self.position_at(self.progress);
+        let t = self.look_at_at(self.progress);
+
+        sync_vars.set_builtin(Camera_Pos_X, f64::from(p[0]));
+        sync_vars.set_builtin(Camera_Pos_Y, f64::from(p[1]));
+        sync_vars.set_builtin(Camera_Pos_Z, f64::from(p[2]));
+
+        sync_vars.set_builtin(Camera_LookAt_X, f64::from(t[0]));
+        sync_vars.set_builtin(Camera_LookAt_Y, f64::from(t[1]));
+        sync_vars.set_builtin(Camera_LookAt_Z, f64::from(t[2]));
+
+        sync_vars.set_builtin(Camera_Up_X, 0.0);
+        sync_vars.set_builtin(Camera_Up_Y, 1.0);
+        sync_vars.set_builtin(Camera_Up_Z, 0.0);
+
+        Ok(())
+    }
+
+    pub fn position_at(&self, progress: f32) -> [f32; 3] {
+        spline_at(&self.positions, self.closed, progress)
+    }
+
+    pub fn look_at_at(&self, progress: f32) -> [f32; 3] {
+        spline_at(&self.look_ats, self.closed, progress)
+    }
+}
+
+/// A point on the Catmull-Rom spline which passes through all the points. The ends of an open
+/// spline are extended by repeating the first and last points.
+pub fn spline_at(points: &[[f32; 3]], closed: bool, progress: f32) -> [f32; 3] {
+    let n = points.len();
+    match n {
+        0 => return [0.0; 3],
+        1 => return points[0],
+        _ => {}
+    }
+
+    let n_segments = if closed { n } else { n - 1 };
+    let s = if closed {
+        progress.rem_euclid(1.0) * n_segments as f32
+    } else {
+        progress.clamp(0.0, 1.0) * n_segments as f32
+    };
+    let segment = (s.floor() as usize).min(n_segments - 1);
+    let u = s - segment as f32;
+
+    let point = |i: isize| -> [f32; 3] {
+        if closed {
+            points[i.rem_euclid(n as isize) as usize]
+        } else {
+            points[i.max(0).min(n as isize - 1) as usize]
+        }
+    };
+
+    let i = segment as isize;
+    let (p0, p1, p2, p3) = (point(i - 1), point(i), point(i + 1), point(i + 2));
+
+    let (u2, u3) = (u * u, u * u * u);
+    let mut res = [0.0; 3];
+    for (k, r) in res.iter_mut().enumerate() {
+        *r = 0.5
+            * (2.0 * p1[k]
+                + (-p0[k] + p2[k]) * u
+                + (2.0 * p0[k] - 5.0 * p1[k] + 4.0 * p2[k] - p3[k]) * u2
+                + (-p0[k] + 3.0 * p1[k] - 3.0 * p2[k] + p3[k]) * u3);
+    }
+    res
+}
@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
 use gl;
 
@@ -7,14 +7,14 @@ use crate::frame_buffer::FrameBuffer;
 use crate::mouse::Mouse;
 use crate::polygon_context::PolygonContext;
 use crate::polygon_scene::PolygonScene;
+use crate::profiler::GpuProfiler;
 use crate::quad_scene_gfx::QuadSceneGfx;
 use crate::sync_vars::BuiltIn::*;
 use crate::sync_vars::SyncVars;
+use crate::timeline::{Transition, VarCondition};
+use crate::transition::TransitionGfx;
 use crate::types::{BufferMapping, Image, UniformMapping};
 
-pub const PROFILE_FRAMES: usize = 60;
-pub const PROFILE_EVENTS: usize = 10;
-
 pub struct ContextGfx {
     /// Variables such as "time".
     pub sync_vars: SyncVars,
@@ -32,14 +32,11 @@ pub struct ContextGfx {
     pub camera: Camera,
     pub mouse: Mouse,
 
-    /// Profile events for 60 frames, max 100 events per frame.
-    pub profile_times: [[f32; PROFILE_EVENTS]; PROFILE_FRAMES],
-    /// Current frame counter for profiling.
-    pub profile_frame_idx: usize,
-    /// Current profile event counter.
-    pub profile_event_idx: usize,
-    /// Count of actually profiled number of events.
-    pub max_profile_event_idx: usize,
+    /// GPU timer queries for the `Profile` draw ops.
+    pub profiler: GpuProfiler,
+
+    /// Blends fading scene blocks over the result image.
+    pub transition: TransitionGfx,
 
     pub t_frame_start: Instant,
     pub t_frame_end: Instant,
@@ -107,9 +104,6 @@ impl ContextGfx {
         sync_vars.set_builtin(Screen_Width, screen_width);
         sync_vars.set_builtin(Screen_Height, screen_height);
 
-        let empty_profile: [[f32; PROFILE_EVENTS]; PROFILE_FRAMES] =
-            [[0.0; PROFILE_EVENTS]; PROFILE_FRAMES];
-
         ContextGfx {
             sync_vars,
 
@@ -125,10 +119,9 @@ impl ContextGfx {
             camera,
             mouse,
 
-            profile_times: empty_profile,
-            profile_frame_idx: 0,
-            profile_event_idx: 0,
-            max_profile_event_idx: 0,
+            profiler: GpuProfiler::new(),
+
+            transition: TransitionGfx::new(),
 
             t_frame_start: Instant::now(),
             t_frame_end: Instant::now(),
@@ -144,6 +137,8 @@ impl ContextGfx {
         for buffer in self.frame_buffers.iter_mut() {
             buffer.gl_cleanup();
         }
+        self.profiler.gl_cleanup();
+        self.transition.gl_cleanup();
     }
 
     pub fn set_time(&mut self, time: f64) {
@@ -196,6 +191,21 @@ impl ContextGfx {
             .set_builtin(Camera_Front_Y, f64::from(self.camera.front.y));
         self.sync_vars
             .set_builtin(Camera_Front_Z, f64::from(self.camera.front.z));
+
+        // The look-at and up for the Sync_LookAt and Path camera modes.
+        let look_at = &self.camera.position + &self.camera.front;
+        self.sync_vars
+            .set_builtin(Camera_LookAt_X, f64::from(look_at.x));
+        self.sync_vars
+            .set_builtin(Camera_LookAt_Y, f64::from(look_at.y));
+        self.sync_vars
+            .set_builtin(Camera_LookAt_Z, f64::from(look_at.z));
+        self.sync_vars
+            .set_builtin(Camera_Up_X, f64::from(self.camera.up.x));
+        self.sync_vars
+            .set_builtin(Camera_Up_Y, f64::from(self.camera.up.y));
+        self.sync_vars
+            .set_builtin(Camera_Up_Z, f64::from(self.camera.up.z));
     }
 
     pub fn get_last_work_buffer(&self) -> &FrameBuffer {
@@ -209,6 +219,7 @@ impl ContextGfx {
         frag_src_idx: usize,
         layout_to_vars: Vec<UniformMapping>,
         binding_to_buffers: Vec<BufferMapping>,
+        bind_uniforms_by_name: bool,
     ) {
         let n = self.quad_scenes.len();
         let quad_scene_id = if n > 0 { n - 1 } else { 0 };
@@ -217,6 +228,7 @@ impl ContextGfx {
 
         quad_scene.layout_to_vars = layout_to_vars;
         quad_scene.binding_to_buffers = binding_to_buffers;
+        quad_scene.bind_uniforms_by_name = bind_uniforms_by_name;
 
         self.quad_scenes.push(quad_scene);
     }
@@ -230,6 +242,12 @@ impl ContextGfx {
     }
 
     pub fn impl_target_buffer(&self, buffer_idx: usize) {
+        // The ops of a fading block draw their result image to the transition buffer.
+        if buffer_idx == 0 && self.transition.is_active {
+            self.transition.block_buffer.bind_for_drawing();
+            return;
+        }
+
         if let Some(buffer) = self.frame_buffers.get(buffer_idx) {
             if let Some(fbo) = buffer.fbo {
                 unsafe {
@@ -262,22 +280,8 @@ impl ContextGfx {
         }
     }
 
-    pub fn impl_profile_event(&mut self, _label_idx: usize) {
-        if self.profile_frame_idx < PROFILE_FRAMES && self.profile_event_idx < PROFILE_EVENTS {
-            let t_delta: Duration = self.t_frame_start.elapsed();
-            // t_delta as nanosec
-            let nanos: u64 =
-                (t_delta.as_secs() * 1_000_000_000) + u64::from(t_delta.subsec_nanos());
-            // as millisec
-            let millis: f32 = (nanos as f32) / 1_000_000_f32;
-
-            self.profile_times[self.profile_frame_idx][self.profile_event_idx] = millis;
-            self.profile_event_idx += 1;
-
-            if self.max_profile_event_idx < self.profile_event_idx {
-                self.max_profile_event_idx = self.profile_event_idx;
-            }
-        }
+    pub fn impl_profile_event(&mut self, label_idx: usize) {
+        self.profiler.event(label_idx, &self.t_frame_start);
     }
 
     pub fn impl_draw_polygon_scene(&self, scene_idx: usize) {
@@ -288,19 +292,105 @@ impl ContextGfx {
         }
     }
 
-    /*
-    pub fn impl_if_var_equal_draw_quad(&self, var_idx: usize, value: f64, scene_idx: usize) {
-        if self.vars[var_idx] == value {
+    pub fn set_block_vars(&mut self, block_time: f64, progress: f64) {
+        self.sync_vars.set_builtin(Block_Time, block_time);
+        self.sync_vars.set_builtin(Block_Progress, progress);
+    }
+
+    /// Counts the frame and sets the seconds since the previous one.
+    pub fn set_frame_vars(&mut self, delta_time: f64) {
+        let n = self.sync_vars.get_builtin(Frame_Index);
+        self.sync_vars.set_builtin(Frame_Index, n + 1.0);
+        self.sync_vars.set_builtin(Delta_Time, delta_time);
+    }
+
+    /// Starts drawing a fading scene block. Its ops will draw to the transition buffer instead of
+    /// the result image.
+    pub fn begin_transition(&mut self) {
+        self.transition.is_active = true;
+        self.impl_target_buffer(0);
+        self.impl_clear(0, 0, 0, 0);
+    }
+
+    /// Blends the image of the fading block over the result image.
+    pub fn end_transition(&mut self, fade: f64, transition: Transition) {
+        self.transition.is_active = false;
+        self.sync_vars.set_builtin(Block_Fade, fade);
+
+        let result = match self.frame_buffers.first() {
+            Some(x) => x,
+            None => panic!("Buffer index doesn't exist: 0"),
+        };
+        let (w, h) = (result.get_width(), result.get_height());
+
+        // Copy the result image to read from it while drawing to it.
+        result.bind_for_reading();
+        self.transition.below_buffer.bind_for_drawing();
+        unsafe {
+            gl::BlitFramebuffer(0, 0, w, h, 0, 0, w, h, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        }
+
+        self.impl_target_buffer(0);
+        self.transition.below_buffer.bind_as_texture(0).unwrap();
+        self.transition.block_buffer.bind_as_texture(1).unwrap();
+
+        unsafe {
+            let depth_test = gl::IsEnabled(gl::DEPTH_TEST);
+            gl::Disable(gl::DEPTH_TEST);
+
+            match transition {
+                Transition::Crossfade | Transition::Wipe => {
+                    let quad = match transition {
+                        Transition::Wipe => &self.transition.wipe,
+                        _ => &self.transition.crossfade,
+                    };
+                    if let Some(ref quad) = quad {
+                        gl::UseProgram(quad.program);
+                        gl::Uniform1f(0, fade as f32);
+                        gl::BindVertexArray(quad.vao);
+                        gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+                        gl::BindVertexArray(0);
+                    }
+                }
+                Transition::Custom(scene_idx) => self.impl_draw_quad_scene(scene_idx),
+            }
+
+            if depth_test == gl::TRUE {
+                gl::Enable(gl::DEPTH_TEST);
+            }
+        }
+    }
+
+    pub fn impl_if_var_draw_quad_scene(
+        &self,
+        var_idx: usize,
+        condition: VarCondition,
+        value: f64,
+        scene_idx: usize,
+    ) {
+        if self.is_var_condition(var_idx, condition, value) {
             self.impl_draw_quad_scene(scene_idx);
         }
     }
 
-    pub fn impl_if_var_equal_draw_polygon(&self, var_idx: usize, value: f64, scene_idx: usize) {
-        if self.vars[var_idx] == value {
+    pub fn impl_if_var_draw_polygon_scene(
+        &self,
+        var_idx: usize,
+        condition: VarCondition,
+        value: f64,
+        scene_idx: usize,
+    ) {
+        if self.is_var_condition(var_idx, condition, value) {
             self.impl_draw_polygon_scene(scene_idx);
         }
     }
-    */
+
+    pub(crate) fn is_var_condition(&self, var_idx: usize, condition: VarCondition, value: f64) -> bool {
+        match self.sync_vars.get_index(var_idx) {
+            Ok(x) => condition.is_true(x, value),
+            Err(_) => panic!("Sync var index doesn't exist: {}", var_idx),
+        }
+    }
 }
 
 impl Drop for ContextGfx {
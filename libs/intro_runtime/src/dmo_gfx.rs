@@ -1,20 +1,22 @@
 use std::str;
 use std::time::Instant;
 
-use intro_3d::lib::{to_radians, Vector3};
+use gl;
+
+use intro_3d::lib::{to_radians, Quaternion, Vector3};
 
 use crate::camera::Camera;
+use crate::camera_path::CameraMode;
 use crate::context_gfx::ContextGfx;
-use crate::context_gfx::PROFILE_FRAMES;
 use crate::dmo_sync::DmoSync;
 use crate::error::RuntimeError;
 use crate::mesh::Mesh;
 use crate::model::ModelType;
-use crate::types::{ValueFloat, ValueVec3};
+use crate::types::{ValueFloat, ValueVec3, ValueVec4};
 use crate::ERR_MSG_LEN;
 //use crate::error::RuntimeError;
 use crate::error::RuntimeError::*;
-use crate::timeline::Timeline;
+use crate::timeline::{DrawOp, Timeline};
 
 pub struct DmoGfx {
     pub settings: Settings,
@@ -23,12 +25,24 @@ pub struct DmoGfx {
     pub timeline: Timeline,
 }
 
+/// The CPU time span of a draw op, recorded by `DmoGfx::draw_with_spans()`.
+pub struct DrawOpSpan {
+    /// The draw op, or `None` for the transition at the end of a fading block.
+    pub op: Option<DrawOp>,
+    /// The `Debug` name of the op or the transition.
+    pub name: String,
+    pub t_start: Instant,
+    pub t_end: Instant,
+}
+
 pub struct Settings {
     pub start_full_screen: bool,
     pub audio_play_on_start: bool,
     pub mouse_sensitivity: f32,
     pub movement_sensitivity: f32,
     pub total_length: f64,
+    /// Loop to the start at `total_length`, otherwise stop there.
+    pub loop_at_end: bool,
 }
 
 impl Default for Settings {
@@ -39,6 +53,7 @@ impl Default for Settings {
             mouse_sensitivity: 0.5,
             movement_sensitivity: 0.5,
             total_length: 10.0,
+            loop_at_end: false,
         }
     }
 }
@@ -77,19 +92,91 @@ impl DmoGfx {
     }
 
     pub fn draw(&mut self) {
+        self.draw_timeline(None);
+    }
+
+    /// Draws the same as `draw()` and records the time span of each draw op for tracing.
+    ///
+    /// Waits for the GPU to finish after each op with `glFinish()`, so the spans include the GPU
+    /// work. This is slow, use it only when capturing a trace.
+    pub fn draw_with_spans(&mut self, spans: &mut Vec<DrawOpSpan>) {
+        self.draw_timeline(Some(spans));
+    }
+
+    fn draw_timeline(&mut self, mut spans: Option<&mut Vec<DrawOpSpan>>) {
+        use crate::timeline::DrawOp::*;
+
+        let time = self.context.get_time();
+        self.timeline.update_active_blocks(time);
+        let context = &mut self.context;
+
+        // Always start by clearing the "RESULT_IMAGE" buffer.
+
+        draw_op(context, Target_Buffer(0), &mut spans);
+        draw_op(context, Clear(0, 0, 0, 0), &mut spans);
+
+        for block_ref in self.timeline.active_blocks().iter() {
+            let block = self.timeline.get_block(block_ref);
+            let time = block_ref.local_time(time);
+
+            context.set_block_vars(time - block.start, block.get_progress(time));
+
+            let fade = block.get_fade(time);
+            let is_fading = fade < 1.0;
+
+            if is_fading {
+                context.begin_transition();
+            }
+
+            for op in block.draw_ops.iter() {
+                draw_op(context, *op, &mut spans);
+            }
+
+            if is_fading {
+                let t_start = Instant::now();
+                context.end_transition(fade, block.transition);
+                if let Some(ref mut s) = spans {
+                    finish_span(s, None, format! {"{:?}", block.transition}, t_start);
+                }
+            }
+        }
+
+        // The user must always render the final result to the framebuffer named
+        // "RESULT_IMAGE", which is implicitly created as the first item in the
+        // array `context.framebuffers`.
+        //
+        // Draw ops have an implicit final sequence: select the default
+        // framebuffer, clear with black, render a simple draw shader on a quad
+        // (scene index 0), reading from the "RESULT_IMAGE" framebuffer.
+
+        draw_op(context, Target_Buffer_Default, &mut spans);
+        draw_op(context, Clear(0, 0, 0, 0), &mut spans);
+        draw_op(context, Draw_Quad_Scene(0), &mut spans);
+    }
+
+    /// The polygon scenes which the blocks active at the last draw draw, in the order of the
+    /// blocks and without repeats. Conditional draw ops count when their condition is true now.
+    pub fn active_polygon_scenes(&self) -> Vec<usize> {
         use crate::timeline::DrawOp::*;
 
-        for op in self.timeline.draw_ops_at_time(self.context.get_time()) {
-            match op {
-                NOOP => {}
-                Draw_Quad_Scene(x) => self.context.impl_draw_quad_scene(x),
-                Draw_Poly_Scene(x) => self.context.impl_draw_polygon_scene(x),
-                Clear(r, g, b, a) => self.context.impl_clear(r, g, b, a),
-                Target_Buffer(x) => self.context.impl_target_buffer(x),
-                Target_Buffer_Default => self.context.impl_target_buffer_default(),
-                Profile(x) => self.context.impl_profile_event(x),
+        let mut scenes: Vec<usize> = Vec::new();
+        for block_ref in self.timeline.active_blocks().iter() {
+            for op in self.timeline.get_block(block_ref).draw_ops.iter() {
+                let scene_idx = match *op {
+                    Draw_Poly_Scene(x) => x,
+                    If_Var_Draw_Poly_Scene(var, cond, value, x)
+                        if self.context.is_var_condition(var, cond, value) =>
+                    {
+                        x
+                    }
+                    _ => continue,
+                };
+                if !scenes.contains(&scene_idx) {
+                    scenes.push(scene_idx);
+                }
             }
         }
+        scenes
     }
 
     pub fn create_quads(
@@ -108,8 +195,11 @@ impl DmoGfx {
             };
 
             scene.create_quad(vert_src, frag_src, err_msg_buf)?;
+            scene.update_named_to_vars(&self.context.sync_vars);
         }
 
+        self.context.transition.create_shaders(err_msg_buf)?;
+
         Ok(())
     }
 
@@ -134,6 +224,8 @@ impl DmoGfx {
             quad.compile_program(vert_src, frag_src, err_msg_buf)?;
         }
 
+        self.context.quad_scenes[scene_idx].update_named_to_vars(&self.context.sync_vars);
+
         Ok(())
     }
 
@@ -151,6 +243,9 @@ impl DmoGfx {
                 buffer.create_buffer(wx as i32, wy as i32, None)?;
             }
         }
+        self.context
+            .transition
+            .create_buffers(wx as i32, wy as i32)?;
         Ok(())
     }
 
@@ -171,11 +266,42 @@ impl DmoGfx {
                 buffer.create_buffer(wx as i32, wy as i32, None)?;
             }
         }
+        self.context
+            .transition
+            .create_buffers(wx as i32, wy as i32)?;
         Ok(())
     }
 
+    /// Sets the sync vars from the tracks, then from the camera path when there is one.
+    ///
+    /// Unless the camera mode is `Sync_Front`, the `Camera_Front_*` vars are set from the
+    /// look-at, so that they agree for the shaders and the explore mode camera.
     pub fn update_vars(&mut self) -> Result<(), RuntimeError> {
-        self.sync.update_vars(&mut self.context)
+        use crate::sync_vars::BuiltIn::*;
+
+        self.sync.update_vars(&mut self.context)?;
+
+        let sync_vars = &mut self.context.sync_vars;
+
+        match self.context.polygon_context.camera_mode {
+            CameraMode::Sync_Front => return Ok(()),
+            CameraMode::Sync_LookAt => {}
+            CameraMode::Path(ref mut path) => path.update(sync_vars)?,
+        }
+
+        let front = Vector3::new(
+            (sync_vars.get_builtin(Camera_LookAt_X) - sync_vars.get_builtin(Camera_Pos_X)) as f32,
+            (sync_vars.get_builtin(Camera_LookAt_Y) - sync_vars.get_builtin(Camera_Pos_Y)) as f32,
+            (sync_vars.get_builtin(Camera_LookAt_Z) - sync_vars.get_builtin(Camera_Pos_Z)) as f32,
+        );
+        if front.norm() > 0.0 {
+            let front = front.normalize();
+            sync_vars.set_builtin(Camera_Front_X, f64::from(front.x));
+            sync_vars.set_builtin(Camera_Front_Y, f64::from(front.y));
+            sync_vars.set_builtin(Camera_Front_Z, f64::from(front.z));
+        }
+
+        Ok(())
     }
 
     pub fn get_shader_src(&mut self, idx: usize) -> Result<Vec<u8>, RuntimeError> {
@@ -221,23 +347,28 @@ impl DmoGfx {
     }
 
     pub fn update_time_frame_start(&mut self, t: Instant) {
+        let t_delta = t.duration_since(self.context.t_frame_start);
+        self.context
+            .set_frame_vars(t_delta.as_secs() as f64 + f64::from(t_delta.subsec_nanos()) / 1e9);
         self.context.t_frame_start = t;
-        self.context.profile_event_idx = 0;
+        self.context.profiler.frame_start();
     }
 
     pub fn update_time_frame_end(&mut self, t: Instant) {
         self.context.t_frame_end = t;
-        if self.context.profile_frame_idx < PROFILE_FRAMES - 1 {
-            self.context.profile_frame_idx += 1;
-        } else {
-            self.context.profile_frame_idx = 0;
-        }
+        self.context.profiler.frame_end();
     }
 
     pub fn create_models(
         &mut self,
         err_msg_buf: &mut [u8; ERR_MSG_LEN],
     ) -> Result<(), RuntimeError> {
+        self.context
+            .polygon_context
+            .default_instances
+            .create_buffer()?;
+        self.context.polygon_context.lights.create_buffer()?;
+
         for model in self.context.polygon_context.models.iter_mut() {
             let mut new_meshes: Vec<Mesh> = Vec::new();
 
@@ -263,14 +394,16 @@ impl DmoGfx {
                         // file changes.
                         m.vert_src_idx = mesh.vert_src_idx;
                         m.frag_src_idx = mesh.frag_src_idx;
+                        m.update_named_to_vars(&self.context.sync_vars);
                         new_meshes.push(m);
                     }
 
-                    ModelType::Obj => {
+                    ModelType::Obj | ModelType::Gltf | ModelType::Primitive => {
                         let mut m = Mesh::new(
                             &mesh.vertices,
                             &mesh.indices,
                             &mesh.textures,
+                            mesh.layout,
                             &vert_src,
                             &frag_src,
                             err_msg_buf,
@@ -278,12 +411,20 @@ impl DmoGfx {
                         // Keep the idx
                         m.vert_src_idx = mesh.vert_src_idx;
                         m.frag_src_idx = mesh.frag_src_idx;
+                        m.transform = mesh.transform;
+                        m.material = mesh.material;
+                        m.binding_to_buffers = std::mem::take(&mut mesh.binding_to_buffers);
+                        m.create_textures(&mesh.images)?;
+                        // Keep the images too, for when the models are created again.
+                        m.images = std::mem::take(&mut mesh.images);
+                        m.update_named_to_vars(&self.context.sync_vars);
                         new_meshes.push(m);
                     }
                 }
             }
 
             model.meshes = new_meshes;
+            model.update_bounds();
         }
         Ok(())
     }
@@ -302,6 +443,7 @@ impl DmoGfx {
             let s = &self.context.shader_sources[mesh.frag_src_idx];
             let frag_src = str::from_utf8(s).unwrap();
             mesh.compile_program(vert_src, frag_src, err_msg_buf)?;
+            mesh.update_named_to_vars(&self.context.sync_vars);
         }
         Ok(())
     }
@@ -329,12 +471,42 @@ impl DmoGfx {
             self.context.sync_vars.get_builtin(Camera_Front_Z) as f32,
         );
 
+        // The up vector only comes from the tracks with a look-at, the front vector is usually
+        // animated without it.
+        match self.context.polygon_context.camera_mode {
+            CameraMode::Sync_Front => {}
+            CameraMode::Sync_LookAt | CameraMode::Path(_) => {
+                let up = Vector3::new(
+                    self.context.sync_vars.get_builtin(Camera_Up_X) as f32,
+                    self.context.sync_vars.get_builtin(Camera_Up_Y) as f32,
+                    self.context.sync_vars.get_builtin(Camera_Up_Z) as f32,
+                );
+                self.context.polygon_context.view_up = if up.norm() > 0.0 {
+                    up.normalize()
+                } else {
+                    Vector3::new(0.0, 1.0, 0.0)
+                };
+            }
+        }
+
         self.context.polygon_context.fovy = self.context.sync_vars.get_builtin(Fovy) as f32;
         self.context.polygon_context.znear = self.context.sync_vars.get_builtin(Znear) as f32;
         self.context.polygon_context.zfar = self.context.sync_vars.get_builtin(Zfar) as f32;
 
         self.context.polygon_context.update_view_matrix();
 
+        // Keep the previous projection while the tracks don't give a valid one.
+        let p = &mut self.context.polygon_context;
+        if p.fovy > 0.0 && p.fovy < 180.0 && p.znear > 0.0 && p.zfar > p.znear {
+            let aspect = p.aspect;
+            p.update_projection_matrix(aspect);
+        }
+
+        self.context
+            .polygon_context
+            .lights
+            .update(&self.context.sync_vars)?;
+
         for scene in self.context.polygon_scenes.iter_mut() {
             for mut scene_object in scene.scene_objects.iter_mut() {
                 match scene_object.position_var {
@@ -366,6 +538,21 @@ impl DmoGfx {
                     }
                 }
 
+                match scene_object.quaternion_rotation_var {
+                    ValueVec4::NOOP => {}
+                    ValueVec4::Sync(x, y, z, w) => {
+                        scene_object.quaternion_rotation = Some(normalized_quaternion(
+                            self.context.sync_vars.get_index(x as usize)? as f32,
+                            self.context.sync_vars.get_index(y as usize)? as f32,
+                            self.context.sync_vars.get_index(z as usize)? as f32,
+                            self.context.sync_vars.get_index(w as usize)? as f32,
+                        ));
+                    }
+                    ValueVec4::Fixed(x, y, z, w) => {
+                        scene_object.quaternion_rotation = Some(normalized_quaternion(x, y, z, w));
+                    }
+                }
+
                 match scene_object.scale_var {
                     ValueFloat::NOOP => {}
                     ValueFloat::Sync(x) => {
@@ -376,10 +563,70 @@ impl DmoGfx {
                     }
                 }
 
-                scene_object.update_model_matrix();
+                if let Some(ref mut instances) = scene_object.instances {
+                    match instances.count_var {
+                        ValueFloat::NOOP => {}
+                        ValueFloat::Sync(x) => {
+                            let n = self.context.sync_vars.get_index(x as usize)?;
+                            instances.update_count(n);
+                        }
+                        ValueFloat::Fixed(x) => instances.update_count(f64::from(x)),
+                    }
+                }
             }
+
+            scene.update_model_matrices();
         }
 
         Ok(())
     }
 }
+
+fn draw_op(context: &mut ContextGfx, op: DrawOp, spans: &mut Option<&mut Vec<DrawOpSpan>>) {
+    use crate::timeline::DrawOp::*;
+
+    let t_start = Instant::now();
+
+    match op {
+        NOOP => {}
+        Draw_Quad_Scene(x) => context.impl_draw_quad_scene(x),
+        Draw_Poly_Scene(x) => context.impl_draw_polygon_scene(x),
+        Clear(r, g, b, a) => context.impl_clear(r, g, b, a),
+        Target_Buffer(x) => context.impl_target_buffer(x),
+        Target_Buffer_Default => context.impl_target_buffer_default(),
+        Profile(x) => context.impl_profile_event(x),
+        If_Var_Draw_Quad_Scene(var, cond, value, x) => {
+            context.impl_if_var_draw_quad_scene(var, cond, value, x)
+        }
+        If_Var_Draw_Poly_Scene(var, cond, value, x) => {
+            context.impl_if_var_draw_polygon_scene(var, cond, value, x)
+        }
+    }
+
+    if let Some(ref mut s) = spans {
+        finish_span(s, Some(op), format! {"{:?}", op}, t_start);
+    }
+}
+
+fn finish_span(spans: &mut Vec<DrawOpSpan>, op: Option<DrawOp>, name: String, t_start: Instant) {
+    unsafe {
+        gl::Finish();
+    }
+    spans.push(DrawOpSpan {
+        op,
+        name,
+        t_start,
+        t_end: Instant::now(),
+    });
+}
+
+/// A unit quaternion from the components, or no rotation when they are all zero, such as from
+/// tracks without keys.
+fn normalized_quaternion(x: f32, y: f32, z: f32, w: f32) -> Quaternion {
+    let q = Quaternion::new(x, y, z, w);
+    if q.norm() > 0.0 {
+        q.normalize()
+    } else {
+        Quaternion::identity()
+    }
+}
@@ -2,8 +2,12 @@ use rocket_sync::SyncDevice;
 
 use crate::context_gfx::ContextGfx;
 use crate::error::RuntimeError;
+use crate::sync_vars::builtin_to_idx;
 use crate::sync_vars::BuiltIn::*;
 
+/// Beats in a bar for the `Bar` builtin and for timing in bars.
+pub const BEATS_PER_BAR: f64 = 4.0;
+
 pub struct DmoSync {
     pub device: SyncDevice,
 }
@@ -15,33 +19,38 @@ impl DmoSync {
             .sync_vars
             .set_builtin(Time, f64::from(self.device.time) / 1000.0);
 
+        // Beats from the bpm of the Rocket device.
+        let beats = f64::from(self.device.time) / 1000.0 * self.device.bpm / 60.0;
+        context.sync_vars.set_builtin(Beat, beats.floor());
+        context
+            .sync_vars
+            .set_builtin(Bar, (beats / BEATS_PER_BAR).floor());
+        context.sync_vars.set_builtin(Beat_Phase, beats.fract());
+
         // Get the Rocket track index for a given sync var idx and calculate the track's value.
 
-        // FIXME this is assuming Rocket device track idx = Sync var idx
+        // The Rocket device track idx = Sync var idx. The builtins come first, and the custom
+        // tracks start after the last one, so that a custom track never lands on a builtin which
+        // the runtime calculates.
         //
         // FIXME starting with idx 5 because Time, Screen_Width, Screen_Height, Window_Width,
         // Window_Height shouldn't be set by Rocket tracks
+        //
+        // Block_Time .. Delta_Time are calculated by the runtime and skipped as well.
+
+        let calculated = builtin_to_idx(Block_Time)..=builtin_to_idx(Delta_Time);
 
         for idx in 5..self.device.tracks.len() {
+            if calculated.contains(&idx) {
+                continue;
+            }
             let x = self.device.tracks[idx].value_at(self.device.row);
             context.sync_vars.set_index(idx, x)?;
         }
 
-        /*
-        context.sync_vars.set_builtin(Camera_Pos_X, context.camera.position.x as f64);
-        context.sync_vars.set_builtin(Camera_Pos_Y, context.camera.position.y as f64);
-        context.sync_vars.set_builtin(Camera_Pos_Z, context.camera.position.z as f64);
-        context.sync_vars.set_builtin(Camera_Front_X, context.camera.front.x as f64);
-        context.sync_vars.set_builtin(Camera_Front_Y, context.camera.front.y as f64);
-        context.sync_vars.set_builtin(Camera_Front_Z, context.camera.front.z as f64);
-        context.sync_vars.set_builtin(Camera_Up_X, context.camera.up.x as f64);
-        context.sync_vars.set_builtin(Camera_Up_Y, context.camera.up.y as f64);
-        context.sync_vars.set_builtin(Camera_Up_Z, context.camera.up.z as f64);
-
-        context.sync_vars.set_builtin(Fovy, context.camera.fovy_angle as f64);
-        context.sync_vars.set_builtin(Znear, context.camera.clip_near as f64);
-        context.sync_vars.set_builtin(Zfar, context.camera.clip_far as f64);
-        */
+        // The camera builtins are set from the tracks above. A camera path and the explore mode
+        // camera override them after this, see `DmoGfx::update_vars()` and
+        // `ContextGfx::set_camera_sync()`.
 
         Ok(())
     }
@@ -16,6 +16,8 @@ pub enum RuntimeError {
     NoId,
     NoFbo,
     NoUbo,
+    NoSsbo,
+    NoShadowMap,
     NoQuad,
     TextureBindingIdxIsOverTheHardwareLimit,
     UniformBlockBindingIdxIsOverTheHardwareLimit,
@@ -24,6 +26,8 @@ pub enum RuntimeError {
     TrackIdxIsOutOfBounds,
     VarIdxIsOutOfBounds,
     DataIdxIsOutOfBounds,
+    ParentIdxIsOutOfBounds,
+    ParentsFormACycle,
     CantOpenImage,
 }
 
@@ -0,0 +1,180 @@
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use libc::c_void;
+
+#[cfg(target_os = "windows")]
+use winapi::ctypes::c_void;
+
+use std::mem;
+
+use gl;
+use gl::types::*;
+
+use intro_3d::lib::{Matrix4, Vector3};
+
+use crate::error::RuntimeError;
+use crate::types::ValueFloat;
+
+/// Shader storage buffer binding of the instance model matrices. Shaders declare it as
+///
+/// ```glsl
+/// layout(std430, binding = 0) buffer Instances { mat4 instance_model[]; };
+/// ```
+///
+/// and read `instance_model[gl_InstanceID]`.
+pub const INSTANCES_BINDING: GLuint = 0;
+
+/// Model matrices of the copies of a scene object, drawn with one instanced draw call. The
+/// instance matrix is applied before the model matrix of the object.
+pub struct Instances {
+    /// Column-major model matrices of all the instances.
+    pub transforms: Vec<[[f32; 4]; 4]>,
+    /// Number of instances to draw, from the first ones.
+    pub count: usize,
+    pub count_var: ValueFloat,
+
+    ssbo: Option<GLuint>,
+}
+
+impl Default for Instances {
+    fn default() -> Instances {
+        Instances::new(vec![Matrix4::identity().as_column_slice()])
+    }
+}
+
+impl Instances {
+    pub fn new(transforms: Vec<[[f32; 4]; 4]>) -> Instances {
+        Instances {
+            count: transforms.len(),
+            count_var: ValueFloat::Fixed(transforms.len() as f32),
+            transforms,
+            ssbo: None,
+        }
+    }
+
+    /// Uploads the transforms into a shader storage buffer.
+    pub fn create_buffer(&mut self) -> Result<(), RuntimeError> {
+        self.gl_cleanup();
+
+        let mut ssbo: GLuint = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut ssbo);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, ssbo);
+            gl::BufferData(
+                gl::SHADER_STORAGE_BUFFER,
+                (self.transforms.len() * mem::size_of::<[[f32; 4]; 4]>()) as isize,
+                self.transforms.as_ptr() as *const c_void,
+                gl::STATIC_DRAW,
+            );
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        self.ssbo = Some(ssbo);
+
+        Ok(())
+    }
+
+    pub fn bind(&self) -> Result<(), RuntimeError> {
+        match self.ssbo {
+            Some(ssbo) => unsafe {
+                gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, INSTANCES_BINDING, ssbo);
+            },
+            None => return Err(RuntimeError::NoSsbo),
+        }
+        Ok(())
+    }
+
+    /// Sets the count from the sync var or fixed value, limited to the number of transforms.
+    pub fn update_count(&mut self, value: f64) {
+        self.count = (value.max(0.0) as usize).min(self.transforms.len());
+    }
+
+    pub fn gl_cleanup(&mut self) {
+        if let Some(ssbo) = self.ssbo.take() {
+            unsafe {
+                gl::DeleteBuffers(1, &ssbo);
+            }
+        }
+    }
+}
+
+impl Drop for Instances {
+    fn drop(&mut self) {
+        self.gl_cleanup();
+    }
+}
+
+/// A grid of `columns` along X, `rows` along Z and `layers` along Y, centered on the origin.
+pub fn grid_transforms(columns: u32, rows: u32, layers: u32, spacing: f32) -> Vec<[[f32; 4]; 4]> {
+    let (columns, rows, layers) = (columns.max(1), rows.max(1), layers.max(1));
+    let offset = |n: u32, i: u32| spacing * (i as f32 - (n - 1) as f32 * 0.5);
+
+    let mut transforms = Vec::with_capacity((columns * rows * layers) as usize);
+    for k in 0..layers {
+        for j in 0..rows {
+            for i in 0..columns {
+                let position = Vector3::new(offset(columns, i), offset(layers, k), offset(rows, j));
+                transforms.push(transform(&position, &Vector3::new(0.0, 0.0, 0.0), 1.0));
+            }
+        }
+    }
+    transforms
+}
+
+/// A ring around the Y axis, with the Z axis of each instance pointing out from the center.
+pub fn ring_transforms(count: u32, radius: f32) -> Vec<[[f32; 4]; 4]> {
+    use std::f32::consts::PI;
+
+    (0..count)
+        .map(|i| {
+            let a = 2.0 * PI * i as f32 / count as f32;
+            let position = Vector3::new(radius * a.sin(), 0.0, radius * a.cos());
+            transform(&position, &Vector3::new(0.0, a, 0.0), 1.0)
+        })
+        .collect()
+}
+
+/// Random positions within `-extent..extent` on each axis. The same seed gives the same
+/// positions. With `rotate`, each instance also gets a random rotation.
+pub fn random_transforms(
+    count: u32,
+    seed: u64,
+    extent: [f32; 3],
+    rotate: bool,
+) -> Vec<[[f32; 4]; 4]> {
+    use std::f32::consts::PI;
+
+    // xorshift64*, the seed must not be zero.
+    let mut state: u64 = seed ^ 0x9E37_79B9_7F4A_7C15;
+    if state == 0 {
+        state = 1;
+    }
+    let mut next = move || -> f32 {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        let x = state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        // Uniform in 0.0..1.0 from the top 24 bits.
+        (x >> 40) as f32 / (1 << 24) as f32
+    };
+
+    (0..count)
+        .map(|_| {
+            let position = Vector3::new(
+                extent[0] * (2.0 * next() - 1.0),
+                extent[1] * (2.0 * next() - 1.0),
+                extent[2] * (2.0 * next() - 1.0),
+            );
+            let rotation = if rotate {
+                Vector3::new(2.0 * PI * next(), 2.0 * PI * next(), 2.0 * PI * next())
+            } else {
+                Vector3::new(0.0, 0.0, 0.0)
+            };
+            transform(&position, &rotation, 1.0)
+        })
+        .collect()
+}
+
+/// Column-major model matrix, as `SceneObject::update_model_matrix()` makes. The rotation is in
+/// radians.
+pub fn transform(position: &Vector3, euler_rotation: &Vector3, scale: f32) -> [[f32; 4]; 4] {
+    Matrix4::new_homogeneous(position, euler_rotation, scale).as_column_slice()
+}
@@ -29,16 +29,23 @@ pub mod sync_vars;
 pub mod quad_scene_gfx;
 
 pub mod camera;
+pub mod camera_path;
+pub mod instances;
+pub mod lights;
 pub mod mesh;
 pub mod model;
 pub mod mouse;
 pub mod polygon_context;
 pub mod polygon_scene;
+pub mod profiler;
+pub mod shadow_map;
 pub mod timeline;
+pub mod transition;
 
 pub mod frame_buffer;
 pub mod texture;
 pub mod uniform_buffer;
+pub mod uniforms;
 
 pub mod data_blob;
 pub mod error;
@@ -0,0 +1,253 @@
+use intro_3d::lib::{to_radians, Vector3};
+
+use crate::error::RuntimeError;
+use crate::shadow_map::ShadowMap;
+use crate::sync_vars::SyncVars;
+use crate::types::{ValueFloat, ValueVec3};
+use crate::uniform_buffer::UniformBuffer;
+
+/// Uniform block binding of the light list. Shaders declare it as
+///
+/// ```glsl
+/// struct Light {
+///   vec4 position;  // .xyz, .w is the type: 0 point, 1 directional, 2 spot
+///   vec4 direction; // .xyz, .w is the cosine of the cutoff angle
+///   vec4 color;     // .rgb, .a is the strength
+///   vec4 falloff;   // constant, linear, quadratic, cosine of the outer cutoff angle
+///   vec4 shadow;    // .x is 1.0 when it has a shadow map, .y is the bias, .z the texel size
+/// };
+///
+/// layout(std140, binding = 0) uniform Lights {
+///   int light_count;
+///   Light lights[8];
+/// };
+/// ```
+pub const LIGHTS_BINDING: u8 = 0;
+
+/// The number of lights in the uniform block. Further lights are not uploaded.
+pub const MAX_LIGHTS: usize = 8;
+
+/// Size of the `Light` struct in the uniform block, five `vec4`.
+const LIGHT_SIZE: usize = 5 * 16;
+
+/// `light_count` padded to 16 bytes, followed by the lights.
+const LIGHTS_BLOCK_SIZE: usize = 16 + MAX_LIGHTS * LIGHT_SIZE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightType {
+    Point,
+    Directional,
+    Spot,
+}
+
+pub struct Light {
+    pub light_type: LightType,
+
+    pub position: Vector3,
+    pub position_var: ValueVec3,
+
+    /// The direction the light shines in, used by directional and spot lights.
+    pub direction: Vector3,
+    pub direction_var: ValueVec3,
+
+    pub color: Vector3,
+    pub color_var: ValueVec3,
+
+    pub strength: f32,
+    pub strength_var: ValueFloat,
+
+    /// Constant, linear and quadratic falloff with distance, used by point and spot lights.
+    pub falloff: Vector3,
+    pub falloff_var: ValueVec3,
+
+    /// Spot lights are at full strength within the cutoff angle from the direction, and fade
+    /// out until the outer cutoff angle. In degrees.
+    pub cutoff_angle: f32,
+    pub cutoff_angle_var: ValueFloat,
+    pub outer_cutoff_angle: f32,
+    pub outer_cutoff_angle_var: ValueFloat,
+
+    /// Directional and spot lights can cast shadows. The scene depth is drawn into the map
+    /// before drawing a polygon scene.
+    pub shadow_map: Option<ShadowMap>,
+}
+
+/// The lights of the `PolygonContext`, uploaded to a uniform block which every polygon shader
+/// can read.
+pub struct Lights {
+    pub lights: Vec<Light>,
+    buffer: Option<UniformBuffer>,
+}
+
+impl Default for Lights {
+    fn default() -> Lights {
+        Lights::new()
+    }
+}
+
+impl Lights {
+    pub fn new() -> Lights {
+        Lights {
+            lights: Vec::new(),
+            buffer: None,
+        }
+    }
+
+    pub fn create_buffer(&mut self) -> Result<(), RuntimeError> {
+        let mut buffer = UniformBuffer::new();
+        buffer.create_buffer(LIGHTS_BLOCK_SIZE)?;
+        self.buffer = Some(buffer);
+
+        for light in self.lights.iter_mut() {
+            if let Some(ref mut shadow_map) = light.shadow_map {
+                shadow_map.create_buffer()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Updates the light values from the sync vars and uploads them, when the buffer has been
+    /// created.
+    pub fn update(&mut self, sync_vars: &SyncVars) -> Result<(), RuntimeError> {
+        for light in self.lights.iter_mut() {
+            light.update(sync_vars)?;
+        }
+
+        if let Some(ref mut buffer) = self.buffer {
+            let count = self.lights.len().min(MAX_LIGHTS);
+            buffer.set_i32(0, count as i32)?;
+            for (idx, light) in self.lights.iter().take(count).enumerate() {
+                buffer.set_vec4_array(16 + idx * LIGHT_SIZE, &light.as_std140())?;
+            }
+            buffer.update_buffer_data()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn bind(&self) -> Result<(), RuntimeError> {
+        match self.buffer {
+            Some(ref buffer) => buffer.bind_as_uniform_block(LIGHTS_BINDING),
+            None => Err(RuntimeError::NoUbo),
+        }
+    }
+
+    pub fn get_shadow_map(&self, light_idx: usize) -> Result<&ShadowMap, RuntimeError> {
+        match self.lights.get(light_idx) {
+            Some(Light {
+                shadow_map: Some(ref x),
+                ..
+            }) => Ok(x),
+            _ => Err(RuntimeError::NoShadowMap),
+        }
+    }
+}
+
+impl Light {
+    pub fn new(light_type: LightType) -> Light {
+        Light {
+            light_type,
+
+            position: Vector3::new(0.0, 0.0, 0.0),
+            position_var: ValueVec3::NOOP,
+
+            direction: Vector3::new(0.0, -1.0, 0.0),
+            direction_var: ValueVec3::NOOP,
+
+            color: Vector3::new(1.0, 1.0, 1.0),
+            color_var: ValueVec3::NOOP,
+
+            strength: 1.0,
+            strength_var: ValueFloat::NOOP,
+
+            // Reaches to about 50 units.
+            falloff: Vector3::new(1.0, 0.09, 0.032),
+            falloff_var: ValueVec3::NOOP,
+
+            cutoff_angle: 12.5,
+            cutoff_angle_var: ValueFloat::NOOP,
+            outer_cutoff_angle: 17.5,
+            outer_cutoff_angle_var: ValueFloat::NOOP,
+
+            shadow_map: None,
+        }
+    }
+
+    pub fn update(&mut self, sync_vars: &SyncVars) -> Result<(), RuntimeError> {
+        update_vec3(&self.position_var, sync_vars, &mut self.position)?;
+        update_vec3(&self.direction_var, sync_vars, &mut self.direction)?;
+        update_vec3(&self.color_var, sync_vars, &mut self.color)?;
+        update_float(&self.strength_var, sync_vars, &mut self.strength)?;
+        update_vec3(&self.falloff_var, sync_vars, &mut self.falloff)?;
+        update_float(&self.cutoff_angle_var, sync_vars, &mut self.cutoff_angle)?;
+        update_float(
+            &self.outer_cutoff_angle_var,
+            sync_vars,
+            &mut self.outer_cutoff_angle,
+        )?;
+
+        if let Some(mut shadow_map) = self.shadow_map.take() {
+            shadow_map.update_matrices(self);
+            self.shadow_map = Some(shadow_map);
+        }
+
+        Ok(())
+    }
+
+    /// The light as the `vec4` values of the `Light` struct in the uniform block.
+    pub fn as_std140(&self) -> [[f32; 4]; 5] {
+        let light_type = match self.light_type {
+            LightType::Point => 0.0,
+            LightType::Directional => 1.0,
+            LightType::Spot => 2.0,
+        };
+        let p = &self.position;
+        let d = &self.direction;
+        let c = &self.color;
+        let f = &self.falloff;
+        let shadow = match self.shadow_map {
+            Some(ref x) => [1.0, x.bias, 1.0 / x.resolution.max(1) as f32, 0.0],
+            None => [0.0; 4],
+        };
+        [
+            [p.x, p.y, p.z, light_type],
+            [d.x, d.y, d.z, to_radians(self.cutoff_angle).cos()],
+            [c.x, c.y, c.z, self.strength],
+            [f.x, f.y, f.z, to_radians(self.outer_cutoff_angle).cos()],
+            shadow,
+        ]
+    }
+}
+
+fn update_vec3(
+    var: &ValueVec3,
+    sync_vars: &SyncVars,
+    value: &mut Vector3,
+) -> Result<(), RuntimeError> {
+    match *var {
+        ValueVec3::NOOP => {}
+        ValueVec3::Sync(x, y, z) => {
+            *value = Vector3::new(
+                sync_vars.get_index(x as usize)? as f32,
+                sync_vars.get_index(y as usize)? as f32,
+                sync_vars.get_index(z as usize)? as f32,
+            );
+        }
+        ValueVec3::Fixed(x, y, z) => *value = Vector3::new(x, y, z),
+    }
+    Ok(())
+}
+
+fn update_float(
+    var: &ValueFloat,
+    sync_vars: &SyncVars,
+    value: &mut f32,
+) -> Result<(), RuntimeError> {
+    match *var {
+        ValueFloat::NOOP => {}
+        ValueFloat::Sync(x) => *value = sync_vars.get_index(x as usize)? as f32,
+        ValueFloat::Fixed(x) => *value = x,
+    }
+    Ok(())
+}
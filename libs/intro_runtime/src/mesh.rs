@@ -9,16 +9,29 @@ use std::{mem, ptr};
 use gl;
 use gl::types::*;
 
+use intro_3d::lib::{Matrix4, Vector3};
+
 use crate::context_gfx::ContextGfx;
 use crate::error::RuntimeError;
 use crate::error::RuntimeError::*;
 use crate::model::ModelViewProjection;
 use crate::shader::{compile_shader, link_program};
-use crate::shapes::{CUBE_ELEMENTS, CUBE_VERTICES};
+use crate::shapes;
+use crate::sync_vars::SyncVars;
 use crate::texture::Texture;
-use crate::types::{BufferMapping, UniformMapping, Vertex};
+use crate::types::{BufferMapping, Image, Material, UniformMapping, Vertex, VertexLayout};
+use crate::uniforms::{get_uniform_location, set_uniform_mappings, uniform_mappings_by_name};
 use crate::ERR_MSG_LEN;
 
+/// Uniform names of the `Material` fields, in the order of the fields.
+const MATERIAL_UNIFORM_NAMES: [&str; 5] = [
+    "material_ambient",
+    "material_diffuse",
+    "material_specular",
+    "material_shininess",
+    "material_opacity",
+];
+
 pub struct Mesh {
     pub vert_src_idx: usize,
     pub frag_src_idx: usize,
@@ -26,8 +39,25 @@ pub struct Mesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
     pub textures: Vec<Texture>,
+    pub layout: VertexLayout,
+    /// Images to create the textures from when the mesh is created. The textures are bound in
+    /// this order from binding 0 when drawing.
+    pub images: Vec<Image>,
+    /// Transform of the mesh within its model, such as a glTF node transform, in column-major
+    /// order. It is applied before the model matrix of the scene object.
+    pub transform: Option<[[f32; 4]; 4]>,
+    pub material: Option<Material>,
+    /// Frame buffers bound as textures for this mesh, such as the textures of its OBJ material.
+    /// They are bound after the mesh textures and before the buffer mappings of the scene object.
+    pub binding_to_buffers: Vec<BufferMapping>,
+
+    /// Mappings found by name after the shader program was linked. Scene
+    /// objects which bind uniforms by name will use these.
+    pub named_to_vars: Vec<UniformMapping>,
 
     program: GLuint,
+    /// Locations of the `MATERIAL_UNIFORM_NAMES` uniforms in the program.
+    material_locations: [GLint; 5],
     vao: GLuint,
     vbo: GLuint,
     ebo: GLuint,
@@ -42,8 +72,16 @@ impl Default for Mesh {
             vertices: Vec::new(),
             indices: Vec::new(),
             textures: Vec::new(),
+            layout: VertexLayout::default(),
+            images: Vec::new(),
+            transform: None,
+            material: None,
+            binding_to_buffers: Vec::new(),
+
+            named_to_vars: Vec::new(),
 
             program: 0,
+            material_locations: [-1; 5],
 
             vao: 0,
             vbo: 0,
@@ -57,6 +95,7 @@ impl Mesh {
         vertices: &[Vertex],
         indices: &[u32],
         textures: &[Texture],
+        layout: VertexLayout,
         vert_src: &str,
         frag_src: &str,
         err_msg_buf: &mut [u8; ERR_MSG_LEN],
@@ -72,7 +111,14 @@ impl Mesh {
             vertices: vertices.to_vec(),
             indices: indices.to_vec(),
             textures: textures.to_vec(),
+            layout,
+            images: Vec::new(),
+            transform: None,
+            material: None,
+            binding_to_buffers: Vec::new(),
+            named_to_vars: Vec::new(),
             program: 0,
+            material_locations: [-1; 5],
             vao: 0,
             vbo: 0,
             ebo: 0,
@@ -114,35 +160,51 @@ impl Mesh {
                 ptr::null(),
             );
             // normal
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                mem::size_of::<Vertex>() as GLsizei,
-                (3 * mem::size_of::<GLfloat>()) as *const c_void,
-            );
+            if mesh.layout.normal {
+                gl::EnableVertexAttribArray(1);
+                gl::VertexAttribPointer(
+                    1,
+                    3,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    mem::size_of::<Vertex>() as GLsizei,
+                    (3 * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+            }
             // texture coord
-            gl::EnableVertexAttribArray(2);
-            gl::VertexAttribPointer(
-                2,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                mem::size_of::<Vertex>() as GLsizei,
-                ((3 + 3) * mem::size_of::<GLfloat>()) as *const c_void,
-            );
-            // // tangent
-            // gl::EnableVertexAttribArray(3);
-            // gl::VertexAttribPointer(3, 3, gl::FLOAT, gl::FALSE,
-            //                         mem::size_of::<Vertex>() as GLsizei,
-            //                         mem::transmute((3+3+2) * mem::size_of::<GLfloat>()));
-            // // bitangent
-            // gl::EnableVertexAttribArray(4);
-            // gl::VertexAttribPointer(4, 3, gl::FLOAT, gl::FALSE,
-            //                         mem::size_of::<Vertex>() as GLsizei,
-            //                         mem::transmute((3+3+2+3) * mem::size_of::<GLfloat>()));
+            if mesh.layout.texcoords {
+                gl::EnableVertexAttribArray(2);
+                gl::VertexAttribPointer(
+                    2,
+                    2,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    mem::size_of::<Vertex>() as GLsizei,
+                    ((3 + 3) * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+            }
+            if mesh.layout.tangents {
+                // tangent
+                gl::EnableVertexAttribArray(3);
+                gl::VertexAttribPointer(
+                    3,
+                    3,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    mem::size_of::<Vertex>() as GLsizei,
+                    ((3 + 3 + 2) * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+                // bitangent
+                gl::EnableVertexAttribArray(4);
+                gl::VertexAttribPointer(
+                    4,
+                    3,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    mem::size_of::<Vertex>() as GLsizei,
+                    ((3 + 3 + 2 + 3) * mem::size_of::<GLfloat>()) as *const c_void,
+                );
+            }
 
             // unbind
             gl::BindVertexArray(0);
@@ -165,20 +227,9 @@ impl Mesh {
         frag_src: &str,
         err_msg_buf: &mut [u8; ERR_MSG_LEN],
     ) -> Result<Mesh, RuntimeError> {
-        let mut vertices: Vec<Vertex> = Vec::new();
-
-        for v in CUBE_VERTICES.iter() {
-            vertices.push(Vertex {
-                position: [v[0], v[1], v[2]],
-                normal: [v[3], v[4], v[5]],
-                texcoords: [v[6], v[7]],
-                //tangent:   [0.0, 0.0, 0.0],
-                //bitangent: [0.0, 0.0, 0.0],
-            });
-        }
+        let (mut vertices, indices) = shapes::cube();
 
-        let mut indices: Vec<u32> = Vec::new();
-        indices.extend_from_slice(&CUBE_ELEMENTS);
+        compute_tangents(&mut vertices, &indices);
 
         let textures: Vec<Texture> = Vec::new();
 
@@ -186,6 +237,7 @@ impl Mesh {
             &vertices,
             &indices,
             &textures,
+            VertexLayout::default(),
             vert_src,
             frag_src,
             err_msg_buf,
@@ -193,6 +245,84 @@ impl Mesh {
         Ok(cube)
     }
 
+    /// Sets the normal of each vertex to the average of the normals of the triangles which share
+    /// it, weighted by the triangle areas. Use for meshes which don't have normals.
+    pub fn compute_smooth_normals(&mut self) {
+        let mut sums: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+
+        for tri in self.indices.chunks_exact(3) {
+            let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+            if a >= self.vertices.len() || b >= self.vertices.len() || c >= self.vertices.len() {
+                continue;
+            }
+            // Not normalized, so that larger triangles weigh more.
+            let n = triangle_normal(&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+            for &i in [a, b, c].iter() {
+                sums[i] += Vector3::from_vec(&n);
+            }
+        }
+
+        for (vertex, sum) in self.vertices.iter_mut().zip(sums.iter()) {
+            vertex.normal = normalize_or_zero(sum).as_slice();
+        }
+        self.layout.normal = true;
+    }
+
+    /// Creates a texture from each image and adds them to the textures of the mesh.
+    pub fn create_textures(&mut self, images: &[Image]) -> Result<(), RuntimeError> {
+        for image in images.iter() {
+            let mut texture = Texture::new(image.format, None);
+            texture.create_texture(image.width as i32, image.height as i32, Some(image))?;
+            self.textures.push(texture);
+        }
+        Ok(())
+    }
+
+    /// Computes the tangents and bitangents from the positions, normals and texcoords, for normal
+    /// mapping. Meshes without texcoords don't get tangents.
+    pub fn compute_tangents(&mut self) {
+        if self.layout.texcoords {
+            compute_tangents(&mut self.vertices, &self.indices);
+        }
+        self.layout.tangents = self.layout.texcoords;
+    }
+
+    /// Turns the faces inside out, reversing the winding and the normals, to see the mesh from
+    /// the inside such as a room or a sky dome.
+    pub fn flip_faces(&mut self) {
+        for tri in self.indices.chunks_exact_mut(3) {
+            tri.swap(1, 2);
+        }
+        for v in self.vertices.iter_mut() {
+            v.normal = [-v.normal[0], -v.normal[1], -v.normal[2]];
+        }
+        self.compute_tangents();
+    }
+
+    /// Gives each triangle its own three vertices with the normal of the triangle, for a faceted
+    /// look. Use for meshes which don't have normals.
+    pub fn compute_flat_normals(&mut self) {
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(self.indices.len());
+
+        for tri in self.indices.chunks_exact(3) {
+            let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+            if a >= self.vertices.len() || b >= self.vertices.len() || c >= self.vertices.len() {
+                continue;
+            }
+            let n = triangle_normal(&self.vertices[a], &self.vertices[b], &self.vertices[c]);
+            let normal = normalize_or_zero(&n).as_slice();
+            for &i in [a, b, c].iter() {
+                let mut v = self.vertices[i].clone();
+                v.normal = normal;
+                vertices.push(v);
+            }
+        }
+
+        self.indices = (0..vertices.len() as u32).collect();
+        self.vertices = vertices;
+        self.layout.normal = true;
+    }
+
     pub fn compile_program(
         &mut self,
         vert_src: &str,
@@ -202,16 +332,32 @@ impl Mesh {
         let vs = compile_shader(vert_src, gl::VERTEX_SHADER, err_msg_buf)?;
         let fs = compile_shader(frag_src, gl::FRAGMENT_SHADER, err_msg_buf)?;
         self.program = link_program(vs, fs, err_msg_buf)?;
+        for (location, name) in self
+            .material_locations
+            .iter_mut()
+            .zip(MATERIAL_UNIFORM_NAMES.iter())
+        {
+            *location = get_uniform_location(self.program, name);
+        }
         Ok(())
     }
 
+    /// Finds the uniforms of the shader program which can be bound to sync
+    /// vars by name. Call again when the program is recompiled.
+    pub fn update_named_to_vars(&mut self, sync_vars: &SyncVars) {
+        self.named_to_vars = uniform_mappings_by_name(self.program, sync_vars);
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &self,
         context: &ContextGfx,
         layout_to_vars: &[UniformMapping],
         binding_to_buffers: &[BufferMapping],
+        bind_uniforms_by_name: bool,
         mvp: &ModelViewProjection,
         camera_pos: &[f32; 3],
+        instance_count: usize,
     ) -> Result<(), RuntimeError> {
         // bind vao, use shader
         unsafe {
@@ -225,93 +371,47 @@ impl Mesh {
         // 2 = mat4 projection
         // 3 = vec3 camera pos
 
+        let model = match self.transform {
+            Some(transform) => {
+                let a = Matrix4::from_row_slice(mvp.model).transpose();
+                let b = Matrix4::from_row_slice(transform).transpose();
+                (a * b).as_column_slice()
+            }
+            None => mvp.model,
+        };
+
         unsafe {
-            gl::UniformMatrix4fv(0, 1, gl::FALSE, mvp.model[0].as_ptr());
+            gl::UniformMatrix4fv(0, 1, gl::FALSE, model[0].as_ptr());
             gl::UniformMatrix4fv(1, 1, gl::FALSE, mvp.view[0].as_ptr());
             gl::UniformMatrix4fv(2, 1, gl::FALSE, mvp.projection[0].as_ptr());
             gl::Uniform3f(3, camera_pos[0], camera_pos[1], camera_pos[2]);
         }
 
-        // TODO this is the same as in QuadScene::draw()
-
-        unsafe {
-            // Mapping sync var indexes to uniform layout indexes
-            for item in layout_to_vars.iter() {
-                use crate::types::UniformMapping::*;
-                match *item {
-                    NOOP => {}
-
-                    Float(layout_idx, var_idx) => {
-                        gl::Uniform1f(
-                            i32::from(layout_idx),
-                            context.sync_vars.get_index(var_idx as usize)? as f32,
-                        );
-                    }
-
-                    Vec2(layout_idx, var1, var2) => {
-                        gl::Uniform2f(
-                            i32::from(layout_idx),
-                            context.sync_vars.get_index(var1 as usize)? as f32,
-                            context.sync_vars.get_index(var2 as usize)? as f32,
-                        );
-                    }
-
-                    Vec3(layout_idx, var1, var2, var3) => {
-                        gl::Uniform3f(
-                            i32::from(layout_idx),
-                            context.sync_vars.get_index(var1 as usize)? as f32,
-                            context.sync_vars.get_index(var2 as usize)? as f32,
-                            context.sync_vars.get_index(var3 as usize)? as f32,
-                        );
-                    }
-
-                    Vec4(layout_idx, var1, var2, var3, var4) => {
-                        gl::Uniform4f(
-                            i32::from(layout_idx),
-                            context.sync_vars.get_index(var1 as usize)? as f32,
-                            context.sync_vars.get_index(var2 as usize)? as f32,
-                            context.sync_vars.get_index(var3 as usize)? as f32,
-                            context.sync_vars.get_index(var4 as usize)? as f32,
-                        );
-                    }
-                }
+        if let Some(ref m) = self.material {
+            let l = &self.material_locations;
+            unsafe {
+                gl::Uniform3f(l[0], m.ambient[0], m.ambient[1], m.ambient[2]);
+                gl::Uniform3f(l[1], m.diffuse[0], m.diffuse[1], m.diffuse[2]);
+                gl::Uniform3f(l[2], m.specular[0], m.specular[1], m.specular[2]);
+                gl::Uniform1f(l[3], m.shininess);
+                gl::Uniform1f(l[4], m.opacity);
             }
         }
 
-        // TODO this is the same as in QuadScene::draw()
+        // Uniforms bound by name first, so that explicit layout mappings can
+        // override them.
+        if bind_uniforms_by_name {
+            set_uniform_mappings(context, &self.named_to_vars)?;
+        }
+        set_uniform_mappings(context, layout_to_vars)?;
 
-        unsafe {
-            // Bind a buffer as texture
-            for item in binding_to_buffers.iter() {
-                use crate::types::BufferMapping::*;
-                match *item {
-                    NOOP => {}
-
-                    Sampler2D(binding_idx, buffer_idx) => {
-                        if (buffer_idx as usize) < context.frame_buffers.len() {
-                            if binding_idx <= gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS as u8 {
-                                if let Some(fbo) = context.frame_buffers[buffer_idx as usize].fbo {
-                                    gl::ActiveTexture(gl::TEXTURE0 + u32::from(binding_idx));
-                                    gl::BindTexture(gl::TEXTURE_2D, fbo);
-                                } else {
-                                    return Err(NoFbo);
-                                }
-                            } else {
-                                return Err(TextureBindingIdxIsOverTheHardwareLimit);
-                            }
-                        } else {
-                            return Err(TextureBindingIdxDoesntExist);
-                        }
-                    }
-                }
-            }
+        // The textures of the mesh first, so that the buffer mappings can override them.
+        for (binding_idx, t) in self.textures.iter().enumerate() {
+            t.bind(binding_idx as u8)?;
         }
 
-        // FIXME remove textures?
-        // // bind the textures
-        // for (binding_idx, t) in self.textures.iter().enumerate() {
-        //     t.bind(binding_idx as u8)?;
-        // }
+        bind_buffer_mappings(context, &self.binding_to_buffers)?;
+        bind_buffer_mappings(context, binding_to_buffers)?;
 
         unsafe {
             // draw the mesh
@@ -320,15 +420,21 @@ impl Mesh {
                 // If the indices list is not empty, assume the vertices are EBO
                 // indexed array.
                 gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-                gl::DrawElements(
+                gl::DrawElementsInstanced(
                     gl::TRIANGLES,
                     self.indices.len() as i32,
                     gl::UNSIGNED_INT,
                     ptr::null(),
+                    instance_count as i32,
                 );
             } else {
                 // Otherwise, draw the vertices as a triangle array.
-                gl::DrawArrays(gl::TRIANGLES, 0, self.vertices.len() as i32);
+                gl::DrawArraysInstanced(
+                    gl::TRIANGLES,
+                    0,
+                    self.vertices.len() as i32,
+                    instance_count as i32,
+                );
             }
 
             // unbind VAO
@@ -352,6 +458,128 @@ impl Mesh {
     }
 }
 
+/// Sets the tangent and bitangent of each vertex, which point along the U and V texture axes on the
+/// surface. The per-triangle values are summed at the shared vertices, then the tangent is made
+/// orthogonal to the normal, and the bitangent is the cross product of the two, flipped where the
+/// texture is mirrored.
+pub fn compute_tangents(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut tangents: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    let mut bitangents: Vec<Vector3> = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+
+    for tri in indices.chunks_exact(3) {
+        let (a, b, c) = (tri[0] as usize, tri[1] as usize, tri[2] as usize);
+        if a >= vertices.len() || b >= vertices.len() || c >= vertices.len() {
+            continue;
+        }
+
+        let p0 = Vector3::from_slice(&vertices[a].position);
+        let e1 = Vector3::from_slice(&vertices[b].position) - Vector3::from_vec(&p0);
+        let e2 = Vector3::from_slice(&vertices[c].position) - p0;
+
+        let uv0 = vertices[a].texcoords;
+        let (du1, dv1) = (
+            vertices[b].texcoords[0] - uv0[0],
+            vertices[b].texcoords[1] - uv0[1],
+        );
+        let (du2, dv2) = (
+            vertices[c].texcoords[0] - uv0[0],
+            vertices[c].texcoords[1] - uv0[1],
+        );
+
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < f32::MIN_POSITIVE {
+            // Degenerate texture mapping.
+            continue;
+        }
+        let r = 1.0 / det;
+
+        let t = (Vector3::from_vec(&e1) * dv2 - Vector3::from_vec(&e2) * dv1) * r;
+        let bt = (e2 * du1 - e1 * du2) * r;
+
+        for &i in [a, b, c].iter() {
+            tangents[i] += Vector3::from_vec(&t);
+            bitangents[i] += Vector3::from_vec(&bt);
+        }
+    }
+
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let n = Vector3::from_slice(&vertex.normal);
+        let t = &tangents[i];
+
+        // Gram-Schmidt
+        let t = normalize_or_zero(&(Vector3::from_vec(t) - Vector3::from_vec(&n) * dot(&n, t)));
+        let mut bt = n.cross(&t);
+        if dot(&bt, &bitangents[i]) < 0.0 {
+            bt = -bt;
+        }
+
+        vertex.tangent = t.as_slice();
+        vertex.bitangent = bt.as_slice();
+    }
+}
+
+// TODO this is the same as in QuadScene::draw()
+fn bind_buffer_mappings(
+    context: &ContextGfx,
+    binding_to_buffers: &[BufferMapping],
+) -> Result<(), RuntimeError> {
+    unsafe {
+        // Bind a buffer as texture
+        for item in binding_to_buffers.iter() {
+            use crate::types::BufferMapping::*;
+            match *item {
+                NOOP => {}
+
+                Sampler2D(binding_idx, buffer_idx) => {
+                    if (buffer_idx as usize) < context.frame_buffers.len() {
+                        if binding_idx <= gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS as u8 {
+                            if let Some(fbo) = context.frame_buffers[buffer_idx as usize].fbo {
+                                gl::ActiveTexture(gl::TEXTURE0 + u32::from(binding_idx));
+                                gl::BindTexture(gl::TEXTURE_2D, fbo);
+                            } else {
+                                return Err(NoFbo);
+                            }
+                        } else {
+                            return Err(TextureBindingIdxIsOverTheHardwareLimit);
+                        }
+                    } else {
+                        return Err(TextureBindingIdxDoesntExist);
+                    }
+                }
+
+                ShadowMap(binding_idx, light_idx) => {
+                    context
+                        .polygon_context
+                        .lights
+                        .get_shadow_map(light_idx as usize)?
+                        .bind_as_texture(binding_idx)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn dot(a: &Vector3, b: &Vector3) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z
+}
+
+/// The cross product of two edges, its length is twice the area of the triangle.
+fn triangle_normal(a: &Vertex, b: &Vertex, c: &Vertex) -> Vector3 {
+    let a = Vector3::from_slice(&a.position);
+    let ab = Vector3::from_slice(&b.position) - Vector3::from_vec(&a);
+    let ac = Vector3::from_slice(&c.position) - a;
+    ab.cross(&ac)
+}
+
+fn normalize_or_zero(v: &Vector3) -> Vector3 {
+    if v.norm() > 0.0 {
+        v.normalize()
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
 impl Drop for Mesh {
     fn drop(&mut self) {
         self.gl_cleanup();
@@ -1,3 +1,5 @@
+use intro_3d::lib::{Aabb, Frustum, Matrix4, Ray, Vector3};
+
 use crate::context_gfx::ContextGfx;
 use crate::error::RuntimeError;
 use crate::mesh::Mesh;
@@ -7,6 +9,9 @@ pub struct Model {
     pub model_type: ModelType,
     // pub textures_loaded: Vec<Texture>, // is this needed?
     pub meshes: Vec<Mesh>,
+    /// The box around the vertices of the meshes in the model space, set by `update_bounds()`
+    /// when the meshes are created.
+    pub bounds: Option<Aabb>,
 }
 
 pub struct ModelViewProjection {
@@ -20,6 +25,8 @@ pub enum ModelType {
     NOOP,
     Cube,
     Obj,
+    Gltf,
+    Primitive,
 }
 
 impl Default for Model {
@@ -33,6 +40,7 @@ impl Model {
         Model {
             model_type: ModelType::Cube,
             meshes: Vec::new(),
+            bounds: None,
         }
     }
 
@@ -40,6 +48,23 @@ impl Model {
         Model {
             model_type: ModelType::Obj,
             meshes: Vec::new(),
+            bounds: None,
+        }
+    }
+
+    pub fn empty_gltf() -> Model {
+        Model {
+            model_type: ModelType::Gltf,
+            meshes: Vec::new(),
+            bounds: None,
+        }
+    }
+
+    pub fn empty_primitive() -> Model {
+        Model {
+            model_type: ModelType::Primitive,
+            meshes: Vec::new(),
+            bounds: None,
         }
     }
 
@@ -53,6 +78,7 @@ impl Model {
         let mut model = Model {
             model_type: ModelType::Cube,
             meshes: Vec::new(),
+            bounds: None,
         };
 
         // Add a mesh but no vertices, which will be created from already stored shapes.
@@ -65,20 +91,109 @@ impl Model {
         model
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &self,
         context: &ContextGfx,
         layout_to_vars: &[UniformMapping],
         binding_to_buffers: &[BufferMapping],
+        bind_uniforms_by_name: bool,
         mvp: &ModelViewProjection,
         camera_pos: &[f32; 3],
+        instance_count: usize,
     ) -> Result<(), RuntimeError> {
         for m in self.meshes.iter() {
-            m.draw(context, layout_to_vars, binding_to_buffers, mvp, camera_pos)?;
+            m.draw(
+                context,
+                layout_to_vars,
+                binding_to_buffers,
+                bind_uniforms_by_name,
+                mvp,
+                camera_pos,
+                instance_count,
+            )?;
         }
         Ok(())
     }
 
+    /// The axis-aligned box around the vertices of the meshes, after the mesh transforms and then
+    /// `transform`, which is in column-major order. `None` when there are no vertices.
+    pub fn bounding_box(&self, transform: &[[f32; 4]; 4]) -> Option<Aabb> {
+        let mut bounds: Option<Aabb> = None;
+
+        for mesh in self.meshes.iter() {
+            let m = mesh_matrix(mesh, transform);
+            for v in mesh.vertices.iter() {
+                let p = m.transform_point(&Vector3::from_slice(&v.position));
+                match bounds {
+                    Some(ref mut a) => a.extend(&p),
+                    None => bounds = Some(Aabb::new(p.clone(), p)),
+                }
+            }
+        }
+
+        bounds
+    }
+
+    /// Sets `bounds` from the vertices of the meshes.
+    pub fn update_bounds(&mut self) {
+        self.bounds = self.bounding_box(&Matrix4::identity().as_column_slice());
+    }
+
+    /// False when `bounds` after the column-major `transform` is outside the frustum. True when
+    /// the bounds are not known.
+    pub fn intersects_frustum(&self, frustum: &Frustum, transform: &[[f32; 4]; 4]) -> bool {
+        match self.bounds {
+            Some(ref a) => {
+                let m = Matrix4::from_row_slice(*transform).transpose();
+                frustum.intersects_aabb(&a.transform(&m))
+            }
+            None => true,
+        }
+    }
+
+    /// The distance along the ray to the nearest triangle of the meshes which it hits, after the
+    /// mesh transforms and then `transform`, which is in column-major order.
+    pub fn intersect_ray(&self, ray: &Ray, transform: &[[f32; 4]; 4]) -> Option<f32> {
+        if let Some(ref a) = self.bounds {
+            let m = Matrix4::from_row_slice(*transform).transpose();
+            ray.intersect_aabb(&a.transform(&m))?;
+        }
+
+        let mut nearest: Option<f32> = None;
+
+        for mesh in self.meshes.iter() {
+            let m = mesh_matrix(mesh, transform);
+            let positions: Vec<Vector3> = mesh
+                .vertices
+                .iter()
+                .map(|v| m.transform_point(&Vector3::from_slice(&v.position)))
+                .collect();
+            // Meshes without indices are drawn as a triangle array.
+            let indices: Vec<usize> = if mesh.indices.is_empty() {
+                (0..positions.len()).collect()
+            } else {
+                mesh.indices.iter().map(|&i| i as usize).collect()
+            };
+
+            for tri in indices.chunks_exact(3) {
+                let (a, b, c) = match (
+                    positions.get(tri[0]),
+                    positions.get(tri[1]),
+                    positions.get(tri[2]),
+                ) {
+                    (Some(a), Some(b), Some(c)) => (a, b, c),
+                    _ => continue,
+                };
+                if let Some(t) = ray.intersect_triangle(a, b, c) {
+                    nearest = Some(nearest.map_or(t, |x| x.min(t)));
+                }
+            }
+        }
+
+        nearest
+    }
+
     pub fn gl_cleanup(&mut self) {
         for mesh in self.meshes.iter_mut() {
             mesh.gl_cleanup();
@@ -91,3 +206,12 @@ impl Drop for Model {
         self.gl_cleanup();
     }
 }
+
+/// The row-major matrix of the mesh transform and then the column-major `transform`.
+fn mesh_matrix(mesh: &Mesh, transform: &[[f32; 4]; 4]) -> Matrix4 {
+    let m = Matrix4::from_row_slice(*transform).transpose();
+    match mesh.transform {
+        Some(t) => m * Matrix4::from_row_slice(t).transpose(),
+        None => m,
+    }
+}
@@ -1,5 +1,8 @@
-use intro_3d::lib::{to_radians, Matrix4, Vector3};
+use intro_3d::lib::{to_radians, Matrix4, Ray, Vector3};
 
+use crate::camera_path::CameraMode;
+use crate::instances::Instances;
+use crate::lights::Lights;
 use crate::model::Model;
 
 pub struct PolygonContext {
@@ -13,8 +16,17 @@ pub struct PolygonContext {
     pub fovy: f32,
     pub znear: f32,
     pub zfar: f32,
+    pub aspect: f32,
+
+    pub camera_mode: CameraMode,
 
     pub models: Vec<Model>,
+
+    /// A single identity transform, bound for the scene objects which are not instanced, so that
+    /// the shaders can read the instance transform either way.
+    pub default_instances: Instances,
+
+    pub lights: Lights,
 }
 
 impl Default for PolygonContext {
@@ -56,8 +68,15 @@ impl PolygonContext {
             fovy,
             znear,
             zfar,
+            aspect,
+
+            camera_mode: CameraMode::Sync_Front,
 
             models: Vec::new(),
+
+            default_instances: Instances::default(),
+
+            lights: Lights::new(),
         };
 
         p.update_view_matrix();
@@ -76,10 +95,28 @@ impl PolygonContext {
     }
 
     pub fn update_projection_matrix(&mut self, aspect: f32) {
+        self.aspect = aspect;
         let a = Matrix4::new_perspective(aspect, to_radians(self.fovy), self.znear, self.zfar);
         self.projection_matrix = a.as_column_slice();
     }
 
+    /// The ray from the camera through a point of the view, in normalized device coordinates
+    /// from (-1.0, -1.0) at the bottom left to (1.0, 1.0) at the top right.
+    pub fn pick_ray(&self, ndc_x: f32, ndc_y: f32) -> Ray {
+        let front = self.view_front.normalize();
+        let right = front.cross(&self.view_up).normalize();
+        let up = right.cross(&front);
+
+        // The projection scales the view space x and y at the distance of 1.0 to the view.
+        let x = ndc_x / self.projection_matrix[0][0];
+        let y = ndc_y / self.projection_matrix[1][1];
+
+        Ray::new(
+            self.view_position.clone(),
+            &(&front + &(&right * x)) + &(&up * y),
+        )
+    }
+
     pub fn get_view_position(&self) -> &Vector3 {
         &self.view_position
     }
@@ -1,13 +1,21 @@
-use intro_3d::lib::{Matrix4, Vector3};
+use intro_3d::lib::{Frustum, Matrix4, Quaternion, Ray, Vector3};
+
+use gl;
+use gl::types::*;
 
 use crate::error::RuntimeError;
+use crate::error::RuntimeError::*;
 
 use crate::context_gfx::ContextGfx;
-use crate::model::ModelViewProjection;
-use crate::types::{BufferMapping, UniformMapping, ValueFloat, ValueVec3};
+use crate::instances::Instances;
+use crate::model::{Model, ModelViewProjection};
+use crate::types::{BufferMapping, UniformMapping, ValueFloat, ValueVec3, ValueVec4};
 
 pub struct PolygonScene {
     pub scene_objects: Vec<SceneObject>,
+    /// Indexes of the scene objects with each parent before its children, the order to update
+    /// the model matrices in. Set by `set_parents()`.
+    update_order: Vec<usize>,
 }
 
 pub struct SceneObject {
@@ -21,9 +29,31 @@ pub struct SceneObject {
     pub euler_rotation_var: ValueVec3,
     pub scale_var: ValueFloat,
 
+    /// Used instead of the Euler rotation when it is set.
+    pub quaternion_rotation: Option<Quaternion>,
+    pub quaternion_rotation_var: ValueVec4,
+
+    /// Index of the parent object in `PolygonScene.scene_objects`. The model matrix of the parent
+    /// is applied after the transform of this object.
+    pub parent_idx: Option<usize>,
+
+    /// Draw the model once for each instance transform, in one draw call.
+    pub instances: Option<Instances>,
+
+    /// Draw the object into the shadow maps of the lights.
+    pub casts_shadow: bool,
+
+    /// Skip drawing the object when the bounding box of its model is outside the view. Turn it
+    /// off when the vertex shader moves the vertices outside the model. Instanced objects are not
+    /// culled.
+    pub frustum_culling: bool,
+
     pub layout_to_vars: Vec<UniformMapping>,
     pub binding_to_buffers: Vec<BufferMapping>,
 
+    /// Bind the active uniforms of the model's meshes to sync vars by name.
+    pub bind_uniforms_by_name: bool,
+
     /// Model matrix to use when drawing the model retreived with `model_idx`
     /// from `PolygonContext.models`.
     pub model_matrix: [[f32; 4]; 4],
@@ -48,9 +78,22 @@ impl Default for SceneObject {
             euler_rotation_var: ValueVec3::Fixed(0.0, 0.0, 0.0),
             scale_var: ValueFloat::Fixed(1.0),
 
+            quaternion_rotation: None,
+            quaternion_rotation_var: ValueVec4::NOOP,
+
+            parent_idx: None,
+
+            instances: None,
+
+            casts_shadow: true,
+
+            frustum_culling: true,
+
             layout_to_vars: Vec::new(),
             binding_to_buffers: Vec::new(),
 
+            bind_uniforms_by_name: false,
+
             // identity matrix
             model_matrix: [
                 [1.0, 0.0, 0.0, 0.0],
@@ -66,22 +109,220 @@ impl PolygonScene {
     pub fn empty() -> PolygonScene {
         PolygonScene {
             scene_objects: Vec::new(),
+            update_order: Vec::new(),
+        }
+    }
+
+    /// Checks the `parent_idx` of the scene objects and orders the objects by their depth in the
+    /// hierarchy, so that each parent comes before its children. Call it after adding the scene
+    /// objects or changing their parents.
+    pub fn set_parents(&mut self) -> Result<(), RuntimeError> {
+        let n = self.scene_objects.len();
+        let mut depths: Vec<usize> = Vec::with_capacity(n);
+
+        for o in self.scene_objects.iter() {
+            let mut depth = 0;
+            let mut parent_idx = o.parent_idx;
+            while let Some(idx) = parent_idx {
+                if idx >= n {
+                    return Err(ParentIdxIsOutOfBounds);
+                }
+                depth += 1;
+                if depth > n {
+                    return Err(ParentsFormACycle);
+                }
+                parent_idx = self.scene_objects[idx].parent_idx;
+            }
+            depths.push(depth);
+        }
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by_key(|&idx| depths[idx]);
+        self.update_order = order;
+
+        Ok(())
+    }
+
+    /// Updates the model matrices in the order of `set_parents()`, so that the parents have their
+    /// world transform when the children are composed with it. When the objects have changed
+    /// since, the order is set again, or the index order is used if the parents are not valid.
+    pub fn update_model_matrices(&mut self) {
+        if self.update_order.len() != self.scene_objects.len() && self.set_parents().is_err() {
+            self.update_order = (0..self.scene_objects.len()).collect();
+        }
+        for &idx in self.update_order.iter() {
+            let parent_matrix = match self.scene_objects[idx].parent_idx {
+                Some(parent_idx) => self.scene_objects.get(parent_idx).map(|x| x.model_matrix),
+                None => None,
+            };
+            self.scene_objects[idx].update_model_matrix(parent_matrix.as_ref());
         }
     }
 
+    /// Draws the shadow maps of the lights, then the scene objects.
     pub fn draw(&self, context: &ContextGfx) -> Result<(), RuntimeError> {
+        context.polygon_context.lights.bind()?;
+
+        self.draw_shadow_maps(context)?;
+
+        self.draw_objects(
+            context,
+            false,
+            &context.polygon_context.view_matrix,
+            &context.polygon_context.projection_matrix,
+            &context.polygon_context.view_position.as_slice(),
+        )
+    }
+
+    /// Draws the depth of the objects which cast shadows into the shadow map of each light which
+    /// has one, with the shaders of the objects. The frame buffer and the viewport are restored
+    /// after.
+    ///
+    /// The texture units which the objects read the shadow maps on still hold the depth textures
+    /// from the last frame. They are unbound first, as a shader sampling the texture which is
+    /// attached to the bound frame buffer is a feedback loop.
+    fn draw_shadow_maps(&self, context: &ContextGfx) -> Result<(), RuntimeError> {
+        let lights = &context.polygon_context.lights.lights;
+        if lights.iter().all(|x| x.shadow_map.is_none()) {
+            return Ok(());
+        }
+
+        let mut framebuffer: GLint = 0;
+        let mut viewport: [GLint; 4] = [0; 4];
+        unsafe {
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut framebuffer);
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+
+            for o in self.scene_objects.iter() {
+                for item in o.binding_to_buffers.iter() {
+                    if let BufferMapping::ShadowMap(binding_idx, _) = *item {
+                        gl::ActiveTexture(gl::TEXTURE0 + u32::from(binding_idx));
+                        gl::BindTexture(gl::TEXTURE_2D, 0);
+                    }
+                }
+            }
+        }
+
+        let mut res = Ok(());
+        for light in lights.iter() {
+            if let Some(ref shadow_map) = light.shadow_map {
+                res = shadow_map.bind_for_drawing().and_then(|_| {
+                    self.draw_objects(
+                        context,
+                        true,
+                        &shadow_map.view_matrix,
+                        &shadow_map.projection_matrix,
+                        &light.position.as_slice(),
+                    )
+                });
+                if res.is_err() {
+                    break;
+                }
+            }
+        }
+
+        unsafe {
+            gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as GLuint);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+
+        res
+    }
+
+    /// The nearest scene object which the ray hits a triangle of, and the distance to it. The
+    /// instances of instanced objects are tested each.
+    pub fn intersect_ray(&self, ray: &Ray, models: &[Model]) -> Option<(usize, f32)> {
+        let mut nearest: Option<(usize, f32)> = None;
+
+        for (idx, o) in self.scene_objects.iter().enumerate() {
+            let model = match models.get(o.model_idx) {
+                Some(x) => x,
+                None => continue,
+            };
+
+            let hit = match o.instances {
+                Some(ref instances) => {
+                    let m = Matrix4::from_row_slice(o.model_matrix).transpose();
+                    instances
+                        .transforms
+                        .iter()
+                        .take(instances.count)
+                        .filter_map(|t| {
+                            let t =
+                                (&m * &Matrix4::from_row_slice(*t).transpose()).as_column_slice();
+                            model.intersect_ray(ray, &t)
+                        })
+                        .fold(None, |a: Option<f32>, t| Some(a.map_or(t, |x| x.min(t))))
+                }
+                None => model.intersect_ray(ray, &o.model_matrix),
+            };
+
+            nearest = match (nearest, hit) {
+                (Some((_, x)), Some(t)) if t < x => Some((idx, t)),
+                (None, Some(t)) => Some((idx, t)),
+                _ => nearest,
+            };
+        }
+
+        nearest
+    }
+
+    /// In the shadow pass, only the objects which cast shadows are drawn, and their buffer
+    /// mappings are not bound, so that a shadow map is not read while it is drawn.
+    ///
+    /// Objects outside the frustum of `projection * view` are skipped, which in the shadow pass
+    /// is the volume that the light casts shadows in.
+    fn draw_objects(
+        &self,
+        context: &ContextGfx,
+        shadow_pass: bool,
+        view: &[[f32; 4]; 4],
+        projection: &[[f32; 4]; 4],
+        view_position: &[f32; 3],
+    ) -> Result<(), RuntimeError> {
+        let frustum = Frustum::from_matrix(
+            &(Matrix4::from_row_slice(*projection).transpose()
+                * Matrix4::from_row_slice(*view).transpose()),
+        );
+
         for o in self.scene_objects.iter() {
+            if shadow_pass && !o.casts_shadow {
+                continue;
+            }
             if let Some(ref model) = context.polygon_context.models.get(o.model_idx) {
+                if o.frustum_culling
+                    && o.instances.is_none()
+                    && !model.intersects_frustum(&frustum, &o.model_matrix)
+                {
+                    continue;
+                }
+
+                let instances = match o.instances {
+                    Some(ref x) => x,
+                    None => &context.polygon_context.default_instances,
+                };
+                instances.bind()?;
+
+                let binding_to_buffers: &[BufferMapping] = if shadow_pass {
+                    &[]
+                } else {
+                    &o.binding_to_buffers
+                };
+
                 model.draw(
                     context,
                     &o.layout_to_vars,
-                    &o.binding_to_buffers,
+                    binding_to_buffers,
+                    o.bind_uniforms_by_name,
                     &ModelViewProjection {
                         model: o.model_matrix,
-                        view: context.polygon_context.view_matrix,
-                        projection: context.polygon_context.projection_matrix,
+                        view: *view,
+                        projection: *projection,
                     },
-                    &context.polygon_context.view_position.as_slice(),
+                    view_position,
+                    instances.count,
                 )?;
             }
         }
@@ -90,8 +331,20 @@ impl PolygonScene {
 }
 
 impl SceneObject {
-    pub fn update_model_matrix(&mut self) {
-        let a = Matrix4::new_homogeneous(&self.position, &self.euler_rotation, self.scale);
-        self.model_matrix = a.as_column_slice();
+    /// Sets the model matrix from the position, rotation and scale, composed with the model
+    /// matrix of the parent when there is one.
+    pub fn update_model_matrix(&mut self, parent_matrix: Option<&[[f32; 4]; 4]>) {
+        let a = match self.quaternion_rotation {
+            Some(ref q) => Matrix4::new_homogeneous_quaternion(&self.position, q, self.scale),
+            None => Matrix4::new_homogeneous(&self.position, &self.euler_rotation, self.scale),
+        };
+        self.model_matrix = match parent_matrix {
+            Some(p) => {
+                // The model matrices are column-major, Matrix4 is row-major.
+                let parent = Matrix4::from_row_slice(*p).transpose();
+                (parent * a).as_column_slice()
+            }
+            None => a.as_column_slice(),
+        };
     }
 }
@@ -0,0 +1,171 @@
+use std::time::{Duration, Instant};
+
+use gl;
+use gl::types::*;
+
+/// Max number of `Profile` draw ops measured in a frame.
+pub const PROFILE_EVENTS: usize = 10;
+/// Number of frames in flight. Query results are read back this many frames
+/// later, when the GPU has most likely finished with them, to avoid stalling
+/// the pipeline.
+pub const PROFILE_FRAMES: usize = 4;
+
+/// Measures the GPU time between `Profile` draw ops with timestamp queries.
+///
+/// A timestamp is recorded at the start of the frame and at each `Profile`
+/// event. The GPU time of an event is the time since the previous event (or the
+/// frame start), so a `Profile` op placed after a pass measures that pass.
+pub struct GpuProfiler {
+    frames: [ProfileFrame; PROFILE_FRAMES],
+    frame_idx: usize,
+    /// Events of the most recent frame whose queries have finished.
+    pub results: Vec<ProfileResult>,
+    /// Set when `results` was updated, cleared by the reader.
+    pub has_new_results: bool,
+}
+
+#[derive(Copy, Clone)]
+pub struct ProfileResult {
+    /// Index of the profile label, as in `DrawOp::Profile(label_idx)`.
+    pub label_idx: usize,
+    /// GPU time since the previous event, in milliseconds.
+    pub gpu_ms: f32,
+    /// CPU time since the frame start when the event was recorded, in
+    /// milliseconds.
+    pub cpu_ms: f32,
+}
+
+#[derive(Copy, Clone)]
+struct ProfileFrame {
+    /// Index 0 is the frame start timestamp, events follow from index 1.
+    queries: [GLuint; PROFILE_EVENTS + 1],
+    labels: [usize; PROFILE_EVENTS],
+    cpu_ms: [f32; PROFILE_EVENTS],
+    event_count: usize,
+    is_pending: bool,
+}
+
+impl Default for ProfileFrame {
+    fn default() -> ProfileFrame {
+        ProfileFrame {
+            queries: [0; PROFILE_EVENTS + 1],
+            labels: [0; PROFILE_EVENTS],
+            cpu_ms: [0.0; PROFILE_EVENTS],
+            event_count: 0,
+            is_pending: false,
+        }
+    }
+}
+
+impl Default for GpuProfiler {
+    fn default() -> GpuProfiler {
+        GpuProfiler::new()
+    }
+}
+
+impl GpuProfiler {
+    pub fn new() -> GpuProfiler {
+        GpuProfiler {
+            frames: [ProfileFrame::default(); PROFILE_FRAMES],
+            frame_idx: 0,
+            results: Vec::new(),
+            has_new_results: false,
+        }
+    }
+
+    /// Moves to the next frame slot, reads the results of the queries which
+    /// were issued in it the last time, and records the frame start timestamp.
+    pub fn frame_start(&mut self) {
+        self.frame_idx = (self.frame_idx + 1) % PROFILE_FRAMES;
+
+        if self.frames[self.frame_idx].is_pending {
+            self.read_results(self.frame_idx);
+        }
+
+        let frame = &mut self.frames[self.frame_idx];
+        frame.event_count = 0;
+        frame.is_pending = false;
+
+        unsafe {
+            if frame.queries[0] == 0 {
+                gl::GenQueries(frame.queries.len() as GLsizei, frame.queries.as_mut_ptr());
+            }
+            gl::QueryCounter(frame.queries[0], gl::TIMESTAMP);
+        }
+    }
+
+    pub fn frame_end(&mut self) {
+        let frame = &mut self.frames[self.frame_idx];
+        frame.is_pending = frame.event_count > 0;
+    }
+
+    pub fn event(&mut self, label_idx: usize, t_frame_start: &Instant) {
+        let frame = &mut self.frames[self.frame_idx];
+        if frame.event_count >= PROFILE_EVENTS || frame.queries[0] == 0 {
+            return;
+        }
+
+        let n = frame.event_count;
+        let t_delta: Duration = t_frame_start.elapsed();
+
+        frame.labels[n] = label_idx;
+        frame.cpu_ms[n] = duration_to_millis(t_delta);
+
+        unsafe {
+            gl::QueryCounter(frame.queries[n + 1], gl::TIMESTAMP);
+        }
+
+        frame.event_count += 1;
+    }
+
+    fn read_results(&mut self, frame_idx: usize) {
+        let frame = &mut self.frames[frame_idx];
+        frame.is_pending = false;
+
+        let n = frame.event_count;
+        let mut timestamps: [GLuint64; PROFILE_EVENTS + 1] = [0; PROFILE_EVENTS + 1];
+
+        unsafe {
+            // The last query finishes last. If it is not available yet, drop
+            // the frame instead of waiting for the GPU.
+            let mut available: GLint = 0;
+            gl::GetQueryObjectiv(frame.queries[n], gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                return;
+            }
+
+            for (i, t) in timestamps.iter_mut().enumerate().take(n + 1) {
+                gl::GetQueryObjectui64v(frame.queries[i], gl::QUERY_RESULT, t);
+            }
+        }
+
+        self.results.clear();
+        for i in 0..n {
+            let nanos = timestamps[i + 1].saturating_sub(timestamps[i]);
+            self.results.push(ProfileResult {
+                label_idx: frame.labels[i],
+                gpu_ms: (nanos as f32) / 1_000_000_f32,
+                cpu_ms: frame.cpu_ms[i],
+            });
+        }
+        self.has_new_results = true;
+    }
+
+    pub fn gl_cleanup(&mut self) {
+        for frame in self.frames.iter_mut() {
+            if frame.queries[0] != 0 {
+                unsafe {
+                    gl::DeleteQueries(frame.queries.len() as GLsizei, frame.queries.as_ptr());
+                }
+            }
+            *frame = ProfileFrame::default();
+        }
+    }
+}
+
+fn duration_to_millis(t: Duration) -> f32 {
+    // t as nanosec
+    let nanos: u64 = (t.as_secs() * 1_000_000_000) + u64::from(t.subsec_nanos());
+    // as millisec
+    (nanos as f32) / 1_000_000_f32
+}
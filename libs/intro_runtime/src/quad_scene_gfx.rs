@@ -14,7 +14,9 @@ use crate::error::RuntimeError;
 use crate::error::RuntimeError::*;
 use crate::shader::{compile_shader, link_program};
 use crate::shapes::*;
+use crate::sync_vars::SyncVars;
 use crate::types::*;
+use crate::uniforms::{set_uniform_mappings, uniform_mappings_by_name};
 use crate::ERR_MSG_LEN;
 
 pub struct QuadSceneGfx {
@@ -28,6 +30,10 @@ pub struct QuadSceneGfx {
     pub layout_to_vars: Vec<UniformMapping>,
     /// Maps uniform layout binding to frame buffer index
     pub binding_to_buffers: Vec<BufferMapping>,
+    /// Bind active uniforms to sync vars by matching their names
+    pub bind_uniforms_by_name: bool,
+    /// Mappings found by name after the shader program was linked
+    pub named_to_vars: Vec<UniformMapping>,
     /// The OpenGL object.
     pub quad: Option<Quad>,
 }
@@ -46,10 +52,23 @@ impl QuadSceneGfx {
             frag_src_idx,
             layout_to_vars: Vec::new(),
             binding_to_buffers: Vec::new(),
+            bind_uniforms_by_name: false,
+            named_to_vars: Vec::new(),
             quad: None,
         }
     }
 
+    /// Finds the uniforms of the shader program which can be bound to sync
+    /// vars by name. Call again when the program is recompiled.
+    pub fn update_named_to_vars(&mut self, sync_vars: &SyncVars) {
+        self.named_to_vars = match self.quad {
+            Some(ref quad) if self.bind_uniforms_by_name => {
+                uniform_mappings_by_name(quad.program, sync_vars)
+            }
+            _ => Vec::new(),
+        };
+    }
+
     pub fn create_quad(
         &mut self,
         vert_src: &str,
@@ -67,47 +86,10 @@ impl QuadSceneGfx {
                     // Use shader
                     gl::UseProgram(quad.program);
 
-                    // Mapping sync var indexes to uniform layout indexes
-                    for item in self.layout_to_vars.iter() {
-                        use crate::types::UniformMapping::*;
-                        match *item {
-                            NOOP => {}
-
-                            Float(layout_idx, var_idx) => {
-                                gl::Uniform1f(
-                                    i32::from(layout_idx),
-                                    context.sync_vars.get_index(var_idx as usize)? as f32,
-                                );
-                            }
-
-                            Vec2(layout_idx, var1, var2) => {
-                                gl::Uniform2f(
-                                    i32::from(layout_idx),
-                                    context.sync_vars.get_index(var1 as usize)? as f32,
-                                    context.sync_vars.get_index(var2 as usize)? as f32,
-                                );
-                            }
-
-                            Vec3(layout_idx, var1, var2, var3) => {
-                                gl::Uniform3f(
-                                    i32::from(layout_idx),
-                                    context.sync_vars.get_index(var1 as usize)? as f32,
-                                    context.sync_vars.get_index(var2 as usize)? as f32,
-                                    context.sync_vars.get_index(var3 as usize)? as f32,
-                                );
-                            }
-
-                            Vec4(layout_idx, var1, var2, var3, var4) => {
-                                gl::Uniform4f(
-                                    i32::from(layout_idx),
-                                    context.sync_vars.get_index(var1 as usize)? as f32,
-                                    context.sync_vars.get_index(var2 as usize)? as f32,
-                                    context.sync_vars.get_index(var3 as usize)? as f32,
-                                    context.sync_vars.get_index(var4 as usize)? as f32,
-                                );
-                            }
-                        }
-                    }
+                    // Uniforms bound by name first, so that explicit layout
+                    // mappings can override them.
+                    set_uniform_mappings(context, &self.named_to_vars)?;
+                    set_uniform_mappings(context, &self.layout_to_vars)?;
 
                     // Bind a buffer as texture
                     for item in self.binding_to_buffers.iter() {
@@ -135,6 +117,14 @@ impl QuadSceneGfx {
                                     return Err(TextureBindingIdxDoesntExist);
                                 }
                             }
+
+                            ShadowMap(binding_idx, light_idx) => {
+                                context
+                                    .polygon_context
+                                    .lights
+                                    .get_shadow_map(light_idx as usize)?
+                                    .bind_as_texture(binding_idx)?;
+                            }
                         }
                     }
 
@@ -0,0 +1,216 @@
+use std::ptr;
+
+use gl;
+use gl::types::*;
+
+use intro_3d::lib::{to_radians, Matrix4, Vector3};
+
+use crate::error::RuntimeError;
+use crate::error::RuntimeError::*;
+use crate::lights::{Light, LightType};
+
+/// The depth of the scene seen from a light, which the object shaders compare against to find
+/// what is in shadow.
+///
+/// Shaders read the light space matrix with `UniformMapping::LightSpaceMatrix` and the depth
+/// texture with `BufferMapping::ShadowMap`. Outside the map the depth is 1.0, so that it is not
+/// in shadow.
+pub struct ShadowMap {
+    /// Width and height of the depth texture.
+    pub resolution: i32,
+    /// Depth bias for the shaders to subtract before comparing, against shadow acne.
+    pub bias: f32,
+    /// Half of the width and height of the box which a directional light casts shadows in.
+    pub extent: f32,
+    pub znear: f32,
+    pub zfar: f32,
+
+    /// Column-major view and projection matrices of the light.
+    pub view_matrix: [[f32; 4]; 4],
+    pub projection_matrix: [[f32; 4]; 4],
+    /// Column-major `projection * view`, which takes a world position to the light clip space.
+    pub light_space_matrix: [[f32; 4]; 4],
+
+    fbo: Option<GLuint>,
+    depth_texture: Option<GLuint>,
+}
+
+impl Default for ShadowMap {
+    fn default() -> ShadowMap {
+        ShadowMap::new(1024, 0.005)
+    }
+}
+
+impl ShadowMap {
+    pub fn new(resolution: i32, bias: f32) -> ShadowMap {
+        let identity = Matrix4::identity().as_column_slice();
+        ShadowMap {
+            resolution,
+            bias,
+            extent: 20.0,
+            znear: 0.1,
+            zfar: 100.0,
+
+            view_matrix: identity,
+            projection_matrix: identity,
+            light_space_matrix: identity,
+
+            fbo: None,
+            depth_texture: None,
+        }
+    }
+
+    pub fn create_buffer(&mut self) -> Result<(), RuntimeError> {
+        self.gl_cleanup();
+
+        let mut fbo: GLuint = 0;
+        let mut depth_texture: GLuint = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut depth_texture);
+            gl::BindTexture(gl::TEXTURE_2D, depth_texture);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::DEPTH_COMPONENT24 as i32,
+                self.resolution,
+                self.resolution,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_S,
+                gl::CLAMP_TO_BORDER as i32,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_WRAP_T,
+                gl::CLAMP_TO_BORDER as i32,
+            );
+            let border = [1.0_f32; 4];
+            gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::TEXTURE_2D,
+                depth_texture,
+                0,
+            );
+            // Only depth, there is no color attachment.
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+
+            let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            self.fbo = Some(fbo);
+            self.depth_texture = Some(depth_texture);
+
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                return Err(FrameBufferIsNotComplete);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the matrices from the current position and direction of the light.
+    ///
+    /// A directional light casts shadows in a box `extent` wide to each side, from its position
+    /// in its direction. A spot light uses a perspective which covers its outer cutoff angle.
+    /// Point lights are not supported.
+    ///
+    /// Expects `extent > 0` and `0 < znear < zfar`, which are checked when the light is built.
+    /// The previous matrices are kept while the direction is zero, such as from tracks without
+    /// keys.
+    pub fn update_matrices(&mut self, light: &Light) {
+        if light.direction.norm() == 0.0 {
+            return;
+        }
+        let direction = light.direction.normalize();
+
+        // Avoid an up vector which is parallel with the direction.
+        let up = if direction.y.abs() > 0.99 {
+            Vector3::new(0.0, 0.0, 1.0)
+        } else {
+            Vector3::new(0.0, 1.0, 0.0)
+        };
+
+        let view = Matrix4::look_at_rh(&light.position, &{ &light.position + &direction }, &up);
+
+        let projection = match light.light_type {
+            LightType::Spot => {
+                let fovy = (2.0 * to_radians(light.outer_cutoff_angle)).min(to_radians(170.0));
+                Matrix4::new_perspective(1.0, fovy, self.znear, self.zfar)
+            }
+            LightType::Directional | LightType::Point => Matrix4::new_orthographic(
+                -self.extent,
+                self.extent,
+                -self.extent,
+                self.extent,
+                self.znear,
+                self.zfar,
+            ),
+        };
+
+        self.view_matrix = view.as_column_slice();
+        self.projection_matrix = projection.as_column_slice();
+        self.light_space_matrix = (projection * view).as_column_slice();
+    }
+
+    /// Binds the depth frame buffer and clears it. The viewport is set to the resolution of the
+    /// map, the caller restores it.
+    pub fn bind_for_drawing(&self) -> Result<(), RuntimeError> {
+        match self.fbo {
+            Some(fbo) => unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+                gl::Viewport(0, 0, self.resolution, self.resolution);
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            },
+            None => return Err(NoFbo),
+        }
+        Ok(())
+    }
+
+    pub fn bind_as_texture(&self, binding_idx: u8) -> Result<(), RuntimeError> {
+        if binding_idx > gl::MAX_COMBINED_TEXTURE_IMAGE_UNITS as u8 {
+            return Err(TextureBindingIdxIsOverTheHardwareLimit);
+        }
+        match self.depth_texture {
+            Some(texture) => unsafe {
+                gl::ActiveTexture(gl::TEXTURE0 + u32::from(binding_idx));
+                gl::BindTexture(gl::TEXTURE_2D, texture);
+            },
+            None => return Err(NoFbo),
+        }
+        Ok(())
+    }
+
+    pub fn gl_cleanup(&mut self) {
+        if let Some(n) = self.fbo.take() {
+            unsafe {
+                gl::DeleteFramebuffers(1, &n);
+            }
+        }
+        if let Some(n) = self.depth_texture.take() {
+            unsafe {
+                gl::DeleteTextures(1, &n);
+            }
+        }
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        self.gl_cleanup();
+    }
+}
@@ -1,9 +1,11 @@
+use crate::types::Vertex;
+
 pub static QUAD_VERTICES: [f32; 16] = [
     // pos: x, y, tex: u, v
     -1.0, -1.0, 0.0, 0.0, -1.0, 1.0, 0.0, 1.0, 1.0, -1.0, 1.0, 0.0, 1.0, 1.0, 1.0, 1.0,
 ];
 
-// FIXME verify tex coords and normals
+// The faces wind counter-clockwise seen from the outside, the normals point out.
 pub static CUBE_VERTICES: [[f32; 3 + 3 + 2]; 4 * 6] = [
     // position x,y,z,     normal x,y,z,       tex coord x,y
     // front
@@ -47,3 +49,353 @@ pub static CUBE_ELEMENTS: [u32; 3 * 2 * 6] = [
     16, 17, 18, 18, 19, 16, // right
     20, 21, 22, 22, 23, 20,
 ];
+
+/// The cube of `CUBE_VERTICES`, two units wide, centered on the origin.
+pub fn cube() -> (Vec<Vertex>, Vec<u32>) {
+    let vertices: Vec<Vertex> = CUBE_VERTICES
+        .iter()
+        .map(|v| Vertex {
+            position: [v[0], v[1], v[2]],
+            normal: [v[3], v[4], v[5]],
+            texcoords: [v[6], v[7]],
+            tangent: [0.0, 0.0, 0.0],
+            bitangent: [0.0, 0.0, 0.0],
+        })
+        .collect();
+
+    (vertices, CUBE_ELEMENTS.to_vec())
+}
+
+// === Parametric shapes =======================================================
+//
+// The faces wind counter-clockwise seen from the outside, with the normals pointing out. The
+// texcoords go from 0.0 to 1.0 around and along each surface, and the seams have their own
+// vertices so that the texture doesn't wrap back.
+
+/// A sphere of quads between meridians and parallels. `segments` is the number around the
+/// equator (at least 3), `rings` is the number from pole to pole (at least 2).
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> (Vec<Vertex>, Vec<u32>) {
+    use std::f32::consts::PI;
+
+    parametric_grid(segments.max(3), rings.max(2), |u, v| {
+        let (lon, lat) = (2.0 * PI * u, PI * (v - 0.5));
+        let n = [lat.cos() * lon.sin(), lat.sin(), lat.cos() * lon.cos()];
+        (scale(&n, radius), n)
+    })
+}
+
+/// A sphere from a subdivided icosahedron, for an even distribution of triangles. Each
+/// subdivision splits every triangle into four, up to 7 times.
+pub fn icosphere(radius: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    use std::collections::HashMap;
+    use std::f32::consts::PI;
+
+    let t = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    let mut points: Vec<[f32; 3]> = [
+        [-1.0, t, 0.0],
+        [1.0, t, 0.0],
+        [-1.0, -t, 0.0],
+        [1.0, -t, 0.0],
+        [0.0, -1.0, t],
+        [0.0, 1.0, t],
+        [0.0, -1.0, -t],
+        [0.0, 1.0, -t],
+        [t, 0.0, -1.0],
+        [t, 0.0, 1.0],
+        [-t, 0.0, -1.0],
+        [-t, 0.0, 1.0],
+    ]
+    .iter()
+    .map(normalize)
+    .collect();
+
+    let mut faces: Vec<[u32; 3]> = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions.min(7) {
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32, points: &mut Vec<[f32; 3]>| -> u32 {
+            let key = if a < b { (a, b) } else { (b, a) };
+            *midpoints.entry(key).or_insert_with(|| {
+                let (pa, pb) = (points[a as usize], points[b as usize]);
+                points.push(normalize(&[pa[0] + pb[0], pa[1] + pb[1], pa[2] + pb[2]]));
+                points.len() as u32 - 1
+            })
+        };
+
+        let mut new_faces: Vec<[u32; 3]> = Vec::with_capacity(faces.len() * 4);
+        for f in faces.iter() {
+            let ab = midpoint(f[0], f[1], &mut points);
+            let bc = midpoint(f[1], f[2], &mut points);
+            let ca = midpoint(f[2], f[0], &mut points);
+            new_faces.push([f[0], ab, ca]);
+            new_faces.push([f[1], bc, ab]);
+            new_faces.push([f[2], ca, bc]);
+            new_faces.push([ab, bc, ca]);
+        }
+        faces = new_faces;
+    }
+
+    let mut vertices: Vec<Vertex> = points
+        .iter()
+        .map(|n| {
+            let u = 0.5 + n[0].atan2(n[2]) / (2.0 * PI);
+            let v = 0.5 + n[1].asin() / PI;
+            vertex(scale(n, radius), *n, [u, v])
+        })
+        .collect();
+
+    // Triangles across the seam at u = 0.0 get copies of their vertices at u + 1.0. Vertices on
+    // the poles get a copy for each triangle, with the u in the middle of the other two.
+    let is_pole = |v: &Vertex| v.normal[0].abs() < 1e-6 && v.normal[2].abs() < 1e-6;
+    let mut seam_copies: HashMap<u32, u32> = HashMap::new();
+    let mut indices: Vec<u32> = Vec::with_capacity(faces.len() * 3);
+    for f in faces.iter() {
+        let max_u = f
+            .iter()
+            .map(|&i| &vertices[i as usize])
+            .filter(|v| !is_pole(v))
+            .map(|v| v.texcoords[0])
+            .fold(0.0, f32::max);
+
+        let mut tri = [0_u32; 3];
+        for (k, &i) in f.iter().enumerate() {
+            let v = &vertices[i as usize];
+            tri[k] = if !is_pole(v) && max_u - v.texcoords[0] > 0.5 {
+                *seam_copies.entry(i).or_insert_with(|| {
+                    let mut copy = vertices[i as usize].clone();
+                    copy.texcoords[0] += 1.0;
+                    vertices.push(copy);
+                    vertices.len() as u32 - 1
+                })
+            } else {
+                i
+            };
+        }
+
+        for k in 0..3 {
+            if is_pole(&vertices[tri[k] as usize]) {
+                let (a, b) = (tri[(k + 1) % 3] as usize, tri[(k + 2) % 3] as usize);
+                let mut copy = vertices[tri[k] as usize].clone();
+                copy.texcoords[0] = 0.5 * (vertices[a].texcoords[0] + vertices[b].texcoords[0]);
+                vertices.push(copy);
+                tri[k] = vertices.len() as u32 - 1;
+            }
+        }
+
+        indices.extend_from_slice(&tri);
+    }
+
+    (vertices, indices)
+}
+
+/// A flat grid on the XZ plane facing up, centered on the origin.
+pub fn plane(width: f32, depth: f32, segments_x: u32, segments_z: u32) -> (Vec<Vertex>, Vec<u32>) {
+    parametric_grid(segments_x.max(1), segments_z.max(1), |u, v| {
+        ([width * (u - 0.5), 0.0, depth * (0.5 - v)], [0.0, 1.0, 0.0])
+    })
+}
+
+/// A ring around the Y axis. `segments` is the number around the ring, `sides` is the number
+/// around the tube, at least 3 each.
+pub fn torus(
+    major_radius: f32,
+    minor_radius: f32,
+    segments: u32,
+    sides: u32,
+) -> (Vec<Vertex>, Vec<u32>) {
+    use std::f32::consts::PI;
+
+    parametric_grid(segments.max(3), sides.max(3), |u, v| {
+        let (a, b) = (2.0 * PI * u, 2.0 * PI * v);
+        let n = [b.cos() * a.sin(), b.sin(), b.cos() * a.cos()];
+        let c = [major_radius * a.sin(), 0.0, major_radius * a.cos()];
+        (add(&c, &scale(&n, minor_radius)), n)
+    })
+}
+
+/// A capped cylinder along the Y axis, centered on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    use std::f32::consts::PI;
+
+    let segments = segments.max(3);
+    let (mut vertices, mut indices) = parametric_grid(segments, 1, |u, v| {
+        let a = 2.0 * PI * u;
+        let n = [a.sin(), 0.0, a.cos()];
+        ([radius * n[0], height * (v - 0.5), radius * n[2]], n)
+    });
+    add_cap(
+        &mut vertices,
+        &mut indices,
+        radius,
+        0.5 * height,
+        segments,
+        true,
+    );
+    add_cap(
+        &mut vertices,
+        &mut indices,
+        radius,
+        -0.5 * height,
+        segments,
+        false,
+    );
+
+    (vertices, indices)
+}
+
+/// A cone along the Y axis with the base cap, centered on the origin and pointing up.
+pub fn cone(radius: f32, height: f32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+    use std::f32::consts::PI;
+
+    let segments = segments.max(3);
+    let (mut vertices, mut indices) = parametric_grid(segments, 1, |u, v| {
+        let a = 2.0 * PI * u;
+        let r = radius * (1.0 - v);
+        let n = normalize(&[height * a.sin(), radius, height * a.cos()]);
+        ([r * a.sin(), height * (v - 0.5), r * a.cos()], n)
+    });
+    add_cap(
+        &mut vertices,
+        &mut indices,
+        radius,
+        -0.5 * height,
+        segments,
+        false,
+    );
+
+    (vertices, indices)
+}
+
+/// Vertices on a `(cols + 1) * (rows + 1)` grid of the `f(u, v)` surface, which gives the
+/// position and normal. The quads are wound so that the derivative along u crossed with the
+/// derivative along v points to the outside. Triangles which collapse to a point, such as at the
+/// poles, are left out.
+fn parametric_grid<F>(cols: u32, rows: u32, f: F) -> (Vec<Vertex>, Vec<u32>)
+where
+    F: Fn(f32, f32) -> ([f32; 3], [f32; 3]),
+{
+    let mut vertices: Vec<Vertex> = Vec::with_capacity(((cols + 1) * (rows + 1)) as usize);
+    for j in 0..=rows {
+        for i in 0..=cols {
+            let (u, v) = (i as f32 / cols as f32, j as f32 / rows as f32);
+            let (position, normal) = f(u, v);
+            vertices.push(vertex(position, normal, [u, v]));
+        }
+    }
+
+    let mut indices: Vec<u32> = Vec::with_capacity((cols * rows * 6) as usize);
+    let idx = |i: u32, j: u32| j * (cols + 1) + i;
+    for j in 0..rows {
+        for i in 0..cols {
+            let (a, b, c, d) = (idx(i, j), idx(i + 1, j), idx(i + 1, j + 1), idx(i, j + 1));
+            for tri in [[a, b, c], [a, c, d]].iter() {
+                if !is_degenerate(&vertices, tri) {
+                    indices.extend_from_slice(tri);
+                }
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// Adds a disc at `y` facing up or down, as a fan around a center vertex.
+fn add_cap(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    radius: f32,
+    y: f32,
+    segments: u32,
+    facing_up: bool,
+) {
+    use std::f32::consts::PI;
+
+    let normal = [0.0, if facing_up { 1.0 } else { -1.0 }, 0.0];
+    let center = vertices.len() as u32;
+    vertices.push(vertex([0.0, y, 0.0], normal, [0.5, 0.5]));
+
+    for i in 0..=segments {
+        let a = 2.0 * PI * i as f32 / segments as f32;
+        let (s, c) = (a.sin(), a.cos());
+        let v = if facing_up {
+            0.5 - 0.5 * c
+        } else {
+            0.5 + 0.5 * c
+        };
+        vertices.push(vertex(
+            [radius * s, y, radius * c],
+            normal,
+            [0.5 + 0.5 * s, v],
+        ));
+    }
+
+    for i in 0..segments {
+        let (a, b) = (center + 1 + i, center + 2 + i);
+        if facing_up {
+            indices.extend_from_slice(&[center, a, b]);
+        } else {
+            indices.extend_from_slice(&[center, b, a]);
+        }
+    }
+}
+
+fn vertex(position: [f32; 3], normal: [f32; 3], texcoords: [f32; 2]) -> Vertex {
+    Vertex {
+        position,
+        normal,
+        texcoords,
+        tangent: [0.0; 3],
+        bitangent: [0.0; 3],
+    }
+}
+
+fn is_degenerate(vertices: &[Vertex], tri: &[u32; 3]) -> bool {
+    let p = |i: u32| vertices[i as usize].position;
+    let (a, b, c) = (p(tri[0]), p(tri[1]), p(tri[2]));
+    let e1 = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let e2 = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        e1[1] * e2[2] - e1[2] * e2[1],
+        e1[2] * e2[0] - e1[0] * e2[2],
+        e1[0] * e2[1] - e1[1] * e2[0],
+    ];
+    n[0] * n[0] + n[1] * n[1] + n[2] * n[2] < 1e-12
+}
+
+fn add(a: &[f32; 3], b: &[f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn scale(a: &[f32; 3], s: f32) -> [f32; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn normalize(a: &[f32; 3]) -> [f32; 3] {
+    let len = (a[0] * a[0] + a[1] * a[1] + a[2] * a[2]).sqrt();
+    if len > 0.0 {
+        scale(a, 1.0 / len)
+    } else {
+        *a
+    }
+}
@@ -1,3 +1,5 @@
+use std::str;
+
 use crate::error::RuntimeError::{self, *};
 //use crate::VAR_NUM;
 
@@ -14,8 +16,8 @@ pub struct SyncTrack {
 
 impl Default for SyncVars {
     fn default() -> SyncVars {
-        // 31 tracks for 31 enum variants.
-        SyncVars::new(31)
+        // One track for each builtin enum variant.
+        SyncVars::new(BUILTIN_NAMES.len())
     }
 }
 
@@ -23,8 +25,6 @@ impl SyncVars {
     pub fn new(tracks_count: usize) -> SyncVars {
         let mut tracks: Vec<SyncTrack> = Vec::new();
 
-        // FIXME set the name of builtin tracks
-
         for _ in 0..=tracks_count {
             tracks.push(SyncTrack {
                 name: [0; 64],
@@ -32,7 +32,15 @@ impl SyncVars {
             });
         }
 
-        SyncVars { tracks }
+        let mut sync_vars = SyncVars { tracks };
+
+        for (idx, name) in BUILTIN_NAMES.iter().enumerate() {
+            if idx < sync_vars.tracks.len() {
+                sync_vars.tracks[idx].set_name(name);
+            }
+        }
+
+        sync_vars
     }
 
     pub fn add_tracks_up_to(&mut self, tracks_count: usize) {
@@ -65,6 +73,76 @@ impl SyncVars {
         }
     }
 
+    pub fn set_name(&mut self, idx: usize, name: &str) -> Result<(), RuntimeError> {
+        if self.tracks.len() > idx {
+            self.tracks[idx].set_name(name);
+            Ok(())
+        } else {
+            Err(VarIdxIsOutOfBounds)
+        }
+    }
+
+    /// Index of the first track with the given name.
+    pub fn find_index(&self, name: &str) -> Option<usize> {
+        if name.is_empty() {
+            return None;
+        }
+        self.tracks.iter().position(|t| t.get_name() == name)
+    }
+
+    /// Resolves a shader uniform name to sync var indexes, one for each
+    /// component of a float, vec2, vec3 or vec4 uniform.
+    ///
+    /// A `float` uniform matches a track by its exact name, such as `Time`, or
+    /// by replacing the last underscore with `#`, so that `cube1_scale`
+    /// matches `cube1#scale`.
+    ///
+    /// Vector uniforms match the component tracks of the name:
+    ///
+    /// - `cube1_pos` matches `cube1#pos.x`, `cube1#pos.y`, ...
+    /// - `pos` matches `pos.x`, `pos.y`, ...
+    /// - `Light_Pos` matches `Light_Pos_X`, `Light_Pos_Y`, ...
+    /// - `Window` as a `vec2` matches `Window_Width`, `Window_Height`
+    pub fn find_uniform_indexes(&self, name: &str, components: usize) -> Option<Vec<usize>> {
+        let hashed = name
+            .rfind('_')
+            .map(|n| format!("{}#{}", &name[..n], &name[n + 1..]));
+
+        if components == 1 {
+            if let Some(idx) = self.find_index(name) {
+                return Some(vec![idx]);
+            }
+            return hashed.and_then(|h| self.find_index(&h).map(|idx| vec![idx]));
+        }
+
+        let mut candidates: Vec<Vec<String>> = Vec::new();
+
+        let lower = ["x", "y", "z", "w"];
+        let upper = ["X", "Y", "Z", "W"];
+
+        if let Some(ref h) = hashed {
+            candidates.push(lower.iter().map(|c| format!("{}.{}", h, c)).collect());
+        }
+        candidates.push(lower.iter().map(|c| format!("{}.{}", name, c)).collect());
+        candidates.push(upper.iter().map(|c| format!("{}_{}", name, c)).collect());
+        if components == 2 {
+            candidates.push(vec![format!("{}_Width", name), format!("{}_Height", name)]);
+        }
+
+        for names in candidates.iter() {
+            let indexes: Vec<usize> = names
+                .iter()
+                .take(components)
+                .filter_map(|n| self.find_index(n))
+                .collect();
+            if indexes.len() == components {
+                return Some(indexes);
+            }
+        }
+
+        None
+    }
+
     pub fn set_builtin(&mut self, name: BuiltIn, value: f64) {
         let idx = builtin_to_idx(name);
         self.tracks[idx].value = value;
@@ -87,6 +165,24 @@ impl SyncVars {
     */
 }
 
+impl SyncTrack {
+    pub fn set_name(&mut self, name: &str) {
+        self.name = [0; 64];
+        for (n, b) in name.as_bytes().iter().take(self.name.len()).enumerate() {
+            self.name[n] = *b;
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(self.name.len());
+        str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+}
+
 pub fn builtin_to_idx(name: BuiltIn) -> usize {
     use self::BuiltIn::*;
     match name {
@@ -132,12 +228,66 @@ pub fn builtin_to_idx(name: BuiltIn) -> usize {
         Light_Quadratic_Falloff => 29,
         Light_Cutoff_Angle => 30,
 
+        Block_Time => 31,
+        Block_Progress => 32,
+        Block_Fade => 33,
+
+        Beat => 34,
+        Bar => 35,
+        Beat_Phase => 36,
+
+        Frame_Index => 37,
+        Delta_Time => 38,
+
         // first n is 0
-        Custom(n) => 31 + n,
+        Custom(n) => 39 + n,
     }
 }
 
-// NOTE remember to update SyncVars::default() when adding more enum variants.
+/// Track names of the builtin variants, in the order of their indexes.
+pub const BUILTIN_NAMES: [&str; 39] = [
+    "Time",
+    "Window_Width",
+    "Window_Height",
+    "Screen_Width",
+    "Screen_Height",
+    "Camera_Pos_X",
+    "Camera_Pos_Y",
+    "Camera_Pos_Z",
+    "Camera_Front_X",
+    "Camera_Front_Y",
+    "Camera_Front_Z",
+    "Camera_Up_X",
+    "Camera_Up_Y",
+    "Camera_Up_Z",
+    "Camera_LookAt_X",
+    "Camera_LookAt_Y",
+    "Camera_LookAt_Z",
+    "Fovy",
+    "Znear",
+    "Zfar",
+    "Light_Pos_X",
+    "Light_Pos_Y",
+    "Light_Pos_Z",
+    "Light_Dir_X",
+    "Light_Dir_Y",
+    "Light_Dir_Z",
+    "Light_Strength",
+    "Light_Constant_Falloff",
+    "Light_Linear_Falloff",
+    "Light_Quadratic_Falloff",
+    "Light_Cutoff_Angle",
+    "Block_Time",
+    "Block_Progress",
+    "Block_Fade",
+    "Beat",
+    "Bar",
+    "Beat_Phase",
+    "Frame_Index",
+    "Delta_Time",
+];
+
+// NOTE remember to update BUILTIN_NAMES when adding more enum variants.
 
 pub enum BuiltIn {
     Time,
@@ -182,5 +332,24 @@ pub enum BuiltIn {
     Light_Quadratic_Falloff,
     Light_Cutoff_Angle,
 
+    /// Seconds since the start of the scene block being drawn.
+    Block_Time,
+    /// The local time of the scene block being drawn, from 0.0 to 1.0.
+    Block_Progress,
+    /// The visibility of the fading scene block in a transition, from 0.0 to 1.0.
+    Block_Fade,
+
+    /// The number of whole beats since the start, from the bpm of the sync device.
+    Beat,
+    /// The number of whole bars since the start, four beats in a bar.
+    Bar,
+    /// The position within the current beat, from 0.0 to 1.0.
+    Beat_Phase,
+
+    /// The number of frames since the start.
+    Frame_Index,
+    /// Seconds since the start of the previous frame.
+    Delta_Time,
+
     Custom(usize),
 }
@@ -1,11 +1,41 @@
+use std::cmp::Ordering;
+
 pub struct Timeline {
     pub tracks: Vec<TimeTrack>,
+    /// Sub-timelines which the tracks can instantiate.
+    pub compositions: Vec<Composition>,
+    /// Named points in time, sorted by time.
+    pub markers: Vec<Marker>,
+    /// The preview cycles through this region while playing.
+    pub loop_region: Option<LoopRegion>,
+    index: Option<TimelineIndex>,
+    /// The blocks active at the time of the last update, which stay the same until
+    /// `active_until`.
+    active: Vec<ActiveBlockRef>,
+    active_scratch: Vec<ActiveBlockRef>,
+    active_from: f64,
+    active_until: f64,
+}
+
+pub struct Marker {
+    pub name: String,
+    pub time: f64,
+}
+
+#[derive(Copy, Clone)]
+pub struct LoopRegion {
+    pub start: f64,
+    pub end: f64,
 }
 
 impl Default for Timeline {
     fn default() -> Timeline {
         let mut track = TimeTrack {
             scene_blocks: Vec::new(),
+            instances: Vec::new(),
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         let mut draw_ops: Vec<DrawOp> = Vec::new();
@@ -17,6 +47,12 @@ impl Default for Timeline {
             start: 0.0,
             end: 60.0,
             draw_ops,
+            fade_in: 0.0,
+            fade_out: 0.0,
+            transition: Transition::Crossfade,
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         track.scene_blocks.push(scene);
@@ -30,66 +66,383 @@ impl Default for Timeline {
 
 impl Timeline {
     pub fn new() -> Timeline {
-        Timeline { tracks: Vec::new() }
-    }
-
-    pub fn draw_ops_at_time(&self, time: f64) -> Vec<DrawOp> {
-        use self::DrawOp::*;
-
-        let mut ops: Vec<DrawOp> = Vec::new();
-
-        // Always start by clearing the "RESULT_IMAGE" buffer.
-
-        ops.push(DrawOp::Target_Buffer(0));
-        ops.push(DrawOp::Clear(0, 0, 0, 0));
-
-        if !self.tracks.is_empty() {
-            for track in self.tracks.iter() {
-                for block in track.scene_blocks.iter() {
-                    if block.start <= time && block.end > time {
-                        for i in block.draw_ops.iter() {
-                            let o = match i {
-                                NOOP => NOOP,
-                                Draw_Quad_Scene(x) => Draw_Quad_Scene(*x),
-                                Draw_Poly_Scene(x) => Draw_Poly_Scene(*x),
-                                Clear(r, g, b, a) => Clear(*r, *g, *b, *a),
-                                Target_Buffer(x) => Target_Buffer(*x),
-                                Target_Buffer_Default => Target_Buffer_Default,
-                                Profile(x) => Profile(*x),
-                            };
-                            ops.push(o);
-                        }
-                    }
+        Timeline {
+            tracks: Vec::new(),
+            compositions: Vec::new(),
+            markers: Vec::new(),
+            loop_region: None,
+            index: None,
+            active: Vec::new(),
+            active_scratch: Vec::new(),
+            active_from: f64::INFINITY,
+            active_until: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Sorts the shown blocks of each track by their start time for `update_active_blocks()`.
+    /// Call it after changing the tracks, compositions or their flags.
+    pub fn build_index(&mut self) {
+        self.index = Some(TimelineIndex {
+            tracks: build_track_indexes(&self.tracks),
+            compositions: self
+                .compositions
+                .iter()
+                .map(|c| build_track_indexes(&c.tracks))
+                .collect(),
+        });
+        self.active.clear();
+        self.active_from = f64::INFINITY;
+        self.active_until = f64::NEG_INFINITY;
+    }
+
+    /// Updates the list of active blocks for the time. The list is only collected again when the
+    /// time moves out of the range where it was valid, and it is replaced only when the active
+    /// blocks have changed, which is the return value.
+    pub fn update_active_blocks(&mut self, time: f64) -> bool {
+        if self.index.is_none() {
+            self.build_index();
+        }
+
+        if self.active_from <= time && time < self.active_until {
+            return false;
+        }
+
+        let mut blocks = std::mem::take(&mut self.active_scratch);
+        blocks.clear();
+        let mut until = f64::INFINITY;
+        self.collect_active(None, TimeMap::IDENTITY, 0, time, &mut blocks, &mut until);
+
+        self.active_from = time;
+        self.active_until = until;
+
+        let changed = blocks != self.active;
+        if changed {
+            std::mem::swap(&mut self.active, &mut blocks);
+        }
+        self.active_scratch = blocks;
+        changed
+    }
+
+    /// The blocks which were active at the last `update_active_blocks()`, in the order of the
+    /// tracks. Composition instances are expanded in place, after the scene blocks of their track.
+    pub fn active_blocks(&self) -> &[ActiveBlockRef] {
+        &self.active
+    }
+
+    pub fn get_block(&self, block_ref: &ActiveBlockRef) -> &SceneBlock {
+        let tracks = match block_ref.composition_idx {
+            Some(idx) => &self.compositions[idx].tracks,
+            None => &self.tracks,
+        };
+        &tracks[block_ref.track_idx].scene_blocks[block_ref.block_idx]
+    }
+
+    /// Replaces the markers, sorting them by time.
+    pub fn set_markers(&mut self, mut markers: Vec<Marker>) {
+        markers.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+        self.markers = markers;
+    }
+
+    pub fn get_marker_time(&self, name: &str) -> Option<f64> {
+        self.markers.iter().find(|m| m.name == name).map(|m| m.time)
+    }
+
+    /// The time of the first marker after the time.
+    pub fn next_marker_time(&self, time: f64) -> Option<f64> {
+        self.markers
+            .iter()
+            .find(|m| m.time > time + MARKER_EPSILON)
+            .map(|m| m.time)
+    }
+
+    /// The time of the last marker before the time.
+    pub fn prev_marker_time(&self, time: f64) -> Option<f64> {
+        self.markers
+            .iter()
+            .rev()
+            .find(|m| m.time < time - MARKER_EPSILON)
+            .map(|m| m.time)
+    }
+
+    /// Collects the active blocks of the top level tracks or a composition, and lowers `until` to
+    /// the next time when a block starts or ends.
+    fn collect_active(
+        &self,
+        composition_idx: Option<usize>,
+        map: TimeMap,
+        depth: usize,
+        time: f64,
+        blocks: &mut Vec<ActiveBlockRef>,
+        until: &mut f64,
+    ) {
+        let index = match self.index {
+            Some(ref x) => x,
+            None => return,
+        };
+
+        let (tracks, track_indexes) = match composition_idx {
+            Some(idx) => match (self.compositions.get(idx), index.compositions.get(idx)) {
+                (Some(c), Some(i)) => (&c.tracks, i),
+                _ => return,
+            },
+            None => (&self.tracks, &index.tracks),
+        };
+
+        let local_time = map.local(time);
+
+        for (track_idx, (track, track_index)) in tracks.iter().zip(track_indexes.iter()).enumerate()
+        {
+            if !track_index.is_shown {
+                continue;
+            }
+
+            let scene_blocks = &track.scene_blocks;
+            let by_start = &track_index.by_start;
+
+            // Blocks which start before this have ended.
+            let first = by_start
+                .partition_point(|&i| scene_blocks[i].start < local_time - track_index.max_length);
+            let last = by_start.partition_point(|&i| scene_blocks[i].start <= local_time);
+
+            if let Some(&i) = by_start.get(last) {
+                *until = until.min(map.global(scene_blocks[i].start));
+            }
+
+            let track_first = blocks.len();
+            for &i in by_start[first..last].iter() {
+                let block = &scene_blocks[i];
+                if block.end > local_time {
+                    *until = until.min(map.global(block.end));
+                    blocks.push(ActiveBlockRef {
+                        composition_idx,
+                        track_idx,
+                        block_idx: i,
+                        time_scale: map.scale,
+                        time_offset: map.offset,
+                    });
                 }
             }
-        }
+            // Draw in the order of the track, not the start times.
+            blocks[track_first..].sort_unstable_by_key(|b| b.block_idx);
 
-        // The user must always render the final result to the framebuffer named
-        // "RESULT_IMAGE", which is implicitly created as the first item in the
-        // array `context.framebuffers`.
-        //
-        // Draw ops have an implicit final sequence: select the default
-        // framebuffer, clear with black, render a simple draw shader on a quad
-        // (scene index 0), reading from the "RESULT_IMAGE" framebuffer.
+            if depth >= MAX_COMPOSITION_DEPTH {
+                continue;
+            }
 
-        ops.push(DrawOp::Target_Buffer_Default);
-        ops.push(DrawOp::Clear(0, 0, 0, 0));
-        ops.push(DrawOp::Draw_Quad_Scene(0));
+            for instance in track.instances.iter() {
+                let start = map.global(instance.start);
+                if time < start {
+                    *until = until.min(start);
+                    continue;
+                }
+                if let Some(duration) = instance.duration {
+                    let end = map.global(instance.start + duration);
+                    if time >= end {
+                        continue;
+                    }
+                    *until = until.min(end);
+                }
 
-        ops
+                let instance_map = TimeMap {
+                    scale: map.scale * instance.time_scale,
+                    offset: (map.offset - instance.start) * instance.time_scale,
+                };
+                self.collect_active(
+                    Some(instance.composition_idx),
+                    instance_map,
+                    depth + 1,
+                    time,
+                    blocks,
+                    until,
+                );
+            }
+        }
     }
 }
 
+/// Compositions can instantiate other compositions up to this depth, which also stops a
+/// composition which instantiates itself.
+const MAX_COMPOSITION_DEPTH: usize = 8;
+
+/// Jumping to the next or previous marker skips markers closer than this, so that repeated jumps
+/// don't stay on the same marker.
+const MARKER_EPSILON: f64 = 0.01;
+
 pub struct TimeTrack {
     pub scene_blocks: Vec<SceneBlock>,
+    pub instances: Vec<CompositionInstance>,
+    pub enabled: bool,
+    pub muted: bool,
+    /// When a track is soloed, only the soloed tracks among its sibling tracks are drawn.
+    pub solo: bool,
+}
+
+impl TimeTrack {
+    pub fn is_shown(&self, any_solo: bool) -> bool {
+        self.enabled && !self.muted && (self.solo || !any_solo)
+    }
+}
+
+/// A sub-timeline which is defined once and placed on tracks with a `CompositionInstance`. The
+/// times of its blocks are local to the instance.
+pub struct Composition {
+    pub tracks: Vec<TimeTrack>,
+}
+
+/// Places a composition on a track.
+#[derive(Copy, Clone, Debug)]
+pub struct CompositionInstance {
+    pub composition_idx: usize,
+    pub start: f64,
+    /// Multiplies the time since `start` to get the local time of the composition.
+    pub time_scale: f64,
+    /// Clips the instance after this many seconds.
+    pub duration: Option<f64>,
+}
+
+/// Refers to a scene block which is active, with the mapping from the timeline's time to the
+/// local time of its composition.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ActiveBlockRef {
+    /// `None` for the top level tracks.
+    pub composition_idx: Option<usize>,
+    pub track_idx: usize,
+    pub block_idx: usize,
+    pub time_scale: f64,
+    pub time_offset: f64,
+}
+
+impl ActiveBlockRef {
+    pub fn local_time(&self, time: f64) -> f64 {
+        time * self.time_scale + self.time_offset
+    }
+}
+
+/// Maps the timeline's time to a local time as `time * scale + offset`.
+#[derive(Copy, Clone)]
+struct TimeMap {
+    scale: f64,
+    offset: f64,
+}
+
+impl TimeMap {
+    const IDENTITY: TimeMap = TimeMap {
+        scale: 1.0,
+        offset: 0.0,
+    };
+
+    fn local(&self, time: f64) -> f64 {
+        time * self.scale + self.offset
+    }
+
+    fn global(&self, local_time: f64) -> f64 {
+        (local_time - self.offset) / self.scale
+    }
+}
+
+struct TimelineIndex {
+    tracks: Vec<TrackIndex>,
+    compositions: Vec<Vec<TrackIndex>>,
+}
+
+/// The shown blocks of a track sorted by start time. A block which starts more than `max_length`
+/// before a time has ended by then, so only a short range of the sorted blocks has to be checked.
+struct TrackIndex {
+    by_start: Vec<usize>,
+    max_length: f64,
+    is_shown: bool,
+}
+
+fn build_track_indexes(tracks: &[TimeTrack]) -> Vec<TrackIndex> {
+    let any_solo = tracks.iter().any(|t| t.solo);
+    tracks
+        .iter()
+        .map(|t| TrackIndex::new(t, t.is_shown(any_solo)))
+        .collect()
+}
+
+impl TrackIndex {
+    fn new(track: &TimeTrack, is_shown: bool) -> TrackIndex {
+        let blocks = &track.scene_blocks;
+        let any_solo = blocks.iter().any(|b| b.solo);
+        let mut by_start: Vec<usize> = (0..blocks.len())
+            .filter(|&i| blocks[i].is_shown(any_solo))
+            .collect();
+        by_start.sort_by(|&a, &b| {
+            blocks[a]
+                .start
+                .partial_cmp(&blocks[b].start)
+                .unwrap_or(Ordering::Equal)
+        });
+        let max_length = by_start
+            .iter()
+            .map(|&i| blocks[i].end - blocks[i].start)
+            .fold(0.0, f64::max);
+        TrackIndex {
+            by_start,
+            max_length,
+            is_shown,
+        }
+    }
 }
 
 pub struct SceneBlock {
     pub start: f64,
     pub end: f64,
     pub draw_ops: Vec<DrawOp>,
+    /// Seconds to fade in from the start of the block.
+    pub fade_in: f64,
+    /// Seconds to fade out before the end of the block.
+    pub fade_out: f64,
+    /// How the block is blended over the image below it while fading.
+    pub transition: Transition,
+    pub enabled: bool,
+    pub muted: bool,
+    /// When a block is soloed, only the soloed blocks on its track are drawn.
+    pub solo: bool,
+}
+
+impl SceneBlock {
+    pub fn is_shown(&self, any_solo: bool) -> bool {
+        self.enabled && !self.muted && (self.solo || !any_solo)
+    }
+
+    /// The local time of the block from 0.0 to 1.0.
+    pub fn get_progress(&self, time: f64) -> f64 {
+        if self.end > self.start {
+            ((time - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    /// The visibility of the block from 0.0 to 1.0, which is 1.0 outside of the fade in and fade
+    /// out durations.
+    pub fn get_fade(&self, time: f64) -> f64 {
+        let mut fade: f64 = 1.0;
+        if self.fade_in > 0.0 {
+            fade = fade.min((time - self.start) / self.fade_in);
+        }
+        if self.fade_out > 0.0 {
+            fade = fade.min((self.end - time) / self.fade_out);
+        }
+        fade.clamp(0.0, 1.0)
+    }
 }
 
+/// The shader which blends a fading scene block over the image of the blocks drawn before it.
+/// Overlap the blocks and set `fade_in` on the incoming one to crossfade between them.
+#[derive(Copy, Clone, Debug)]
+pub enum Transition {
+    Crossfade,
+    /// Reveals the block from left to right.
+    Wipe,
+    /// Draws the quad scene with the index. The image below is on texture binding 0, the block's
+    /// image on binding 1, and the `Block_Fade` builtin has the fade value.
+    Custom(usize),
+}
+
+#[derive(Copy, Clone, Debug)]
 pub enum DrawOp {
     NOOP,
     Draw_Quad_Scene(usize),
@@ -98,4 +451,31 @@ pub enum DrawOp {
     Target_Buffer(usize),
     Target_Buffer_Default,
     Profile(usize),
+    /// Draws the quad scene (last arg) when the sync var (first arg) meets the condition with the
+    /// value.
+    If_Var_Draw_Quad_Scene(usize, VarCondition, f64, usize),
+    /// Draws the polygon scene (last arg) when the sync var (first arg) meets the condition with
+    /// the value.
+    If_Var_Draw_Poly_Scene(usize, VarCondition, f64, usize),
+}
+
+/// Compares a sync var with a value in conditional draw ops.
+#[derive(Copy, Clone, Debug)]
+pub enum VarCondition {
+    Equal,
+    Above,
+    Below,
+}
+
+/// Sync var values come from f32 Rocket keys, so equality is checked within this margin.
+const VAR_EQUAL_EPSILON: f64 = 0.0001;
+
+impl VarCondition {
+    pub fn is_true(&self, var_value: f64, value: f64) -> bool {
+        match self {
+            VarCondition::Equal => (var_value - value).abs() < VAR_EQUAL_EPSILON,
+            VarCondition::Above => var_value > value,
+            VarCondition::Below => var_value < value,
+        }
+    }
 }
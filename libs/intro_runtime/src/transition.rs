@@ -0,0 +1,99 @@
+use crate::error::RuntimeError;
+use crate::frame_buffer::{BufferKind, FrameBuffer};
+use crate::quad_scene_gfx::Quad;
+use crate::types::PixelFormat;
+use crate::ERR_MSG_LEN;
+
+pub const TRANSITION_VERT_SRC: &str = r#"#version 430
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec2 tex;
+out vec2 texCoord;
+void main() {
+  gl_Position = vec4(pos, 0.0, 1.0);
+  texCoord = tex;
+}
+"#;
+
+pub const CROSSFADE_FRAG_SRC: &str = r#"#version 430
+in vec2 texCoord;
+out vec4 out_color;
+layout(location = 0) uniform float fade;
+layout(binding = 0) uniform sampler2D belowTexture;
+layout(binding = 1) uniform sampler2D blockTexture;
+void main() {
+  out_color = mix(texture(belowTexture, texCoord), texture(blockTexture, texCoord), fade);
+}
+"#;
+
+pub const WIPE_FRAG_SRC: &str = r#"#version 430
+in vec2 texCoord;
+out vec4 out_color;
+layout(location = 0) uniform float fade;
+layout(binding = 0) uniform sampler2D belowTexture;
+layout(binding = 1) uniform sampler2D blockTexture;
+void main() {
+  out_color = mix(texture(blockTexture, texCoord), texture(belowTexture, texCoord), step(fade, texCoord.x));
+}
+"#;
+
+/// Buffers and shaders for blending a fading scene block over the result image.
+///
+/// While a fading block is drawn, its draw ops which target the result image (buffer 0) draw to
+/// `block_buffer` instead. Then the result image is copied to `below_buffer`, and the transition
+/// shader draws the blend of the two to the result image. A transition shader samples the image
+/// below on texture binding 0 and the block's image on binding 1.
+pub struct TransitionGfx {
+    pub block_buffer: FrameBuffer,
+    pub below_buffer: FrameBuffer,
+    pub crossfade: Option<Quad>,
+    pub wipe: Option<Quad>,
+    /// Set while drawing the ops of a fading block.
+    pub is_active: bool,
+}
+
+impl Default for TransitionGfx {
+    fn default() -> TransitionGfx {
+        TransitionGfx::new()
+    }
+}
+
+impl TransitionGfx {
+    pub fn new() -> TransitionGfx {
+        TransitionGfx {
+            block_buffer: FrameBuffer::new(BufferKind::Empty_Texture, PixelFormat::RGBA_u8, None),
+            below_buffer: FrameBuffer::new(BufferKind::Empty_Texture, PixelFormat::RGBA_u8, None),
+            crossfade: None,
+            wipe: None,
+            is_active: false,
+        }
+    }
+
+    pub fn create_buffers(&mut self, width: i32, height: i32) -> Result<(), RuntimeError> {
+        self.block_buffer.gl_cleanup();
+        self.below_buffer.gl_cleanup();
+        self.block_buffer.create_buffer(width, height, None)?;
+        self.below_buffer.create_buffer(width, height, None)?;
+        Ok(())
+    }
+
+    pub fn create_shaders(
+        &mut self,
+        err_msg_buf: &mut [u8; ERR_MSG_LEN],
+    ) -> Result<(), RuntimeError> {
+        self.crossfade = Some(Quad::new(
+            TRANSITION_VERT_SRC,
+            CROSSFADE_FRAG_SRC,
+            err_msg_buf,
+        )?);
+        self.wipe = Some(Quad::new(TRANSITION_VERT_SRC, WIPE_FRAG_SRC, err_msg_buf)?);
+        Ok(())
+    }
+
+    pub fn gl_cleanup(&mut self) {
+        self.block_buffer.gl_cleanup();
+        self.below_buffer.gl_cleanup();
+        self.crossfade = None;
+        self.wipe = None;
+        self.is_active = false;
+    }
+}
@@ -8,8 +8,41 @@ pub struct Vertex {
     pub position: [GLfloat; 3],
     pub normal: [GLfloat; 3],
     pub texcoords: [GLfloat; 2],
-    //pub tangent:   [GLfloat; 3],
-    //pub bitangent: [GLfloat; 3],
+    pub tangent: [GLfloat; 3],
+    pub bitangent: [GLfloat; 3],
+}
+
+/// The vertex attributes which a mesh has data for. Position is at location 0, normal at 1,
+/// texcoords at 2, tangent at 3 and bitangent at 4. Attributes which the mesh doesn't have are not
+/// enabled, and the shaders read the default value for them.
+#[derive(Copy, Clone, Debug)]
+pub struct VertexLayout {
+    pub normal: bool,
+    pub texcoords: bool,
+    /// Both the tangent and the bitangent.
+    pub tangents: bool,
+}
+
+impl Default for VertexLayout {
+    fn default() -> VertexLayout {
+        VertexLayout {
+            normal: true,
+            texcoords: true,
+            tangents: true,
+        }
+    }
+}
+
+/// Material constants of a mesh, such as from an OBJ `.mtl` file. They are set on the uniforms
+/// `material_ambient`, `material_diffuse`, `material_specular`, `material_shininess` and
+/// `material_opacity`, when the shader declares them.
+#[derive(Copy, Clone, Debug)]
+pub struct Material {
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub shininess: f32,
+    pub opacity: f32,
 }
 
 pub struct Image {
@@ -35,6 +68,14 @@ pub enum ValueVec3 {
     Fixed(f32, f32, f32),
 }
 
+/// Value for a `vec4` type, such as a quaternion. Either sync `.xyzw` from tracks, or set a
+/// fixed value.
+pub enum ValueVec4 {
+    NOOP,
+    Sync(u8, u8, u8, u8),
+    Fixed(f32, f32, f32, f32),
+}
+
 /// Value for a `float` type uniform. Either sync from a track, or set a fixed
 /// value.
 pub enum ValueFloat {
@@ -50,10 +91,14 @@ pub enum UniformMapping {
     Vec2(u8, u8, u8),
     Vec3(u8, u8, u8, u8),
     Vec4(u8, u8, u8, u8, u8),
+    /// The `mat4` light space matrix of the shadow map of a light: (layout_idx, light_idx)
+    LightSpaceMatrix(u8, u8),
 }
 
 /// Map a frame buffer: (layout_idx, buffer_idx)
 pub enum BufferMapping {
     NOOP,
     Sampler2D(u8, u8),
+    /// The depth texture of the shadow map of a light: (layout_idx, light_idx)
+    ShadowMap(u8, u8),
 }
@@ -9,7 +9,7 @@ use std::ptr;
 use gl;
 use gl::types::*;
 
-use crate::data_blob::push_f32;
+use crate::data_blob::{push_f32, push_u32};
 use crate::error::RuntimeError;
 use crate::error::RuntimeError::*;
 
@@ -35,10 +35,10 @@ impl UniformBuffer {
     }
 
     pub fn create_buffer(&mut self, byte_size: usize) -> Result<(), RuntimeError> {
+        self.gl_cleanup();
+
         self.byte_size = byte_size;
-        for _ in 0..byte_size {
-            self.data.push(0);
-        }
+        self.data = vec![0; byte_size];
 
         let mut ubo: GLuint = 0;
         unsafe {
@@ -94,6 +94,36 @@ impl UniformBuffer {
         Ok(())
     }
 
+    /// Sets consecutive `vec4` values, which are not padded in layout std140.
+    pub fn set_vec4_array(
+        &mut self,
+        start_offset: usize,
+        data: &[[f32; 4]],
+    ) -> Result<(), RuntimeError> {
+        let end_offset = start_offset + data.len() * 16;
+        if end_offset > self.data.len() {
+            return Err(RuntimeError::DataIdxIsOutOfBounds);
+        }
+
+        let mut v: Vec<u8> = Vec::with_capacity(data.len() * 16);
+        for n in data.iter().flat_map(|x| x.iter()) {
+            push_f32(&mut v, *n);
+        }
+        self.data[start_offset..end_offset].clone_from_slice(&v);
+        Ok(())
+    }
+
+    pub fn set_i32(&mut self, offset: usize, n: i32) -> Result<(), RuntimeError> {
+        if offset + 4 > self.data.len() {
+            return Err(RuntimeError::DataIdxIsOutOfBounds);
+        }
+
+        let mut v: Vec<u8> = Vec::new();
+        push_u32(&mut v, n as u32);
+        self.data[offset..(offset + 4)].clone_from_slice(&v[..4]);
+        Ok(())
+    }
+
     pub fn bind_as_uniform_block(&self, binding_idx: u8) -> Result<(), RuntimeError> {
         if binding_idx <= gl::MAX_UNIFORM_BUFFER_BINDINGS as u8 {
             if let Some(ubo) = self.ubo {
@@ -114,4 +144,18 @@ impl UniformBuffer {
         }
         Ok(())
     }
+
+    pub fn gl_cleanup(&mut self) {
+        if let Some(ubo) = self.ubo.take() {
+            unsafe {
+                gl::DeleteBuffers(1, &ubo);
+            }
+        }
+    }
+}
+
+impl Drop for UniformBuffer {
+    fn drop(&mut self) {
+        self.gl_cleanup();
+    }
 }
@@ -0,0 +1,184 @@
+use std::{ffi, str};
+
+use gl;
+use gl::types::*;
+
+use crate::context_gfx::ContextGfx;
+use crate::error::RuntimeError;
+use crate::sync_vars::SyncVars;
+use crate::types::UniformMapping;
+
+/// An active uniform of a linked shader program, as reported by OpenGL.
+pub struct ActiveUniform {
+    pub name: String,
+    pub location: GLint,
+    pub kind: GLenum,
+}
+
+pub fn get_active_uniforms(program: GLuint) -> Vec<ActiveUniform> {
+    let mut uniforms: Vec<ActiveUniform> = Vec::new();
+
+    unsafe {
+        let mut count: GLint = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+
+        let mut max_len: GLint = 0;
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+        if max_len <= 0 {
+            return uniforms;
+        }
+
+        for i in 0..count {
+            let mut buf: Vec<u8> = vec![0; max_len as usize];
+            let mut len: GLsizei = 0;
+            let mut size: GLint = 0;
+            let mut kind: GLenum = 0;
+
+            gl::GetActiveUniform(
+                program,
+                i as GLuint,
+                max_len,
+                &mut len,
+                &mut size,
+                &mut kind,
+                buf.as_mut_ptr() as *mut GLchar,
+            );
+
+            let name = match str::from_utf8(&buf[..len as usize]) {
+                Ok(x) => x.to_string(),
+                Err(_) => continue,
+            };
+
+            let c_name = match ffi::CString::new(name.as_bytes()) {
+                Ok(x) => x,
+                Err(_) => continue,
+            };
+            let location = gl::GetUniformLocation(program, c_name.as_ptr());
+
+            uniforms.push(ActiveUniform {
+                name,
+                location,
+                kind,
+            });
+        }
+    }
+
+    uniforms
+}
+
+/// Location of the uniform in the program, or -1 when the program doesn't have it.
+pub fn get_uniform_location(program: GLuint, name: &str) -> GLint {
+    match ffi::CString::new(name) {
+        Ok(c_name) => unsafe { gl::GetUniformLocation(program, c_name.as_ptr()) },
+        Err(_) => -1,
+    }
+}
+
+/// Maps the active uniforms of the program to sync vars by name. Uniforms
+/// without a matching track are left out. See
+/// `SyncVars::find_uniform_indexes()` for the naming convention.
+pub fn uniform_mappings_by_name(program: GLuint, sync_vars: &SyncVars) -> Vec<UniformMapping> {
+    let mut mappings: Vec<UniformMapping> = Vec::new();
+
+    for u in get_active_uniforms(program).iter() {
+        // Only uniforms which fit the u8 indexes of the mapping.
+        if u.location < 0 || u.location > i32::from(u8::MAX) {
+            continue;
+        }
+        let layout_idx = u.location as u8;
+
+        let components = match u.kind {
+            gl::FLOAT => 1,
+            gl::FLOAT_VEC2 => 2,
+            gl::FLOAT_VEC3 => 3,
+            gl::FLOAT_VEC4 => 4,
+            _ => continue,
+        };
+
+        let v = match sync_vars.find_uniform_indexes(&u.name, components) {
+            Some(x) => x,
+            None => continue,
+        };
+        if v.iter().any(|idx| *idx > usize::from(u8::MAX)) {
+            continue;
+        }
+
+        let m = match components {
+            1 => UniformMapping::Float(layout_idx, v[0] as u8),
+            2 => UniformMapping::Vec2(layout_idx, v[0] as u8, v[1] as u8),
+            3 => UniformMapping::Vec3(layout_idx, v[0] as u8, v[1] as u8, v[2] as u8),
+            _ => UniformMapping::Vec4(
+                layout_idx,
+                v[0] as u8,
+                v[1] as u8,
+                v[2] as u8,
+                v[3] as u8,
+            ),
+        };
+
+        mappings.push(m);
+    }
+
+    mappings
+}
+
+/// Sets the uniforms of the program in use from sync var values.
+pub fn set_uniform_mappings(
+    context: &ContextGfx,
+    layout_to_vars: &[UniformMapping],
+) -> Result<(), RuntimeError> {
+    let sync_vars = &context.sync_vars;
+    unsafe {
+        // Mapping sync var indexes to uniform layout indexes
+        for item in layout_to_vars.iter() {
+            use crate::types::UniformMapping::*;
+            match *item {
+                NOOP => {}
+
+                Float(layout_idx, var_idx) => {
+                    gl::Uniform1f(
+                        i32::from(layout_idx),
+                        sync_vars.get_index(var_idx as usize)? as f32,
+                    );
+                }
+
+                Vec2(layout_idx, var1, var2) => {
+                    gl::Uniform2f(
+                        i32::from(layout_idx),
+                        sync_vars.get_index(var1 as usize)? as f32,
+                        sync_vars.get_index(var2 as usize)? as f32,
+                    );
+                }
+
+                Vec3(layout_idx, var1, var2, var3) => {
+                    gl::Uniform3f(
+                        i32::from(layout_idx),
+                        sync_vars.get_index(var1 as usize)? as f32,
+                        sync_vars.get_index(var2 as usize)? as f32,
+                        sync_vars.get_index(var3 as usize)? as f32,
+                    );
+                }
+
+                Vec4(layout_idx, var1, var2, var3, var4) => {
+                    gl::Uniform4f(
+                        i32::from(layout_idx),
+                        sync_vars.get_index(var1 as usize)? as f32,
+                        sync_vars.get_index(var2 as usize)? as f32,
+                        sync_vars.get_index(var3 as usize)? as f32,
+                        sync_vars.get_index(var4 as usize)? as f32,
+                    );
+                }
+
+                LightSpaceMatrix(layout_idx, light_idx) => {
+                    let m = &context
+                        .polygon_context
+                        .lights
+                        .get_shadow_map(light_idx as usize)?
+                        .light_space_matrix;
+                    gl::UniformMatrix4fv(i32::from(layout_idx), 1, gl::FALSE, m[0].as_ptr());
+                }
+            }
+        }
+    }
+    Ok(())
+}
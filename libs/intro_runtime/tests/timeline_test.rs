@@ -0,0 +1,298 @@
+extern crate intro_runtime;
+
+use intro_runtime::timeline::{
+    Composition, CompositionInstance, DrawOp, LoopRegion, SceneBlock, TimeTrack, Timeline,
+    Transition,
+};
+
+fn block(start: f64, end: f64) -> SceneBlock {
+    SceneBlock {
+        start,
+        end,
+        draw_ops: vec![DrawOp::NOOP],
+        fade_in: 0.0,
+        fade_out: 0.0,
+        transition: Transition::Crossfade,
+        enabled: true,
+        muted: false,
+        solo: false,
+    }
+}
+
+fn track(scene_blocks: Vec<SceneBlock>) -> TimeTrack {
+    TimeTrack {
+        scene_blocks,
+        instances: Vec::new(),
+        enabled: true,
+        muted: false,
+        solo: false,
+    }
+}
+
+fn instance(
+    composition_idx: usize,
+    start: f64,
+    time_scale: f64,
+    duration: Option<f64>,
+) -> CompositionInstance {
+    CompositionInstance {
+        composition_idx,
+        start,
+        time_scale,
+        duration,
+    }
+}
+
+/// The composition, track and block indexes of the active blocks at the time.
+fn active_at(timeline: &mut Timeline, time: f64) -> Vec<(Option<usize>, usize, usize)> {
+    timeline.update_active_blocks(time);
+    timeline
+        .active_blocks()
+        .iter()
+        .map(|b| (b.composition_idx, b.track_idx, b.block_idx))
+        .collect()
+}
+
+#[test]
+fn block_start_and_end_boundaries() {
+    let mut timeline = Timeline::new();
+    timeline
+        .tracks
+        .push(track(vec![block(1.0, 3.0), block(3.0, 4.0)]));
+    timeline.build_index();
+
+    assert!(active_at(&mut timeline, 0.0).is_empty());
+    assert!(active_at(&mut timeline, 0.999).is_empty());
+    // The start is included.
+    assert_eq!(active_at(&mut timeline, 1.0), vec![(None, 0, 0)]);
+    assert_eq!(active_at(&mut timeline, 2.999), vec![(None, 0, 0)]);
+    // The end is not, the next block starts there.
+    assert_eq!(active_at(&mut timeline, 3.0), vec![(None, 0, 1)]);
+    assert!(active_at(&mut timeline, 4.0).is_empty());
+    assert!(active_at(&mut timeline, 100.0).is_empty());
+}
+
+#[test]
+fn update_returns_if_active_blocks_changed() {
+    let mut timeline = Timeline::new();
+    timeline.tracks.push(track(vec![block(1.0, 3.0)]));
+    timeline.build_index();
+
+    // Nothing was active before, and nothing is active now.
+    assert!(!timeline.update_active_blocks(0.5));
+    assert!(timeline.update_active_blocks(1.0));
+    assert!(!timeline.update_active_blocks(1.5));
+    assert!(!timeline.update_active_blocks(2.5));
+    assert!(timeline.update_active_blocks(3.0));
+    assert!(!timeline.update_active_blocks(3.5));
+}
+
+#[test]
+fn overlapping_blocks_of_different_lengths() {
+    let mut timeline = Timeline::new();
+    // Not in the order of start times. The long block has to be found when the short blocks
+    // around the time started much later than it.
+    timeline.tracks.push(track(vec![
+        block(50.0, 55.0),
+        block(0.0, 100.0),
+        block(10.0, 12.0),
+        block(11.0, 11.5),
+    ]));
+    timeline.build_index();
+
+    assert_eq!(active_at(&mut timeline, 5.0), vec![(None, 0, 1)]);
+    assert_eq!(
+        active_at(&mut timeline, 10.5),
+        vec![(None, 0, 1), (None, 0, 2)]
+    );
+    // Drawn in the order of the track.
+    assert_eq!(
+        active_at(&mut timeline, 11.2),
+        vec![(None, 0, 1), (None, 0, 2), (None, 0, 3)]
+    );
+    assert_eq!(
+        active_at(&mut timeline, 11.7),
+        vec![(None, 0, 1), (None, 0, 2)]
+    );
+    assert_eq!(
+        active_at(&mut timeline, 52.0),
+        vec![(None, 0, 0), (None, 0, 1)]
+    );
+    assert_eq!(active_at(&mut timeline, 60.0), vec![(None, 0, 1)]);
+    assert_eq!(active_at(&mut timeline, 99.9), vec![(None, 0, 1)]);
+    assert!(active_at(&mut timeline, 100.0).is_empty());
+}
+
+#[test]
+fn blocks_on_several_tracks() {
+    let mut timeline = Timeline::new();
+    timeline.tracks.push(track(vec![block(0.0, 10.0)]));
+    timeline.tracks.push(track(vec![block(5.0, 6.0)]));
+    let mut muted = track(vec![block(0.0, 10.0)]);
+    muted.muted = true;
+    timeline.tracks.push(muted);
+    timeline.build_index();
+
+    assert_eq!(active_at(&mut timeline, 2.0), vec![(None, 0, 0)]);
+    assert_eq!(
+        active_at(&mut timeline, 5.5),
+        vec![(None, 0, 0), (None, 1, 0)]
+    );
+}
+
+#[test]
+fn time_moving_backwards() {
+    let mut timeline = Timeline::new();
+    timeline.tracks.push(track(vec![
+        block(0.0, 2.0),
+        block(2.0, 30.0),
+        block(4.0, 5.0),
+    ]));
+    timeline.build_index();
+
+    assert_eq!(
+        active_at(&mut timeline, 4.5),
+        vec![(None, 0, 1), (None, 0, 2)]
+    );
+    assert!(timeline.update_active_blocks(3.0));
+    assert_eq!(active_at(&mut timeline, 3.0), vec![(None, 0, 1)]);
+    assert_eq!(active_at(&mut timeline, 1.0), vec![(None, 0, 0)]);
+    assert_eq!(active_at(&mut timeline, 20.0), vec![(None, 0, 1)]);
+    // Back before the time of the last update, within the same block.
+    assert!(!timeline.update_active_blocks(10.0));
+    assert_eq!(active_at(&mut timeline, 10.0), vec![(None, 0, 1)]);
+}
+
+#[test]
+fn jump_to_the_start_of_the_loop_region() {
+    let mut timeline = Timeline::new();
+    timeline
+        .tracks
+        .push(track(vec![block(5.0, 15.0), block(15.0, 25.0)]));
+    timeline.loop_region = Some(LoopRegion {
+        start: 10.0,
+        end: 20.0,
+    });
+    timeline.build_index();
+
+    let region = timeline.loop_region.unwrap();
+    let mut time = region.start;
+    let mut blocks_at_start = Vec::new();
+    let mut blocks_at_end = Vec::new();
+
+    // Play through the region twice, jumping back at the end like the preview does.
+    for _ in 0..2 {
+        blocks_at_start.push(active_at(&mut timeline, time));
+        while time + 0.5 < region.end {
+            time += 0.5;
+            timeline.update_active_blocks(time);
+        }
+        blocks_at_end.push(active_at(&mut timeline, time));
+        time = region.start;
+    }
+
+    assert_eq!(
+        blocks_at_start,
+        vec![vec![(None, 0, 0)], vec![(None, 0, 0)]]
+    );
+    assert_eq!(blocks_at_end, vec![vec![(None, 0, 1)], vec![(None, 0, 1)]]);
+    assert!(timeline.update_active_blocks(region.start));
+}
+
+#[test]
+fn composition_instances_with_offsets() {
+    let mut timeline = Timeline::new();
+    timeline.compositions.push(Composition {
+        tracks: vec![track(vec![block(0.0, 2.0), block(2.0, 4.0)])],
+    });
+
+    let mut t = track(vec![block(0.0, 100.0)]);
+    t.instances.push(instance(0, 10.0, 1.0, Some(3.0)));
+    t.instances.push(instance(0, 20.0, 2.0, None));
+    timeline.tracks.push(t);
+    timeline.build_index();
+
+    assert_eq!(active_at(&mut timeline, 9.0), vec![(None, 0, 0)]);
+
+    // The scene blocks of the track come first, then the blocks of the instances.
+    assert_eq!(
+        active_at(&mut timeline, 10.5),
+        vec![(None, 0, 0), (Some(0), 0, 0)]
+    );
+    let b = timeline.active_blocks()[1];
+    assert_eq!(b.local_time(10.5), 0.5);
+    assert_eq!(timeline.get_block(&b).end, 2.0);
+
+    assert_eq!(
+        active_at(&mut timeline, 12.5),
+        vec![(None, 0, 0), (Some(0), 0, 1)]
+    );
+    assert_eq!(timeline.active_blocks()[1].local_time(12.5), 2.5);
+
+    // Clipped by the duration of the instance.
+    assert_eq!(active_at(&mut timeline, 13.0), vec![(None, 0, 0)]);
+
+    // The second instance plays at double speed.
+    assert_eq!(
+        active_at(&mut timeline, 20.5),
+        vec![(None, 0, 0), (Some(0), 0, 0)]
+    );
+    assert_eq!(timeline.active_blocks()[1].local_time(20.5), 1.0);
+    assert_eq!(
+        active_at(&mut timeline, 21.0),
+        vec![(None, 0, 0), (Some(0), 0, 1)]
+    );
+    assert_eq!(timeline.active_blocks()[1].local_time(21.5), 3.0);
+    assert_eq!(active_at(&mut timeline, 22.0), vec![(None, 0, 0)]);
+}
+
+#[test]
+fn nested_composition_instances() {
+    let mut timeline = Timeline::new();
+
+    let mut outer = track(Vec::new());
+    outer.instances.push(instance(1, 1.0, 1.0, None));
+    timeline.compositions.push(Composition {
+        tracks: vec![outer],
+    });
+    timeline.compositions.push(Composition {
+        tracks: vec![track(vec![block(0.0, 1.0)])],
+    });
+
+    let mut t = track(Vec::new());
+    t.instances.push(instance(0, 5.0, 1.0, None));
+    timeline.tracks.push(t);
+    timeline.build_index();
+
+    assert!(active_at(&mut timeline, 5.5).is_empty());
+    assert_eq!(active_at(&mut timeline, 6.5), vec![(Some(1), 0, 0)]);
+    assert_eq!(timeline.active_blocks()[0].local_time(6.5), 0.5);
+    assert!(active_at(&mut timeline, 7.0).is_empty());
+}
+
+#[test]
+fn cache_is_invalidated_after_build_index() {
+    let mut timeline = Timeline::new();
+    timeline.tracks.push(track(vec![block(0.0, 10.0)]));
+    timeline.build_index();
+
+    assert_eq!(active_at(&mut timeline, 1.0), vec![(None, 0, 0)]);
+
+    // Without building the index, the cached blocks stay until the block ends.
+    timeline.tracks[0].scene_blocks[0].enabled = false;
+    assert!(!timeline.update_active_blocks(2.0));
+    assert_eq!(active_at(&mut timeline, 2.0), vec![(None, 0, 0)]);
+
+    timeline.build_index();
+    assert!(!timeline.update_active_blocks(2.0));
+    assert!(timeline.active_blocks().is_empty());
+
+    timeline.tracks[0].scene_blocks[0].enabled = true;
+    timeline.tracks.push(track(vec![block(1.0, 3.0)]));
+    timeline.build_index();
+    assert!(timeline.update_active_blocks(2.0));
+    assert_eq!(
+        active_at(&mut timeline, 2.0),
+        vec![(None, 0, 0), (None, 1, 0)]
+    );
+}
@@ -12,38 +12,112 @@ in vec2 texCoord;
 //layout(location = 2) uniform mat4 projection;
 layout(location = 3) uniform vec3 view_pos;
 
-// Further locations are bound with layout_to_vars.
+// Further uniforms are bound to the sync tracks by name.
 
-layout(location = 4) uniform float iTime; // Time
-layout(location = 5) uniform vec2 iResolution; // Window_Width, _Height
-layout(location = 6) uniform vec2 screenResolution; // Screen_Width, _Height
-layout(location = 7) uniform vec3 g_light_position; // Light_Pos_X, _Y, _Z,
+uniform float Time;
+uniform vec2 Window; // Window_Width, _Height
+uniform vec2 Screen; // Screen_Width, _Height
 
 layout (binding = 0) uniform sampler2D objTexture;
 
-void main() {
-  vec3 ambient = vec3(0.1);
-  vec3 light_diffuse = vec3(0.8, 0.0, 0.8) + vec3(sin(iTime), 0., 0.);
-  vec3 light_specular = vec3(1.0);
-  vec3 material_specular = vec3(0.0, 0.0, 0.9);
+// The lights of the polygon_context.
 
-  //vec3 material_diffuse = vec3(0.9, 0.0, 0.0);
-  vec2 tex_uv = texCoord * (screenResolution / iResolution);
-  vec3 material_diffuse = vec3(texture(objTexture, tex_uv));
+struct Light {
+  vec4 position;  // .xyz, .w is the type: 0 point, 1 directional, 2 spot
+  vec4 direction; // .xyz, .w is the cosine of the cutoff angle
+  vec4 color;     // .rgb, .a is the strength
+  vec4 falloff;   // constant, linear, quadratic, cosine of the outer cutoff angle
+  vec4 shadow;    // .x is 1.0 when it has a shadow map, .y is the bias, .z the texel size
+};
+
+layout(std140, binding = 0) uniform Lights {
+  int light_count;
+  Light lights[8];
+};
+
+// The shadow map of one light, bound with the LightSpaceMatrix and ShadowMap
+// mappings of the scene object.
+
+const int SHADOW_LIGHT = 1;
+
+layout(location = 4) uniform mat4 light_space;
+layout(binding = 1) uniform sampler2D shadow_map;
+
+// 1.0 in shadow, 0.0 in light, averaged over the neighbouring texels.
+float shadow(Light light) {
+  vec4 p = light_space * vec4(fragPos, 1.0);
+  vec3 c = p.xyz / p.w * 0.5 + 0.5;
+  if (c.z > 1.0) {
+    return 0.0;
+  }
+
+  float s = 0.0;
+  for (int x = -1; x <= 1; x++) {
+    for (int y = -1; y <= 1; y++) {
+      float closest = texture(shadow_map, c.xy + vec2(x, y) * light.shadow.z).r;
+      s += (c.z - light.shadow.y > closest) ? 1.0 : 0.0;
+    }
+  }
+  return s / 9.0;
+}
+
+vec3 light_contribution(Light light, vec3 norm, vec3 viewDir,
+                        vec3 material_diffuse, vec3 material_specular) {
+  int light_type = int(light.position.w);
+
+  vec3 lightDir;
+  float attenuation = 1.0;
+
+  if (light_type == 1) {
+    lightDir = normalize(-light.direction.xyz);
+  } else {
+    vec3 d = light.position.xyz - fragPos;
+    float dist = length(d);
+    lightDir = d / dist;
+    attenuation = 1.0 / (light.falloff.x + light.falloff.y * dist +
+                         light.falloff.z * dist * dist);
+
+    if (light_type == 2) {
+      float theta = dot(lightDir, normalize(-light.direction.xyz));
+      float epsilon = light.direction.w - light.falloff.w;
+      attenuation *= clamp((theta - light.falloff.w) / epsilon, 0.0, 1.0);
+    }
+  }
+
+  vec3 light_color = light.color.rgb * light.color.a * attenuation;
 
   // diffuse
-  vec3 norm = normalize(normal);
-  vec3 lightDir = normalize(g_light_position - fragPos);
   float diff = max(dot(norm, lightDir), 0.0);
-  vec3 diffuse = light_diffuse * (diff * material_diffuse);
+  vec3 diffuse = light_color * (diff * material_diffuse);
 
   // specular
-  vec3 viewDir = normalize(view_pos - fragPos);
   vec3 reflectDir = reflect(-lightDir, norm);
   float spec = pow(max(dot(viewDir, reflectDir), 0.0), 24.0);
-  vec3 specular = light_specular * (spec * material_specular);
+  vec3 specular = light_color * (spec * material_specular);
 
-  vec3 result = ambient + diffuse + specular;
+  return diffuse + specular;
+}
+
+void main() {
+  vec3 ambient = vec3(0.1);
+  vec3 material_specular = vec3(0.0, 0.0, 0.9);
+
+  //vec3 material_diffuse = vec3(0.9, 0.0, 0.0);
+  vec2 tex_uv = texCoord * (Screen / Window);
+  vec3 material_diffuse = vec3(texture(objTexture, tex_uv));
+
+  // lights
+  vec3 norm = normalize(normal);
+  vec3 viewDir = normalize(view_pos - fragPos);
+  vec3 result = ambient;
+  for (int i = 0; i < light_count; i++) {
+    vec3 c = light_contribution(lights[i], norm, viewDir,
+                                material_diffuse, material_specular);
+    if (i == SHADOW_LIGHT && lights[i].shadow.x > 0.0) {
+      c *= 1.0 - shadow(lights[i]);
+    }
+    result += c;
+  }
 
   out_color = vec4(result, 1.0);
 }
@@ -12,18 +12,18 @@ in vec2 texCoord;
 //layout(location = 2) uniform mat4 projection;
 layout(location = 3) uniform vec3 view_pos;
 
-// Further locations are bound with layout_to_vars.
+// Further uniforms are bound to the sync tracks by name.
 
-layout(location = 4) uniform float iTime; // Time
-layout(location = 5) uniform vec2 iResolution; // Window_Width, _Height
-layout(location = 6) uniform vec2 screenResolution; // Screen_Width, _Height
-layout(location = 7) uniform vec3 g_light_position; // Light_Pos_X, _Y, _Z,
+uniform float Time;
+uniform vec2 Window; // Window_Width, _Height
+uniform vec2 Screen; // Screen_Width, _Height
+uniform vec3 Light_Pos; // Light_Pos_X, _Y, _Z
 
 layout (binding = 0) uniform sampler2D objTexture;
 
 void main() {
 
-  vec2 tex_uv = texCoord * (screenResolution / iResolution);
+  vec2 tex_uv = texCoord * (Screen / Window);
   vec3 material_diffuse = vec3(texture(objTexture, tex_uv));
 
   vec3 result = material_diffuse;
@@ -2,6 +2,9 @@
 layout(location = 0) in vec3 pos;
 layout(location = 1) in vec3 nor;
 layout(location = 2) in vec2 tex;
+// For normal mapping, e.g. mat3 TBN = mat3(tangent, bitangent, nor);
+layout(location = 3) in vec3 tangent;
+layout(location = 4) in vec3 bitangent;
 
 out vec3 fragPos;
 out vec3 normal;
@@ -11,9 +14,16 @@ layout(location = 0) uniform mat4 model;
 layout(location = 1) uniform mat4 view;
 layout(location = 2) uniform mat4 projection;
 
+// The transform of each copy when the scene object has instances, otherwise a
+// single identity matrix.
+layout(std430, binding = 0) buffer Instances {
+  mat4 instance_model[];
+};
+
 void main() {
-  fragPos = vec3(model * vec4(pos, 1.0));
-  normal = mat3(transpose(inverse(model))) * nor;
+  mat4 m = model * instance_model[gl_InstanceID];
+  fragPos = vec3(m * vec4(pos, 1.0));
+  normal = mat3(transpose(inverse(m))) * nor;
 
   gl_Position = projection * view * vec4(fragPos, 1.0);
   texCoord = tex;
@@ -30,13 +30,15 @@ use glutin::{ElementState, Event, EventsLoop, GlContext, GlWindow, WindowEvent};
 use intro_3d::lib::Vector3;
 use rocket_client::SyncClient;
 
+use crate::dmo_data::timeline::{LoopRegion, Marker};
 use crate::error::ToolError;
 use intro_runtime::error::RuntimeError;
 
 use crate::preview_client::client_actor::{ClientActor, ClientMessage};
 use crate::server_actor::{
     MsgDataType, Receiving, Sending, ServerActor, ServerState, ServerStateWrap, SetDmoMsg,
-    SetShaderMsg, ShaderCompilationFailedMsg, ShaderCompilationSuccessMsg,
+    SetShaderMsg, SetTimelineFlagsMsg, ShaderCompilationFailedMsg, ShaderCompilationSuccessMsg,
+    StartTraceCaptureMsg,
 };
 use crate::server_init_actor::{self, ServerInitActor};
 use crate::webview_actor::{self, WebviewActor};
@@ -94,6 +96,9 @@ pub struct AppStartParams {
     pub is_nwjs: bool,
     pub is_dialogs: bool,
     pub show_logs: bool,
+    /// Capture a trace of this many frames when the preview starts.
+    pub trace_frames: Option<usize>,
+    pub trace_path: Option<PathBuf>,
 }
 
 pub struct AppInfo {
@@ -119,6 +124,8 @@ impl Default for AppStartParams {
                 is_nwjs: false,
                 is_dialogs: false,
                 show_logs: false,
+                trace_frames: None,
+                trace_path: None,
             }
         } else {
             AppStartParams {
@@ -135,6 +142,8 @@ impl Default for AppStartParams {
                 is_nwjs: false,
                 is_dialogs: false,
                 show_logs: false,
+                trace_frames: None,
+                trace_path: None,
             }
         }
     }
@@ -272,6 +281,22 @@ pub fn process_cli_args(matches: clap::ArgMatches) -> Result<AppStartParams, Box
         params.show_logs = true;
     }
 
+    if matches.is_present("trace_frames") {
+        match matches.value_of("trace_frames").unwrap().parse::<usize>() {
+            Ok(x) => params.trace_frames = Some(x),
+            Err(e) => {
+                error! {"🔥 {:?}", e};
+                exit(2);
+            }
+        }
+    }
+
+    if matches.is_present("trace_path") {
+        if let Ok(x) = matches.value_of("trace_path").unwrap().parse::<String>() {
+            params.trace_path = Some(PathBuf::from(&x));
+        }
+    }
+
     Ok(params)
 }
 
@@ -656,6 +681,8 @@ pub fn start_nwjs(plazma_server_port: Arc<usize>, path_to_nwjs: &PathBuf) -> Res
 pub fn start_preview(
     plazma_server_port: Arc<usize>,
     yml_path: Option<PathBuf>,
+    trace_frames: Option<usize>,
+    trace_path: Option<PathBuf>,
 ) -> Result<(), Box<dyn Error>> {
     info!("⚽ start_preview() start");
 
@@ -824,6 +851,10 @@ pub fn start_preview(
         state.set_is_paused(false);
     }
 
+    if let Some(frames) = trace_frames {
+        state.start_trace_capture(frames, trace_path);
+    }
+
     // TODO server_sender will error when server is not connected. Detect the condition and don't
     // send messages.
 
@@ -1107,6 +1138,7 @@ fn render_loop(
                                 mouse_sensitivity: settings_data.mouse_sensitivity,
                                 movement_sensitivity: settings_data.movement_sensitivity,
                                 total_length: settings_data.total_length,
+                                loop_at_end: settings_data.loop_at_end,
                             };
                             state.dmo_gfx.settings = settings;
                         }
@@ -1137,6 +1169,72 @@ fn render_loop(
                         SaveProject => {}
                         NewProject => {}
                         DeleteMessageFile => {}
+                        ProfileResults => {}
+                        SelectedObject => {}
+
+                        StartTraceCapture => {
+                            let msg: StartTraceCaptureMsg =
+                                match serde_json::from_str(&message.data) {
+                                    Ok(x) => x,
+                                    Err(e) => {
+                                        error! {"🔥 Can't deserialize to StartTraceCaptureMsg: {:?}", e};
+                                        return;
+                                    }
+                                };
+                            state.start_trace_capture(msg.frames, msg.path.map(PathBuf::from));
+                        }
+
+                        SetMarkers => {
+                            let markers: Vec<Marker> = match serde_json::from_str(&message.data) {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    error! {"🔥 Can't deserialize to markers: {:?}", e};
+                                    return;
+                                }
+                            };
+                            state.set_markers(&markers);
+                        }
+
+                        SetLoopRegion => {
+                            let loop_region: Option<LoopRegion> =
+                                match serde_json::from_str(&message.data) {
+                                    Ok(x) => x,
+                                    Err(e) => {
+                                        error! {"🔥 Can't deserialize to loop region: {:?}", e};
+                                        return;
+                                    }
+                                };
+                            state.set_loop_region(&loop_region);
+                        }
+
+                        JumpToMarker => {
+                            let name: String = match serde_json::from_str(&message.data) {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    error! {"🔥 Can't deserialize to marker name: {:?}", e};
+                                    return;
+                                }
+                            };
+                            match state.jump_to_marker(&name) {
+                                Ok(_) => {}
+                                Err(e) => error! {"🔥 Can't jump to marker: {:?}", e},
+                            }
+                        }
+
+                        SetTimelineFlags => {
+                            let msg: SetTimelineFlagsMsg = match serde_json::from_str(&message.data)
+                            {
+                                Ok(x) => x,
+                                Err(e) => {
+                                    error! {"🔥 Can't deserialize to SetTimelineFlagsMsg: {:?}", e};
+                                    return;
+                                }
+                            };
+                            match state.set_timeline_flags(&msg) {
+                                Ok(_) => {}
+                                Err(e) => error! {"🔥 Can't set timeline flags: {:?}", e},
+                            }
+                        }
 
                         ExitApp => {
                             info!("render_loop() Received ExitApp.");
@@ -1166,10 +1264,6 @@ fn render_loop(
 
         state.update_time_frame_start();
 
-        if state.dmo_gfx.settings.total_length < state.get_time() {
-            break;
-        }
-
         // 1. sync vars (time, camera, etc.)
 
         match state.update_rocket(&mut rocket) {
@@ -1227,6 +1321,11 @@ fn render_loop(
                                     // act on key release
                                     if !pressed {
                                         state.explore_mode = !state.explore_mode;
+
+                                        // Leaving explore mode ends the recording.
+                                        if !state.explore_mode && state.camera_recorder.is_some() {
+                                            state.toggle_camera_recording();
+                                        }
                                     }
 
                                     // When turning on explore mode, copy the
@@ -1237,6 +1336,41 @@ fn render_loop(
                                     }
                                 }
 
+                                // switch the explore mode camera between free-fly and orbit
+                                O => {
+                                    if !pressed {
+                                        state.toggle_orbit_camera();
+                                    }
+                                }
+
+                                // select the next scene object
+                                Tab => {
+                                    if !pressed {
+                                        state.select_next_object();
+                                    }
+                                }
+
+                                // frame the selected object with the orbit camera
+                                F => {
+                                    if !pressed {
+                                        if !state.explore_mode {
+                                            state.explore_mode = true;
+                                            state.set_camera_from_context();
+                                        }
+                                        match state.frame_selected_object() {
+                                            Ok(_) => {}
+                                            Err(e) => error!("🔥 frame_selected_object() {:?}", e),
+                                        }
+                                    }
+                                }
+
+                                // record the explore mode camera into the camera tracks
+                                R => {
+                                    if !pressed {
+                                        state.toggle_camera_recording();
+                                    }
+                                }
+
                                 // pause time
                                 Space => {
                                     if !pressed {
@@ -1269,6 +1403,27 @@ fn render_loop(
                                     }
                                 }
 
+                                // jump to the next or previous timeline marker
+                                PageDown => {
+                                    if !pressed && rocket.is_none() {
+                                        state.jump_to_next_marker();
+                                        match state.update_vars() {
+                                            Ok(_) => {}
+                                            Err(e) => error!("🔥 update_vars() {:?}", e),
+                                        }
+                                    }
+                                }
+
+                                PageUp => {
+                                    if !pressed && rocket.is_none() {
+                                        state.jump_to_prev_marker();
+                                        match state.update_vars() {
+                                            Ok(_) => {}
+                                            Err(e) => error!("🔥 update_vars() {:?}", e),
+                                        }
+                                    }
+                                }
+
                                 // print camera values
                                 C => {
                                     if pressed {
@@ -1355,6 +1510,7 @@ fn render_loop(
         // 3. move, update camera (only works in explore mode)
 
         state.update_camera_from_keys();
+        state.record_camera_sample();
 
         // 4. rebuild when assets change on disk
 
@@ -1373,6 +1529,35 @@ fn render_loop(
 
         state.update_time_frame_end();
 
+        // send the recent profiling results to the server
+
+        if let Some(data) = state.get_profile_results() {
+            let msg = serde_json::to_string(&Sending {
+                data_type: MsgDataType::ProfileResults,
+                data: serde_json::to_string(&data).unwrap(),
+            })
+            .unwrap();
+            match server_sender.send(msg) {
+                Ok(_) => {}
+                Err(e) => error!("🔥 Can't send ProfileResults on server_sender: {:?}", e),
+            };
+        }
+
+        // send the selected scene object to the server, when it has changed
+
+        if state.selection_changed {
+            state.selection_changed = false;
+            let msg = serde_json::to_string(&Sending {
+                data_type: MsgDataType::SelectedObject,
+                data: serde_json::to_string(&state.get_selected_object_msg()).unwrap(),
+            })
+            .unwrap();
+            match server_sender.send(msg) {
+                Ok(_) => {}
+                Err(e) => error!("🔥 Can't send SelectedObject on server_sender: {:?}", e),
+            };
+        }
+
         // ship the frame
 
         window.swap_buffers().unwrap();
@@ -1625,6 +1810,13 @@ fn dialogs_loop(client_receiver: mpsc::Receiver<String>, server_sender: &mpsc::S
                         SaveProject => {}
                         NewProject => {}
                         DeleteMessageFile => {}
+                        ProfileResults => {}
+                        SelectedObject => {}
+                        StartTraceCapture => {}
+                        SetMarkers => {}
+                        SetLoopRegion => {}
+                        JumpToMarker => {}
+                        SetTimelineFlags => {}
 
                         ExitApp => {
                             info!("dialogs_loop() Received ExitApp.");
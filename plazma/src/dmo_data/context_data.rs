@@ -2,6 +2,10 @@ use std::error::Error;
 use std::path::PathBuf;
 
 use crate::dmo_data::data_index::DataIndex;
+use crate::dmo_data::model::ModelType;
+use crate::dmo_data::obj_material::{
+    load_obj_materials, material_buffer_name, material_textures, read_asset_text,
+};
 use crate::dmo_data::polygon_context::PolygonContext;
 use crate::dmo_data::polygon_scene::PolygonScene;
 use crate::dmo_data::quad_scene::QuadScene;
@@ -71,6 +75,8 @@ impl ContextData {
             )?;
         }
 
+        self.add_material_frame_buffers(project_root, embedded)?;
+
         for (idx, buffer) in self.frame_buffers.iter().enumerate() {
             self.index.add_frame_buffer(
                 buffer,
@@ -99,6 +105,49 @@ impl ContextData {
 
         Ok(())
     }
+
+    /// Adds an image frame buffer for each texture in the materials of the OBJ models, so that
+    /// the meshes can bind them as any other buffer. Buffers from an earlier build are kept.
+    fn add_material_frame_buffers(
+        &mut self,
+        project_root: &Option<PathBuf>,
+        embedded: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let project_root = match project_root {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        for model in self.polygon_context.models.iter() {
+            match model.model_type {
+                ModelType::Obj => {}
+                _ => continue,
+            }
+            if model.obj_path.is_empty() {
+                continue;
+            }
+
+            let p = project_root.join(PathBuf::from(&model.obj_path));
+            let obj_text = read_asset_text(&p, embedded)?;
+
+            for material in load_obj_materials(&obj_text, &model.obj_path, project_root, embedded) {
+                for (_, path) in material_textures(&material) {
+                    let name = material_buffer_name(path);
+                    if self.frame_buffers.iter().any(|b| b.name == name) {
+                        continue;
+                    }
+                    self.frame_buffers.push(FrameBuffer {
+                        name,
+                        kind: BufferKind::Image_Texture,
+                        format: PixelFormat::RGBA_u8,
+                        image_path: path.to_owned(),
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for ContextData {
@@ -29,6 +29,9 @@ pub struct DataIndex {
     pub model_name_to_idx: BTreeMap<String, usize>,
     pub obj_path_to_idx: BTreeMap<String, usize>,
     pub buffer_name_to_idx: BTreeMap<String, usize>,
+    /// Labels of the `Profile` draw ops, the index is the profile label index.
+    pub profile_names: Vec<String>,
+    pub profile_name_to_idx: BTreeMap<String, usize>,
 }
 
 impl DataIndex {
@@ -45,6 +48,8 @@ impl DataIndex {
             buffer_name_to_idx: BTreeMap::new(),
             image_path_to_idx: BTreeMap::new(),
             image_path_to_format: BTreeMap::new(),
+            profile_names: vec![],
+            profile_name_to_idx: BTreeMap::new(),
         }
     }
 
@@ -185,7 +190,9 @@ impl DataIndex {
         self.buffer_name_to_idx.insert(buffer.name.to_string(), idx);
 
         // TODO should error if buffer is not Empty_Texture but path.len() == 0
-        if !buffer.image_path.is_empty() {
+        // Buffers with the same image share it, the image is only read once.
+        if !buffer.image_path.is_empty() && !self.image_path_to_idx.contains_key(&buffer.image_path)
+        {
             self.add_image_path_format_to_index(&buffer.image_path, buffer.format);
 
             if read_image_path {
@@ -205,18 +212,20 @@ impl DataIndex {
                     .image_path_to_format
                     .get(&buffer.image_path.clone())
                     .ok_or("bad image path name")?;
-                let mut new_image = Image {
+                // Convert to the format of the buffer, such as an RGB JPEG into an RGBA texture.
+                let raw_pixels = match *f {
+                    PixelFormat::NOOP => image_data.raw_pixels(),
+                    PixelFormat::RED_u8 => image_data.to_luma().into_raw(),
+                    PixelFormat::RGB_u8 => image_data.to_rgb().into_raw(),
+                    PixelFormat::RGBA_u8 => image_data.to_rgba().into_raw(),
+                };
+
+                image_sources.push(Image {
                     width,
                     height,
                     format: *f,
-                    raw_pixels: vec![],
-                };
-
-                for x in image_data.raw_pixels().iter() {
-                    new_image.raw_pixels.push(*x);
-                }
-
-                image_sources.push(new_image);
+                    raw_pixels,
+                });
             }
         }
 
@@ -301,9 +310,23 @@ impl DataIndex {
         Ok(*idx)
     }
 
-    pub fn get_profile_index(&self, _name: &str) -> Result<usize, Box<dyn Error>> {
-        // TODO implement
-        Ok(0)
+    /// Profile labels may repeat in the timeline, the same name gets the same index.
+    pub fn add_profile_name(&mut self, name: &str) {
+        if self.profile_name_to_idx.contains_key(name) {
+            return;
+        }
+
+        self.profile_names.push(name.to_owned());
+        let idx = self.profile_names.len() - 1;
+        self.profile_name_to_idx.insert(name.to_owned(), idx);
+    }
+
+    pub fn get_profile_index(&self, name: &str) -> Result<usize, Box<dyn Error>> {
+        let idx = self
+            .profile_name_to_idx
+            .get(name)
+            .ok_or_else(|| format! {"no such profile name: {}", name})?;
+        Ok(*idx)
     }
 }
 
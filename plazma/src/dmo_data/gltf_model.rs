@@ -0,0 +1,269 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::GenericImageView;
+
+use intro_3d::lib::Matrix4;
+use intro_runtime::mesh::Mesh;
+use intro_runtime::types::{Image, PixelFormat, Vertex, VertexLayout};
+
+use crate::dmo_data::model::ComputedNormals;
+use crate::project_data::get_template_asset_bytes;
+
+/// Loads the meshes of a glTF 2.0 file (`.gltf` or `.glb`), one for each triangle primitive in
+/// the nodes of the default scene, or the first scene when there is no default.
+///
+/// The node transforms are accumulated down the hierarchy and stored on the mesh. The base color
+/// texture of the material is at binding 0 and its normal map at binding 1.
+pub fn load_gltf_meshes(
+    path: &PathBuf,
+    embedded: bool,
+    computed_normals: ComputedNormals,
+) -> Result<Vec<Mesh>, Box<dyn Error>> {
+    let gltf::Gltf { document, mut blob } = gltf::Gltf::from_slice(&read_asset(path, embedded)?)?;
+
+    let base = match path.parent() {
+        Some(p) => p.to_path_buf(),
+        None => PathBuf::new(),
+    };
+
+    let mut buffers: Vec<Vec<u8>> = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => match blob.take() {
+                Some(x) => x,
+                None => {
+                    return Err(From::from(format! {
+                        "The binary chunk is missing from {:?}", path
+                    }))
+                }
+            },
+            gltf::buffer::Source::Uri(uri) => read_uri(&base, uri, embedded)?,
+        };
+        buffers.push(data);
+    }
+
+    let scene = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(x) => x,
+        None => return Ok(Vec::new()),
+    };
+
+    let loader = GltfLoader {
+        base,
+        embedded,
+        buffers,
+        computed_normals,
+    };
+
+    let mut meshes: Vec<Mesh> = Vec::new();
+    let identity = Matrix4::identity().as_row_slice();
+    for node in scene.nodes() {
+        loader.add_node_meshes(&node, &identity, &mut meshes)?;
+    }
+
+    Ok(meshes)
+}
+
+struct GltfLoader {
+    base: PathBuf,
+    embedded: bool,
+    buffers: Vec<Vec<u8>>,
+    computed_normals: ComputedNormals,
+}
+
+impl GltfLoader {
+    /// `parent` is the row-major world transform of the parent node.
+    fn add_node_meshes(
+        &self,
+        node: &gltf::Node,
+        parent: &[[f32; 4]; 4],
+        meshes: &mut Vec<Mesh>,
+    ) -> Result<(), Box<dyn Error>> {
+        // glTF matrices are column-major.
+        let local = Matrix4::from_row_slice(node.transform().matrix()).transpose();
+        let world = (Matrix4::from_row_slice(*parent) * local).as_row_slice();
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(mut new_mesh) = self.read_primitive(&primitive)? {
+                    new_mesh.transform = Some(Matrix4::from_row_slice(world).as_column_slice());
+                    meshes.push(new_mesh);
+                }
+            }
+        }
+
+        for child in node.children() {
+            self.add_node_meshes(&child, &world, meshes)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_primitive(&self, primitive: &gltf::Primitive) -> Result<Option<Mesh>, Box<dyn Error>> {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            warn! {"Skipping a glTF primitive which is not made of triangles: {:?}", primitive.mode()};
+            return Ok(None);
+        }
+
+        let reader = primitive.reader(|b| self.buffers.get(b.index()).map(|x| x.as_slice()));
+
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(x) => x.collect(),
+            None => return Ok(None),
+        };
+        let n_vertices = positions.len();
+
+        let normals: Option<Vec<[f32; 3]>> = reader
+            .read_normals()
+            .map(|x| x.collect::<Vec<_>>())
+            .filter(|x| x.len() == n_vertices);
+
+        let texcoords: Option<Vec<[f32; 2]>> = reader
+            .read_tex_coords(0)
+            .map(|x| x.into_f32().collect::<Vec<_>>())
+            .filter(|x| x.len() == n_vertices);
+
+        let mut new_mesh = Mesh::default();
+
+        for (i, position) in positions.iter().enumerate() {
+            new_mesh.vertices.push(Vertex {
+                position: *position,
+                normal: normals.as_ref().map_or([0.0; 3], |x| x[i]),
+                texcoords: texcoords.as_ref().map_or([0.0; 2], |x| x[i]),
+                tangent: [0.0; 3],
+                bitangent: [0.0; 3],
+            });
+        }
+
+        new_mesh.indices = match reader.read_indices() {
+            Some(x) => x.into_u32().collect(),
+            None => (0..n_vertices as u32).collect(),
+        };
+
+        if let Some(index) = new_mesh.indices.iter().find(|&&x| x as usize >= n_vertices) {
+            return Err(From::from(format! {
+                "Vertex index {} is out of bounds in a glTF primitive", index
+            }));
+        }
+
+        new_mesh.layout = VertexLayout {
+            normal: true,
+            texcoords: texcoords.is_some(),
+            tangents: false,
+        };
+
+        if normals.is_none() {
+            match self.computed_normals {
+                ComputedNormals::Smooth => new_mesh.compute_smooth_normals(),
+                ComputedNormals::Flat => new_mesh.compute_flat_normals(),
+            }
+        }
+
+        new_mesh.compute_tangents();
+
+        let material = primitive.material();
+        let base_color = material
+            .pbr_metallic_roughness()
+            .base_color_texture()
+            .map(|x| x.texture());
+        let normal_map = material.normal_texture().map(|x| x.texture());
+
+        // Keep the normal map at binding 1 with a white base color when it has none.
+        if base_color.is_some() || normal_map.is_some() {
+            let image = match base_color {
+                Some(x) => self.read_image(&x)?,
+                None => Image {
+                    width: 1,
+                    height: 1,
+                    format: PixelFormat::RGBA_u8,
+                    raw_pixels: vec![255; 4],
+                },
+            };
+            new_mesh.images.push(image);
+        }
+
+        if let Some(x) = normal_map {
+            new_mesh.images.push(self.read_image(&x)?);
+        }
+
+        Ok(Some(new_mesh))
+    }
+
+    fn read_image(&self, texture: &gltf::Texture) -> Result<Image, Box<dyn Error>> {
+        let bytes: Vec<u8> = match texture.source().source() {
+            gltf::image::Source::View { view, .. } => {
+                let buffer = match self.buffers.get(view.buffer().index()) {
+                    Some(x) => x,
+                    None => return Err(From::from("Missing buffer for a glTF image")),
+                };
+                match buffer.get(view.offset()..view.offset() + view.length()) {
+                    Some(x) => x.to_vec(),
+                    None => return Err(From::from("glTF image is out of the buffer bounds")),
+                }
+            }
+            gltf::image::Source::Uri { uri, .. } => read_uri(&self.base, uri, self.embedded)?,
+        };
+
+        let image = image::load_from_memory(&bytes)?;
+        let (width, height) = image.dimensions();
+
+        Ok(Image {
+            width,
+            height,
+            format: PixelFormat::RGBA_u8,
+            raw_pixels: image.to_rgba().into_raw(),
+        })
+    }
+}
+
+/// Reads a buffer or image from a `data:` URI with base64 content, or from a file relative to the
+/// folder of the glTF file. File URIs are percent-encoded.
+fn read_uri(base: &Path, uri: &str, embedded: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    if uri.starts_with("data:") {
+        return match uri.find(";base64,") {
+            Some(idx) => Ok(base64::decode(&uri[idx + ";base64,".len()..])?),
+            None => Err(From::from(
+                "Only base64 data URIs are supported in glTF files",
+            )),
+        };
+    }
+    read_asset(&base.join(percent_decode(uri)?), embedded)
+}
+
+/// Decodes the percent-encoded bytes of a URI, such as `%20` for a space, and reads the result
+/// as UTF-8.
+pub fn percent_decode(uri: &str) -> Result<String, Box<dyn Error>> {
+    let src = uri.as_bytes();
+    let mut bytes: Vec<u8> = Vec::with_capacity(src.len());
+    let mut i = 0;
+    while i < src.len() {
+        if src[i] == b'%' {
+            let hex = match uri.get(i + 1..i + 3) {
+                Some(x) if x.bytes().all(|c| c.is_ascii_hexdigit()) => x,
+                _ => {
+                    return Err(From::from(
+                        format! {"Invalid percent-encoding in URI: {}", uri},
+                    ))
+                }
+            };
+            bytes.push(u8::from_str_radix(hex, 16)?);
+            i += 3;
+        } else {
+            bytes.push(src[i]);
+            i += 1;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn read_asset(path: &PathBuf, embedded: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    if embedded {
+        get_template_asset_bytes(path)
+    } else {
+        Ok(fs::read(path)?)
+    }
+}
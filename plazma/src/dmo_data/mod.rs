@@ -1,4 +1,3 @@
-use std::collections::HashMap;
 use std::error::Error;
 use std::io::BufReader;
 use std::path::PathBuf;
@@ -7,7 +6,9 @@ use tobj;
 
 pub mod context_data;
 pub mod data_index;
+pub mod gltf_model;
 pub mod model;
+pub mod obj_material;
 pub mod polygon_context;
 pub mod polygon_scene;
 pub mod quad_scene;
@@ -18,9 +19,8 @@ use intro_runtime::dmo_gfx::DmoGfx;
 use crate::dmo_data::context_data::{BufferKind, ContextData, FrameBuffer, PixelFormat};
 use crate::dmo_data::quad_scene::QuadScene;
 use crate::dmo_data::quad_scene::{DRAW_RESULT_FRAG_SRC_PATH, DRAW_RESULT_VERT_SRC_PATH};
-use crate::dmo_data::timeline::{DrawOp, SceneBlock, TimeTrack, Timeline};
+use crate::dmo_data::timeline::{DrawOp, SceneBlock, TimePos, TimeTrack, Timeline, Transition};
 use crate::error::ToolError;
-use crate::project_data::get_template_asset_string;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DmoData {
@@ -117,6 +117,7 @@ impl DmoData {
             read_image_paths,
             embedded,
         )?;
+        dmo_data.build_profile_index();
         Ok(dmo_data)
     }
 
@@ -135,6 +136,7 @@ impl DmoData {
             read_image_paths,
             embedded,
         )?;
+        dmo_data.build_profile_index();
         Ok(dmo_data)
     }
 
@@ -177,6 +179,7 @@ impl DmoData {
                 UniformMapping::Vec2(2, Screen_Width, Screen_Height),
             ],
             binding_to_buffers: vec![],
+            bind_uniforms_by_name: false,
         };
 
         dmo_data.context.index.add_quad_scene(
@@ -204,6 +207,7 @@ impl DmoData {
                 UniformMapping::Vec2(2, Screen_Width, Screen_Height),
             ],
             binding_to_buffers: vec![BufferMapping::Sampler2D(0, "scene buf".to_owned())],
+            bind_uniforms_by_name: false,
         };
 
         dmo_data.context.index.add_quad_scene(
@@ -242,11 +246,15 @@ impl DmoData {
 
         let mut track = TimeTrack {
             scene_blocks: vec![],
+            instances: vec![],
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         let scene = SceneBlock {
-            start: 0.0,
-            end: 240.0,
+            start: TimePos::Seconds(0.0),
+            end: TimePos::Seconds(240.0),
             draw_ops: vec![
                 DrawOp::Target_Buffer("scene buf".to_owned()),
                 // #4682B4, Steel Blue
@@ -257,12 +265,21 @@ impl DmoData {
                 DrawOp::Clear(70, 130, 180, 0),
                 DrawOp::Draw_Quad_Scene("cross".to_owned()),
             ],
+            fade_in: 0.0,
+            fade_out: 0.0,
+            transition: Transition::default(),
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         track.scene_blocks.push(scene);
 
         let timeline = Timeline {
             tracks: vec![track],
+            compositions: vec![],
+            markers: vec![],
+            loop_region: None,
         };
 
         dmo_data.timeline = timeline;
@@ -271,9 +288,41 @@ impl DmoData {
         // ------
 
         //dmo_data.context.build_index(false, false)?;
+        dmo_data.build_profile_index();
         Ok(dmo_data)
     }
 
+    /// The bpm for resolving musical time on the timeline, from the settings or else the bpm of
+    /// the sync tracks. When it is not above zero, it is only an error if the timeline uses
+    /// musical time, otherwise the bpm is not used.
+    pub fn get_bpm(&self, sync_tracks_bpm: f64) -> Result<f64, Box<dyn Error>> {
+        let bpm = self.settings.bpm.unwrap_or(sync_tracks_bpm);
+        if bpm > 0.0 || !self.timeline.uses_musical_time() {
+            Ok(bpm)
+        } else {
+            Err(From::from(format! {"bpm must be above zero: {}", bpm}))
+        }
+    }
+
+    /// Adds the labels of `Profile` draw ops in the timeline to the index. Call after
+    /// `ContextData::build_index()`, which resets the index.
+    pub fn build_profile_index(&mut self) {
+        let composition_tracks = self
+            .timeline
+            .compositions
+            .iter()
+            .flat_map(|c| c.tracks.iter());
+        for track in self.timeline.tracks.iter().chain(composition_tracks) {
+            for block in track.scene_blocks.iter() {
+                for op in block.draw_ops.iter() {
+                    if let DrawOp::Profile(name) = op {
+                        self.context.index.add_profile_name(name);
+                    }
+                }
+            }
+        }
+    }
+
     /// Ensures implicit builtins are included in the data. Skips them when
     /// already present. When the server is sending a serialized DmoData, the
     /// builtins will already be there.
@@ -335,6 +384,12 @@ impl DmoData {
                 d::model::ModelType::Obj => {
                     self.add_model_obj_to(dmo_gfx, model_data, project_root, embedded)?
                 }
+                d::model::ModelType::Gltf => {
+                    self.add_model_gltf_to(dmo_gfx, model_data, project_root, embedded)?
+                }
+                d::model::ModelType::Primitive => {
+                    self.add_model_primitive_to(dmo_gfx, model_data)?
+                }
                 d::model::ModelType::NOOP => {}
             }
         }
@@ -349,8 +404,7 @@ impl DmoData {
     ) -> Result<(), Box<dyn Error>> {
         use intro_runtime::mesh::Mesh;
         use intro_runtime::model::Model;
-
-        let mut model = Model::empty_cube();
+        use intro_runtime::shapes;
 
         let vert_src_idx = self
             .context
@@ -361,11 +415,97 @@ impl DmoData {
             .index
             .get_shader_index(&model_data.frag_src_path)?;
 
-        // Add a mesh but no vertices, those will be created from shapes.
         let mut mesh = Mesh::default();
         mesh.vert_src_idx = vert_src_idx;
         mesh.frag_src_idx = frag_src_idx;
 
+        // The meshes of a Cube model are created from the stored shape, so a flipped cube is
+        // added as a primitive with its own vertices.
+        let mut model = if model_data.flip_faces {
+            let (vertices, indices) = shapes::cube();
+            mesh.vertices = vertices;
+            mesh.indices = indices;
+            mesh.flip_faces();
+            Model::empty_primitive()
+        } else {
+            // Add a mesh but no vertices, those will be created from shapes.
+            Model::empty_cube()
+        };
+
+        model.meshes.push(mesh);
+
+        dmo_gfx.context.polygon_context.models.push(model);
+
+        Ok(())
+    }
+
+    pub fn add_model_primitive_to(
+        &self,
+        dmo_gfx: &mut DmoGfx,
+        model_data: &self::model::Model,
+    ) -> Result<(), Box<dyn Error>> {
+        use self::model::Primitive;
+        use intro_runtime::mesh::Mesh;
+        use intro_runtime::model::Model;
+        use intro_runtime::shapes;
+
+        let primitive = match model_data.primitive {
+            Some(x) => x,
+            None => return Err(Box::new(ToolError::MissingPrimitive)),
+        };
+
+        let (vertices, indices) = match primitive {
+            Primitive::UvSphere {
+                radius,
+                segments,
+                rings,
+            } => shapes::uv_sphere(radius, segments, rings),
+            Primitive::Icosphere {
+                radius,
+                subdivisions,
+            } => shapes::icosphere(radius, subdivisions),
+            Primitive::Plane {
+                width,
+                depth,
+                segments_x,
+                segments_z,
+            } => shapes::plane(width, depth, segments_x, segments_z),
+            Primitive::Torus {
+                major_radius,
+                minor_radius,
+                segments,
+                sides,
+            } => shapes::torus(major_radius, minor_radius, segments, sides),
+            Primitive::Cylinder {
+                radius,
+                height,
+                segments,
+            } => shapes::cylinder(radius, height, segments),
+            Primitive::Cone {
+                radius,
+                height,
+                segments,
+            } => shapes::cone(radius, height, segments),
+        };
+
+        let mut mesh = Mesh::default();
+        mesh.vertices = vertices;
+        mesh.indices = indices;
+        mesh.compute_tangents();
+        if model_data.flip_faces {
+            mesh.flip_faces();
+        }
+
+        mesh.vert_src_idx = self
+            .context
+            .index
+            .get_shader_index(&model_data.vert_src_path)?;
+        mesh.frag_src_idx = self
+            .context
+            .index
+            .get_shader_index(&model_data.frag_src_path)?;
+
+        let mut model = Model::empty_primitive();
         model.meshes.push(mesh);
 
         dmo_gfx.context.polygon_context.models.push(model);
@@ -380,9 +520,13 @@ impl DmoData {
         project_root: &Option<PathBuf>,
         embedded: bool,
     ) -> Result<(), Box<dyn Error>> {
+        use self::model::ComputedNormals;
+        use self::obj_material::{
+            load_mtl, material_buffer_name, material_textures, obj_dir, read_asset_text,
+        };
         use intro_runtime::mesh::Mesh;
         use intro_runtime::model::Model;
-        use intro_runtime::types::Vertex;
+        use intro_runtime::types::{BufferMapping, Material, Vertex, VertexLayout};
 
         let project_root = if let Some(p) = project_root {
             p
@@ -408,49 +552,106 @@ impl DmoData {
         // Add meshes.
         {
             let p = project_root.join(PathBuf::from(&model_data.obj_path));
-            let (meshes, _materials) = if embedded {
-                let text = get_template_asset_string(&p)?;
-                let mut reader = BufReader::new(text.as_bytes());
-                tobj::load_obj_buf(&mut reader, |_p| Ok((Vec::new(), HashMap::new())))?
-            } else {
-                tobj::load_obj(&p)?
-            };
+            let obj_dir = obj_dir(&model_data.obj_path);
+            let text = read_asset_text(&p, embedded)?;
+            let mut reader = BufReader::new(text.as_bytes());
+            let (meshes, materials) = tobj::load_obj_buf(&mut reader, |mtl_path| {
+                Ok(load_mtl(&obj_dir.join(mtl_path), project_root, embedded))
+            })?;
 
             for i in meshes.iter() {
                 let mesh = &i.mesh;
                 let mut new_mesh = Mesh::default();
 
-                // Serializing each vertex per index.
-                // This will be a vertex array, not an indexed object using EBO.
+                // tobj gives one index per vertex, which indexes the positions, normals and
+                // texcoords alike. Upload the vertices once and draw them with the indices.
 
-                let n_index = mesh.indices.len();
-                if n_index > std::u32::MAX as usize {
-                    panic! {"Index list must not be over u32 max"};
-                }
-
-                // Add vertex data.
-                for index in mesh.indices.iter() {
-                    let i = *index as usize;
+                let n_vertices = mesh.positions.len() / 3;
+                let has_normals = mesh.normals.len() == 3 * n_vertices;
+                let has_texcoords = mesh.texcoords.len() == 2 * n_vertices;
 
+                for i in 0..n_vertices {
                     let position: [f32; 3] = [
                         mesh.positions[3 * i],
                         mesh.positions[3 * i + 1],
                         mesh.positions[3 * i + 2],
                     ];
 
-                    let normal: [f32; 3] = [
-                        mesh.normals[3 * i],
-                        mesh.normals[3 * i + 1],
-                        mesh.normals[3 * i + 2],
-                    ];
+                    let normal: [f32; 3] = if has_normals {
+                        [
+                            mesh.normals[3 * i],
+                            mesh.normals[3 * i + 1],
+                            mesh.normals[3 * i + 2],
+                        ]
+                    } else {
+                        [0.0; 3]
+                    };
+
+                    let texcoords: [f32; 2] = if has_texcoords {
+                        [mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]]
+                    } else {
+                        [0.0; 2]
+                    };
 
-                    let vertex = Vertex {
+                    new_mesh.vertices.push(Vertex {
                         position,
                         normal,
-                        texcoords: [0.0; 2], // TODO UV texcoords
-                    };
+                        texcoords,
+                        tangent: [0.0; 3],
+                        bitangent: [0.0; 3],
+                    });
+                }
+
+                if let Some(index) = mesh.indices.iter().find(|&&x| x as usize >= n_vertices) {
+                    return Err(From::from(format! {
+                        "Vertex index {} is out of bounds in {}", index, model_data.obj_path
+                    }));
+                }
+                new_mesh.indices = mesh.indices.clone();
+
+                new_mesh.layout = VertexLayout {
+                    normal: true,
+                    texcoords: has_texcoords,
+                    tangents: false,
+                };
+
+                if !has_normals {
+                    match model_data.computed_normals {
+                        ComputedNormals::Smooth => new_mesh.compute_smooth_normals(),
+                        ComputedNormals::Flat => new_mesh.compute_flat_normals(),
+                    }
+                }
+
+                new_mesh.compute_tangents();
+                if model_data.flip_faces {
+                    new_mesh.flip_faces();
+                }
 
-                    new_mesh.vertices.push(vertex);
+                // The material textures were added as image frame buffers when building the
+                // index.
+                if let Some(material) = mesh.material_id.and_then(|id| materials.get(id)) {
+                    new_mesh.material = Some(Material {
+                        ambient: material.ambient,
+                        diffuse: material.diffuse,
+                        specular: material.specular,
+                        shininess: material.shininess,
+                        opacity: material.dissolve,
+                    });
+
+                    for (binding_idx, path) in material_textures(material) {
+                        let buffer_idx = self
+                            .context
+                            .index
+                            .get_buffer_index(&material_buffer_name(path))?;
+                        if buffer_idx > usize::from(u8::MAX) {
+                            return Err(From::from(format! {
+                                "Too many frame buffers for the texture {}", path
+                            }));
+                        }
+                        new_mesh
+                            .binding_to_buffers
+                            .push(BufferMapping::Sampler2D(binding_idx, buffer_idx as u8));
+                    }
                 }
 
                 // Use the shaders specified on the model for each mesh.
@@ -465,6 +666,56 @@ impl DmoData {
 
         Ok(())
     }
+
+    pub fn add_model_gltf_to(
+        &self,
+        dmo_gfx: &mut DmoGfx,
+        model_data: &self::model::Model,
+        project_root: &Option<PathBuf>,
+        embedded: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        use intro_runtime::model::Model;
+
+        let project_root = if let Some(p) = project_root {
+            p
+        } else {
+            return Err(Box::new(ToolError::MissingProjectRoot));
+        };
+
+        if model_data.gltf_path.is_empty() {
+            return Err(Box::new(ToolError::MissingGltfPath));
+        }
+
+        let mut model = Model::empty_gltf();
+
+        let vert_src_idx = self
+            .context
+            .index
+            .get_shader_index(&model_data.vert_src_path)?;
+        let frag_src_idx = self
+            .context
+            .index
+            .get_shader_index(&model_data.frag_src_path)?;
+
+        let p = project_root.join(PathBuf::from(&model_data.gltf_path));
+        let meshes = gltf_model::load_gltf_meshes(&p, embedded, model_data.computed_normals)
+            .map_err(|e| format! {"Error loading {}: {}", model_data.gltf_path, e})?;
+
+        for mut mesh in meshes.into_iter() {
+            // Use the shaders specified on the model for each mesh.
+            mesh.vert_src_idx = vert_src_idx;
+            mesh.frag_src_idx = frag_src_idx;
+            if model_data.flip_faces {
+                mesh.flip_faces();
+            }
+
+            model.meshes.push(mesh);
+        }
+
+        dmo_gfx.context.polygon_context.models.push(model);
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -474,6 +725,13 @@ pub struct Settings {
     pub mouse_sensitivity: f32,
     pub movement_sensitivity: f32,
     pub total_length: f64,
+    /// Beats per minute for the timing in bars and beats. Defaults to the bpm of the Rocket sync
+    /// tracks.
+    #[serde(default)]
+    pub bpm: Option<f64>,
+    /// Loop to the start at `total_length`, otherwise stop there.
+    #[serde(default)]
+    pub loop_at_end: bool,
 }
 
 impl Default for Settings {
@@ -484,6 +742,8 @@ impl Default for Settings {
             mouse_sensitivity: 0.5,
             movement_sensitivity: 0.5,
             total_length: 60.0,
+            bpm: None,
+            loop_at_end: false,
         }
     }
 }
@@ -520,6 +780,13 @@ pub enum ValueVec3 {
     Fixed(f32, f32, f32),
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ValueVec4 {
+    NOOP,
+    Sync(BuiltIn, BuiltIn, BuiltIn, BuiltIn),
+    Fixed(f32, f32, f32, f32),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ValueFloat {
     NOOP,
@@ -534,12 +801,16 @@ pub enum UniformMapping {
     Vec2(u8, BuiltIn, BuiltIn),
     Vec3(u8, BuiltIn, BuiltIn, BuiltIn),
     Vec4(u8, BuiltIn, BuiltIn, BuiltIn, BuiltIn),
+    /// The `mat4` light space matrix of the shadow map of a light: (layout_idx, light_idx)
+    LightSpaceMatrix(u8, u8),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum BufferMapping {
     NOOP,
     Sampler2D(u8, String),
+    /// The depth texture of the shadow map of a light: (layout_idx, light_idx)
+    ShadowMap(u8, u8),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -586,5 +857,16 @@ pub enum BuiltIn {
     Light_Quadratic_Falloff,
     Light_Cutoff_Angle,
 
+    Block_Time,
+    Block_Progress,
+    Block_Fade,
+
+    Beat,
+    Bar,
+    Beat_Phase,
+
+    Frame_Index,
+    Delta_Time,
+
     Custom(String),
 }
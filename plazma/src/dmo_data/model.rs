@@ -4,7 +4,23 @@ pub struct Model {
     pub model_type: ModelType,
     pub vert_src_path: String,
     pub frag_src_path: String,
+    /// Path of the `.obj` file when the model type is `Obj`. The diffuse, normal and specular
+    /// maps of its `.mtl` materials are bound at 0, 1 and 2, and the material colors are set on
+    /// the `material_*` uniforms.
+    #[serde(default)]
     pub obj_path: String,
+    /// Path of the `.gltf` or `.glb` file when the model type is `Gltf`.
+    #[serde(default)]
+    pub gltf_path: String,
+    /// Shape and parameters when the model type is `Primitive`.
+    #[serde(default)]
+    pub primitive: Option<Primitive>,
+    /// Turn the faces inside out, to see the model from the inside such as a room or a sky dome.
+    #[serde(default)]
+    pub flip_faces: bool,
+    /// How to compute the normals of meshes which don't have them in the OBJ or glTF file.
+    #[serde(default)]
+    pub computed_normals: ComputedNormals,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
@@ -12,4 +28,61 @@ pub enum ModelType {
     NOOP,
     Cube,
     Obj,
+    Gltf,
+    Primitive,
+}
+
+/// Procedural shapes with normals and texcoords, centered on the origin. The segment counts are
+/// the number of faces around (and along) the surface.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum Primitive {
+    UvSphere {
+        radius: f32,
+        segments: u32,
+        rings: u32,
+    },
+    Icosphere {
+        radius: f32,
+        subdivisions: u32,
+    },
+    /// A grid on the XZ plane facing up.
+    Plane {
+        width: f32,
+        depth: f32,
+        segments_x: u32,
+        segments_z: u32,
+    },
+    /// A ring around the Y axis.
+    Torus {
+        major_radius: f32,
+        minor_radius: f32,
+        segments: u32,
+        sides: u32,
+    },
+    /// Along the Y axis, with caps.
+    Cylinder {
+        radius: f32,
+        height: f32,
+        segments: u32,
+    },
+    /// Along the Y axis pointing up, with the base cap.
+    Cone {
+        radius: f32,
+        height: f32,
+        segments: u32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum ComputedNormals {
+    /// Average the normals of the faces around each vertex.
+    Smooth,
+    /// Use the face normal on each face, for a faceted look.
+    Flat,
+}
+
+impl Default for ComputedNormals {
+    fn default() -> ComputedNormals {
+        ComputedNormals::Smooth
+    }
 }
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use tobj;
+
+use crate::project_data::get_template_asset_string;
+use crate::utils::file_to_string;
+
+/// Texture bindings of the material maps, the same as the base color and normal map of glTF
+/// meshes.
+pub const DIFFUSE_BINDING: u8 = 0;
+pub const NORMAL_BINDING: u8 = 1;
+pub const SPECULAR_BINDING: u8 = 2;
+
+/// Name of the image frame buffer which is added for a material texture.
+pub fn material_buffer_name(image_path: &str) -> String {
+    format! {"mtl:{}", image_path}
+}
+
+/// The textures of the material with their binding, leaving out the maps it doesn't have.
+pub fn material_textures(material: &tobj::Material) -> Vec<(u8, &str)> {
+    [
+        (DIFFUSE_BINDING, &material.diffuse_texture),
+        (NORMAL_BINDING, &material.normal_texture),
+        (SPECULAR_BINDING, &material.specular_texture),
+    ]
+    .iter()
+    .filter(|(_, path)| !path.is_empty())
+    .map(|(binding_idx, path)| (*binding_idx, path.as_str()))
+    .collect()
+}
+
+/// Reads an OBJ or MTL file, from the template assets when embedded.
+pub fn read_asset_text(path: &PathBuf, embedded: bool) -> Result<String, Box<dyn Error>> {
+    if embedded {
+        get_template_asset_string(path)
+    } else {
+        file_to_string(path)
+    }
+}
+
+/// Loads the materials of the `mtllib` files in the OBJ text, in the order tobj would.
+/// `obj_path` is relative to the project root.
+pub fn load_obj_materials(
+    obj_text: &str,
+    obj_path: &str,
+    project_root: &PathBuf,
+    embedded: bool,
+) -> Vec<tobj::Material> {
+    let obj_dir = obj_dir(obj_path);
+    let mut materials: Vec<tobj::Material> = Vec::new();
+
+    for line in obj_text.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("mtllib") {
+            continue;
+        }
+        if let Some(mtl_name) = words.next() {
+            let (mut m, _) = load_mtl(&obj_dir.join(mtl_name), project_root, embedded);
+            materials.append(&mut m);
+        }
+    }
+
+    materials
+}
+
+/// The folder of the OBJ file, which the `mtllib` paths are relative to.
+pub fn obj_dir(obj_path: &str) -> PathBuf {
+    match Path::new(obj_path).parent() {
+        Some(p) => p.to_path_buf(),
+        None => PathBuf::new(),
+    }
+}
+
+/// Loads a material library, with the texture paths made relative to the project root.
+/// `mtl_path` is relative to the project root.
+///
+/// A missing or broken library is only a warning, the meshes are drawn without the materials,
+/// as many exporters write an `mtllib` line anyway.
+pub fn load_mtl(
+    mtl_path: &Path,
+    project_root: &PathBuf,
+    embedded: bool,
+) -> (Vec<tobj::Material>, HashMap<String, usize>) {
+    let p = project_root.join(mtl_path);
+    let text = match read_asset_text(&p, embedded) {
+        Ok(x) => x,
+        Err(e) => {
+            warn! {"Can't read the material library {:?}: {}", mtl_path, e};
+            return (Vec::new(), HashMap::new());
+        }
+    };
+
+    let (mut materials, names) = match tobj::load_mtl_buf(&mut BufReader::new(text.as_bytes())) {
+        Ok(x) => x,
+        Err(e) => {
+            warn! {"Can't load the material library {:?}: {}", mtl_path, e};
+            return (Vec::new(), HashMap::new());
+        }
+    };
+
+    let mtl_dir = match mtl_path.parent() {
+        Some(p) => p.to_path_buf(),
+        None => PathBuf::new(),
+    };
+
+    for m in materials.iter_mut() {
+        for path in [
+            &mut m.diffuse_texture,
+            &mut m.normal_texture,
+            &mut m.specular_texture,
+        ]
+        .iter_mut()
+        {
+            if !path.is_empty() {
+                **path = mtl_dir
+                    .join(path.as_str())
+                    .to_string_lossy()
+                    .replace("\\", "/");
+            }
+        }
+    }
+
+    (materials, names)
+}
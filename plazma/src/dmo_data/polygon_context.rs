@@ -1,12 +1,106 @@
 use crate::dmo_data::model::Model;
+use crate::dmo_data::{ValueFloat, ValueVec3};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PolygonContext {
     pub models: Vec<Model>,
+    /// The lights are available to every polygon shader in the `Lights` uniform block.
+    #[serde(default)]
+    pub lights: Vec<Light>,
+    /// Where the camera takes its view from in each frame.
+    #[serde(default)]
+    pub camera: CameraMode,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum CameraMode {
+    /// Position and front vector from the `Camera_Pos_*` and `Camera_Front_*` tracks.
+    Sync_Front,
+    /// Position, look-at target and up vector from the `Camera_Pos_*`, `Camera_LookAt_*` and
+    /// `Camera_Up_*` tracks.
+    Sync_LookAt,
+    /// Position and look-at target along a spline through the points.
+    Path(CameraPath),
+}
+
+impl Default for CameraMode {
+    fn default() -> CameraMode {
+        CameraMode::Sync_Front
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CameraPath {
+    pub points: Vec<CameraPathPoint>,
+    /// Continue from the last point to the first.
+    #[serde(default)]
+    pub closed: bool,
+    /// From 0.0 at the first point to 1.0 at the last, or back at the first when closed.
+    pub progress: ValueFloat,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CameraPathPoint {
+    pub position: [f32; 3],
+    pub look_at: [f32; 3],
+}
+
+/// A light of the scene. The values which are not given keep the defaults of the runtime
+/// `Light`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Light {
+    pub light_type: LightType,
+    #[serde(default)]
+    pub position: Option<ValueVec3>,
+    #[serde(default)]
+    pub direction: Option<ValueVec3>,
+    #[serde(default)]
+    pub color: Option<ValueVec3>,
+    #[serde(default)]
+    pub strength: Option<ValueFloat>,
+    /// Constant, linear and quadratic falloff with distance.
+    #[serde(default)]
+    pub falloff: Option<ValueVec3>,
+    /// Angles of the spot light cone from its direction, in degrees.
+    #[serde(default)]
+    pub cutoff_angle: Option<ValueFloat>,
+    #[serde(default)]
+    pub outer_cutoff_angle: Option<ValueFloat>,
+    /// Draw the scene depth from the light into a shadow map before each polygon scene.
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+}
+
+/// The shadow map of a directional or spot light. Shaders read it with the `LightSpaceMatrix`
+/// uniform mapping and the `ShadowMap` buffer mapping of the light index.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Shadow {
+    /// Width and height of the depth texture.
+    pub resolution: u32,
+    /// Depth bias for the shaders, in `lights[i].shadow.y` of the `Lights` uniform block.
+    pub bias: f32,
+    /// Half of the width and height of the box which a directional light casts shadows in.
+    #[serde(default)]
+    pub extent: Option<f32>,
+    #[serde(default)]
+    pub znear: Option<f32>,
+    #[serde(default)]
+    pub zfar: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LightType {
+    Point,
+    Directional,
+    Spot,
 }
 
 impl Default for PolygonContext {
     fn default() -> PolygonContext {
-        PolygonContext { models: vec![] }
+        PolygonContext {
+            models: vec![],
+            lights: vec![],
+            camera: CameraMode::default(),
+        }
     }
 }
@@ -1,4 +1,12 @@
-use crate::dmo_data::{BufferMapping, UniformMapping, ValueFloat, ValueVec3};
+use std::error::Error;
+use std::path::PathBuf;
+
+use intro_3d::lib::Vector3;
+use intro_runtime::instances::{grid_transforms, random_transforms, ring_transforms, transform};
+
+use crate::dmo_data::obj_material::read_asset_text;
+use crate::dmo_data::{BufferMapping, UniformMapping, ValueFloat, ValueVec3, ValueVec4};
+use crate::error::ToolError;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PolygonScene {
@@ -8,12 +16,169 @@ pub struct PolygonScene {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SceneObject {
+    /// Name of the model to draw.
     pub name: String,
+    /// Name of this object for the `parent` of other objects in the scene.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// The `id` of the parent object. The position, rotation and scale are relative to the
+    /// parent, and the object moves with it.
+    #[serde(default)]
+    pub parent: Option<String>,
+    /// Draw copies of the model with one draw call, each with a transform from the layout.
+    #[serde(default)]
+    pub instances: Option<Instances>,
+    /// Draw the object into the shadow maps of the lights.
+    #[serde(default = "default_casts_shadow")]
+    pub casts_shadow: bool,
+    /// Skip drawing the object when its model is outside the view. Turn it off when the vertex
+    /// shader moves the vertices outside the model.
+    #[serde(default = "default_frustum_culling")]
+    pub frustum_culling: bool,
     pub position: ValueVec3,
     pub euler_rotation: ValueVec3,
+    /// Rotation as a quaternion (x, y, z, w), used instead of the Euler rotation when given.
+    #[serde(default)]
+    pub quaternion_rotation: Option<ValueVec4>,
     pub scale: ValueFloat,
+    #[serde(default)]
     pub layout_to_vars: Vec<UniformMapping>,
+    #[serde(default)]
     pub binding_to_buffers: Vec<BufferMapping>,
+    /// Bind active uniforms to tracks with a matching name, see
+    /// `QuadScene::bind_uniforms_by_name`.
+    #[serde(default)]
+    pub bind_uniforms_by_name: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Instances {
+    pub layout: InstanceLayout,
+    /// Number of instances to draw, from the first ones in the layout. Draws all of them when
+    /// not given.
+    #[serde(default)]
+    pub count: Option<ValueFloat>,
+}
+
+/// Where the instance transforms come from. They are relative to the scene object.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum InstanceLayout {
+    /// `columns` along X, `rows` along Z and `layers` along Y, centered on the object.
+    Grid {
+        columns: u32,
+        rows: u32,
+        #[serde(default = "default_layers")]
+        layers: u32,
+        spacing: f32,
+    },
+    /// A ring around the Y axis, each instance facing out.
+    Ring { count: u32, radius: f32 },
+    /// Random positions within `-extent..extent`, the same for the same seed.
+    Random {
+        count: u32,
+        seed: u64,
+        extent: [f32; 3],
+        #[serde(default)]
+        rotate: bool,
+    },
+    /// A text file with an instance on each line: the position `x y z`, optionally followed by
+    /// the rotation `x y z` in degrees and the scale. Lines starting with `#` are comments.
+    Data { path: String },
+}
+
+fn default_casts_shadow() -> bool {
+    true
+}
+
+fn default_frustum_culling() -> bool {
+    true
+}
+
+fn default_layers() -> u32 {
+    1
+}
+
+impl InstanceLayout {
+    /// The column-major model matrices of the instances.
+    pub fn transforms(
+        &self,
+        project_root: &Option<PathBuf>,
+        embedded: bool,
+    ) -> Result<Vec<[[f32; 4]; 4]>, Box<dyn Error>> {
+        let transforms = match *self {
+            InstanceLayout::Grid {
+                columns,
+                rows,
+                layers,
+                spacing,
+            } => grid_transforms(columns, rows, layers, spacing),
+
+            InstanceLayout::Ring { count, radius } => ring_transforms(count, radius),
+
+            InstanceLayout::Random {
+                count,
+                seed,
+                extent,
+                rotate,
+            } => random_transforms(count, seed, extent, rotate),
+
+            InstanceLayout::Data { ref path } => {
+                let p = match project_root {
+                    Some(project_root) => project_root.join(PathBuf::from(path)),
+                    None => return Err(Box::new(ToolError::MissingProjectRoot)),
+                };
+                parse_instance_data(&read_asset_text(&p, embedded)?)
+                    .map_err(|e| format! {"Error reading {}: {}", path, e})?
+            }
+        };
+
+        Ok(transforms)
+    }
+}
+
+fn parse_instance_data(text: &str) -> Result<Vec<[[f32; 4]; 4]>, Box<dyn Error>> {
+    let mut transforms: Vec<[[f32; 4]; 4]> = Vec::new();
+
+    for (line_idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut v: Vec<f32> = Vec::new();
+        for word in line.split_whitespace() {
+            match word.parse::<f32>() {
+                Ok(x) => v.push(x),
+                Err(_) => {
+                    return Err(From::from(format! {
+                        "Not a number on line {}: {}", line_idx + 1, word
+                    }))
+                }
+            }
+        }
+
+        let (position, rotation, scale) = match v.len() {
+            3 => (
+                Vector3::new(v[0], v[1], v[2]),
+                Vector3::new(0.0, 0.0, 0.0),
+                1.0,
+            ),
+            6 | 7 => (
+                Vector3::new(v[0], v[1], v[2]),
+                Vector3::new(v[3].to_radians(), v[4].to_radians(), v[5].to_radians()),
+                if v.len() == 7 { v[6] } else { 1.0 },
+            ),
+            _ => {
+                return Err(From::from(format! {
+                    "Expected 3, 6 or 7 numbers on line {}", line_idx + 1
+                }))
+            }
+        };
+
+        transforms.push(transform(&position, &rotation, scale));
+    }
+
+    Ok(transforms)
 }
 
 impl Default for PolygonScene {
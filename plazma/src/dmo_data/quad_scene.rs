@@ -11,11 +11,20 @@ pub struct QuadScene {
 
     /// Which index in `ContextData.sync_vars[]` corresponds to a uniform layout
     /// binding in the fragment shader.
+    #[serde(default)]
     pub layout_to_vars: Vec<UniformMapping>,
 
     /// Which index in `ContextData.frame_buffers[]` corresponds to a texture
     /// binding in the fragment shader.
+    #[serde(default)]
     pub binding_to_buffers: Vec<BufferMapping>,
+
+    /// Bind active uniforms to tracks with a matching name, such as `uniform
+    /// float Time;` or `uniform vec3 cube1_pos;` for the `cube1#pos.x`,
+    /// `cube1#pos.y`, `cube1#pos.z` tracks. Mappings in `layout_to_vars`
+    /// still apply and take precedence.
+    #[serde(default)]
+    pub bind_uniforms_by_name: bool,
 }
 
 impl Default for QuadScene {
@@ -32,6 +41,7 @@ impl QuadScene {
             frag_src_path: "".to_string(),
             layout_to_vars: vec![],
             binding_to_buffers: vec![],
+            bind_uniforms_by_name: false,
         }
     }
 
@@ -47,6 +57,7 @@ impl QuadScene {
                 UniformMapping::Vec2(1, Screen_Width, Screen_Height),
             ],
             binding_to_buffers: vec![BufferMapping::Sampler2D(0, "RESULT_IMAGE".to_string())],
+            bind_uniforms_by_name: false,
         }
     }
 }
@@ -1,42 +1,335 @@
+use std::error::Error;
+
+use intro_runtime::dmo_sync::BEATS_PER_BAR;
+use intro_runtime::timeline::TimeTrack as RuntimeTrack;
+
+use crate::dmo_data::BuiltIn;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Timeline {
     pub tracks: Vec<TimeTrack>,
+    #[serde(default)]
+    pub compositions: Vec<Composition>,
+    #[serde(default)]
+    pub markers: Vec<Marker>,
+    #[serde(default)]
+    pub loop_region: Option<LoopRegion>,
+}
+
+/// A named point in time, such as "drop" or "outro".
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Marker {
+    pub name: String,
+    pub time: TimePos,
+}
+
+/// The preview cycles through this region while playing.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoopRegion {
+    pub start: TimePos,
+    pub end: TimePos,
+}
+
+impl Timeline {
+    /// Sets the flags of a track, or of a block on the track when `block_idx` is given.
+    /// `composition` names the composition which has the track, or is `None` for the tracks of
+    /// the timeline.
+    pub fn set_flags(
+        &mut self,
+        composition: &Option<String>,
+        track_idx: usize,
+        block_idx: Option<usize>,
+        flags: TimelineFlags,
+    ) -> Result<(), Box<dyn Error>> {
+        let compositions = self
+            .compositions
+            .iter_mut()
+            .map(|c| (&c.name, &mut c.tracks));
+        set_track_flags(
+            &mut self.tracks,
+            compositions,
+            composition,
+            track_idx,
+            block_idx,
+            flags,
+        )
+    }
+
+    /// True when a block, composition instance, marker or the loop region is placed in bars and
+    /// beats, which needs a bpm to resolve.
+    pub fn uses_musical_time(&self) -> bool {
+        let composition_tracks = self.compositions.iter().flat_map(|c| c.tracks.iter());
+        let in_tracks = self.tracks.iter().chain(composition_tracks).any(|track| {
+            track
+                .scene_blocks
+                .iter()
+                .any(|b| b.start.is_musical() || b.end.is_musical())
+                || track.instances.iter().any(|x| {
+                    let duration_is_musical = match x.duration {
+                        Some(ref d) => d.is_musical(),
+                        None => false,
+                    };
+                    x.start.is_musical() || duration_is_musical
+                })
+        });
+        let in_markers = self.markers.iter().any(|m| m.time.is_musical());
+        let in_loop_region = match self.loop_region {
+            Some(ref r) => r.start.is_musical() || r.end.is_musical(),
+            None => false,
+        };
+        in_tracks || in_markers || in_loop_region
+    }
 }
 
 impl Default for Timeline {
     fn default() -> Timeline {
         let mut track = TimeTrack {
             scene_blocks: vec![],
+            instances: vec![],
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         let scene = SceneBlock {
-            start: 0.0,
-            end: 60.0,
+            start: TimePos::Seconds(0.0),
+            end: TimePos::Seconds(60.0),
             draw_ops: vec![
                 DrawOp::Target_Buffer_Default,
                 // #4682B4, Steel Blue
                 DrawOp::Clear(70, 130, 180, 0),
             ],
+            fade_in: 0.0,
+            fade_out: 0.0,
+            transition: Transition::default(),
+            enabled: true,
+            muted: false,
+            solo: false,
         };
 
         track.scene_blocks.push(scene);
 
         Timeline {
             tracks: vec![track],
+            compositions: vec![],
+            markers: vec![],
+            loop_region: None,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TimeTrack {
+    #[serde(default)]
     pub scene_blocks: Vec<SceneBlock>,
+    #[serde(default)]
+    pub instances: Vec<CompositionInstance>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub muted: bool,
+    /// When a track is soloed, only the soloed tracks among its sibling tracks are drawn.
+    #[serde(default)]
+    pub solo: bool,
+}
+
+/// A sub-timeline which is defined once and placed on tracks by name. The times of its blocks
+/// are local to each instance, so editing the composition updates every instance.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Composition {
+    pub name: String,
+    pub tracks: Vec<TimeTrack>,
+}
+
+/// Places a composition on a track, e.g.
+/// `{ composition: "strobe", start: { bar: 8 }, time_scale: 2.0, duration: 4.0 }`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompositionInstance {
+    pub composition: String,
+    pub start: TimePos,
+    /// Multiplies the time since `start` to get the local time of the composition. Defaults to
+    /// 1.0.
+    #[serde(default)]
+    pub time_scale: Option<f64>,
+    /// Clips the instance after this length of time.
+    #[serde(default)]
+    pub duration: Option<TimePos>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SceneBlock {
-    pub start: f64,
-    pub end: f64,
+    pub start: TimePos,
+    pub end: TimePos,
     pub draw_ops: Vec<DrawOp>,
+    /// Seconds to fade in from the start of the block.
+    #[serde(default)]
+    pub fade_in: f64,
+    /// Seconds to fade out before the end of the block.
+    #[serde(default)]
+    pub fade_out: f64,
+    #[serde(default)]
+    pub transition: Transition,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub muted: bool,
+    /// When a block is soloed, only the soloed blocks on its track are drawn.
+    #[serde(default)]
+    pub solo: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// The debugging flags of a track or a block. A disabled or muted item is not drawn, and when an
+/// item is soloed, only the soloed items among its siblings are drawn.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct TimelineFlags {
+    pub enabled: bool,
+    pub muted: bool,
+    pub solo: bool,
+}
+
+/// A track which has the flags of `TimelineFlags`, on itself and on its blocks. Both the tracks
+/// of the project data and the tracks of the runtime timeline have them.
+pub trait FlaggedTrack {
+    fn set_flags(&mut self, flags: TimelineFlags);
+
+    /// Returns false when there is no block at `block_idx`.
+    fn set_block_flags(&mut self, block_idx: usize, flags: TimelineFlags) -> bool;
+}
+
+impl FlaggedTrack for TimeTrack {
+    fn set_flags(&mut self, flags: TimelineFlags) {
+        self.enabled = flags.enabled;
+        self.muted = flags.muted;
+        self.solo = flags.solo;
+    }
+
+    fn set_block_flags(&mut self, block_idx: usize, flags: TimelineFlags) -> bool {
+        match self.scene_blocks.get_mut(block_idx) {
+            Some(block) => {
+                block.enabled = flags.enabled;
+                block.muted = flags.muted;
+                block.solo = flags.solo;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl FlaggedTrack for RuntimeTrack {
+    fn set_flags(&mut self, flags: TimelineFlags) {
+        self.enabled = flags.enabled;
+        self.muted = flags.muted;
+        self.solo = flags.solo;
+    }
+
+    fn set_block_flags(&mut self, block_idx: usize, flags: TimelineFlags) -> bool {
+        match self.scene_blocks.get_mut(block_idx) {
+            Some(block) => {
+                block.enabled = flags.enabled;
+                block.muted = flags.muted;
+                block.solo = flags.solo;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Sets the flags of a track, or of a block on the track when `block_idx` is given.
+/// `composition` names one of the `compositions` to look up the track in, or is `None` for
+/// `tracks`.
+pub fn set_track_flags<'a, T, C>(
+    tracks: &'a mut Vec<T>,
+    compositions: C,
+    composition: &Option<String>,
+    track_idx: usize,
+    block_idx: Option<usize>,
+    flags: TimelineFlags,
+) -> Result<(), Box<dyn Error>>
+where
+    T: FlaggedTrack + 'a,
+    C: Iterator<Item = (&'a String, &'a mut Vec<T>)>,
+{
+    let mut compositions = compositions;
+    let tracks = match composition {
+        Some(name) => match compositions.find(|(n, _)| *n == name) {
+            Some((_, x)) => x,
+            None => return Err(From::from(format! {"no such composition: {}", name})),
+        },
+        None => tracks,
+    };
+
+    let track = match tracks.get_mut(track_idx) {
+        Some(x) => x,
+        None => return Err(From::from(format! {"no such track: {}", track_idx})),
+    };
+
+    match block_idx {
+        Some(idx) => {
+            if !track.set_block_flags(idx, flags) {
+                return Err(From::from(format! {"no such block: {}", idx}));
+            }
+        }
+        None => track.set_flags(flags),
+    }
+
+    Ok(())
+}
+
+/// A point on the timeline in seconds, or in bars and beats resolved with the project bpm.
+///
+/// In YAML, a number is in seconds (`start: 12.5`), a map is in musical time (`start: { bar: 8 }`,
+/// `start: { bar: 8, beat: 2 }` or `start: { beat: 34 }`). Bars and beats count from zero.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(untagged)]
+pub enum TimePos {
+    Seconds(f64),
+    Musical(MusicalTime),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MusicalTime {
+    #[serde(default)]
+    pub bar: f64,
+    #[serde(default)]
+    pub beat: f64,
+}
+
+impl TimePos {
+    pub fn is_musical(&self) -> bool {
+        match self {
+            TimePos::Seconds(_) => false,
+            TimePos::Musical(_) => true,
+        }
+    }
+
+    pub fn to_seconds(&self, bpm: f64) -> f64 {
+        match self {
+            TimePos::Seconds(x) => *x,
+            TimePos::Musical(t) => (t.bar * BEATS_PER_BAR + t.beat) * 60.0 / bpm,
+        }
+    }
+}
+
+/// Blends a fading block over the image of the blocks drawn before it. `Custom` names a quad scene
+/// which samples the image below on binding 0 and the block on binding 1.
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Transition {
+    Crossfade,
+    Wipe,
+    Custom(String),
+}
+
+impl Default for Transition {
+    fn default() -> Transition {
+        Transition::Crossfade
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -48,4 +341,15 @@ pub enum DrawOp {
     Target_Buffer(String),
     Target_Buffer_Default,
     Profile(String),
+    /// Draws the quad scene when the sync var meets the condition with the value.
+    If_Var_Draw_Quad_Scene(BuiltIn, VarCondition, f64, String),
+    /// Draws the polygon scene when the sync var meets the condition with the value.
+    If_Var_Draw_Poly_Scene(BuiltIn, VarCondition, f64, String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum VarCondition {
+    Equal,
+    Above,
+    Below,
 }
@@ -13,6 +13,8 @@ pub enum ToolError {
     UiError(String),
     MissingProjectRoot,
     MissingObjectPath,
+    MissingGltfPath,
+    MissingPrimitive,
     MissingTemplateAssetPath(String),
 }
 
@@ -54,6 +56,8 @@ impl fmt::Debug for ToolError {
                 NoId => "No Id",
                 NoFbo => "No FBO",
                 NoUbo => "No UBO",
+                NoSsbo => "No SSBO",
+                NoShadowMap => "No shadow map",
                 NoQuad => "No Quad",
                 TextureBindingIdxIsOverTheHardwareLimit => {
                     "Texture binding idx is over the hardware limit"
@@ -66,6 +70,8 @@ impl fmt::Debug for ToolError {
                 TrackIdxIsOutOfBounds => "Track idx is out of bounds",
                 VarIdxIsOutOfBounds => "Var idx is out of bounds",
                 DataIdxIsOutOfBounds => "Data idx is out of bounds",
+                ParentIdxIsOutOfBounds => "Parent idx is out of bounds",
+                ParentsFormACycle => "The parents form a cycle",
                 CantOpenImage => "Can't open image",
             },
 
@@ -76,6 +82,8 @@ impl fmt::Debug for ToolError {
             ToolError::NameAlreadyExists => "Name already exists",
             ToolError::MissingProjectRoot => "Missing project root",
             ToolError::MissingObjectPath => "Missing object path",
+            ToolError::MissingGltfPath => "Missing glTF path",
+            ToolError::MissingPrimitive => "Missing primitive",
             ToolError::MissingTemplateAssetPath(_) => "Missing template asset path",
         };
 
@@ -105,6 +113,8 @@ impl error::Error for ToolError {
             ToolError::NameAlreadyExists => "Name already exists",
             ToolError::MissingProjectRoot => "Missing project root",
             ToolError::MissingObjectPath => "Missing object path",
+            ToolError::MissingGltfPath => "Missing glTF path",
+            ToolError::MissingPrimitive => "Missing primitive",
             ToolError::MissingTemplateAssetPath(_) => "Missing template asset path",
         }
     }
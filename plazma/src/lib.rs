@@ -24,6 +24,8 @@ extern crate glutin;
 
 extern crate image;
 extern crate tobj;
+extern crate gltf;
+extern crate base64;
 
 extern crate intro_3d;
 extern crate intro_runtime;
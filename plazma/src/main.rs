@@ -65,7 +65,13 @@ fn main() {
         } else {
             None
         };
-        app::start_preview(port, p).unwrap();
+        app::start_preview(
+            port,
+            p,
+            app_params.trace_frames,
+            app_params.trace_path.clone(),
+        )
+        .unwrap();
     };
 
     // --- HTTP and WebSocket server ---
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use intro_3d::lib::Vector3;
+use rocket_sync::{key_to_code, KeyType, SyncDevice, SyncTrack, TrackKey};
+
+/// The tracks which a recording is written to, in the order of `CameraSample::values()`. Both the
+/// front and the look-at tracks are written, so that the recording plays in either camera mode.
+pub const CAMERA_RECORD_TRACKS: [&str; 9] = [
+    "Camera_Pos_X",
+    "Camera_Pos_Y",
+    "Camera_Pos_Z",
+    "Camera_Front_X",
+    "Camera_Front_Y",
+    "Camera_Front_Z",
+    "Camera_LookAt_X",
+    "Camera_LookAt_Y",
+    "Camera_LookAt_Z",
+];
+
+/// Records the explore mode camera at each Rocket row, and writes it as keys on the camera
+/// position, front and look-at tracks.
+pub struct CameraRecorder {
+    pub samples: Vec<CameraSample>,
+    /// The largest difference from the recorded values which the keys are allowed.
+    pub tolerance: f32,
+}
+
+pub struct CameraSample {
+    pub row: u32,
+    pub position: Vector3,
+    pub front: Vector3,
+    pub look_at: Vector3,
+}
+
+impl CameraSample {
+    pub fn values(&self) -> [f32; 9] {
+        let p = &self.position;
+        let f = &self.front;
+        let t = &self.look_at;
+        [p.x, p.y, p.z, f.x, f.y, f.z, t.x, t.y, t.z]
+    }
+}
+
+impl CameraRecorder {
+    pub fn new(tolerance: f32) -> CameraRecorder {
+        CameraRecorder {
+            samples: Vec::new(),
+            tolerance,
+        }
+    }
+
+    /// Adds a sample when the row has moved on since the last one. While the time is paused or
+    /// moved backwards there is nothing to record.
+    pub fn add_sample(&mut self, row: u32, position: &Vector3, front: &Vector3) {
+        if let Some(last) = self.samples.last() {
+            if row <= last.row {
+                return;
+            }
+        }
+        self.samples.push(CameraSample {
+            row,
+            position: position.clone(),
+            front: front.clone(),
+            look_at: position + front,
+        });
+    }
+
+    /// Replaces the keys in the recorded rows of the camera tracks with the simplified samples.
+    /// Returns the number of keys added.
+    pub fn write_keys(
+        &self,
+        device: &mut SyncDevice,
+        track_name_to_idx: &BTreeMap<String, usize>,
+    ) -> Result<usize, Box<dyn Error>> {
+        let (first_row, last_row) = match (self.samples.first(), self.samples.last()) {
+            (Some(a), Some(b)) => (a.row, b.row),
+            _ => return Ok(0),
+        };
+
+        let mut n_keys = 0;
+
+        for (n, name) in CAMERA_RECORD_TRACKS.iter().enumerate() {
+            let track_idx = match track_name_to_idx.get(*name) {
+                Some(x) => *x,
+                None => return Err(From::from(format! {"Track name not found: {}", name})),
+            };
+
+            while device.tracks.len() <= track_idx {
+                device.tracks.push(SyncTrack::new());
+            }
+            let track = &mut device.tracks[track_idx];
+
+            track.keys.retain(|k| k.row < first_row || k.row > last_row);
+
+            let samples: Vec<(u32, f32)> = self
+                .samples
+                .iter()
+                .map(|s| (s.row, s.values()[n]))
+                .collect();
+
+            for key in simplify_keys(&samples, self.tolerance) {
+                track.add_key(key);
+                n_keys += 1;
+            }
+        }
+
+        Ok(n_keys)
+    }
+}
+
+/// Replaces the camera track elements in the text of a Rocket XML file with the keys of the
+/// tracks in the device, keeping the other tracks and the attributes of the elements. A track
+/// which is not in the file is added at the end.
+pub fn write_rocket_xml(
+    text: &str,
+    device: &SyncDevice,
+    track_name_to_idx: &BTreeMap<String, usize>,
+) -> Result<String, Box<dyn Error>> {
+    let mut text = text.to_owned();
+
+    for name in CAMERA_RECORD_TRACKS.iter() {
+        let keys = match track_name_to_idx
+            .get(*name)
+            .and_then(|idx| device.tracks.get(*idx))
+        {
+            Some(track) => &track.keys,
+            None => return Err(From::from(format! {"Track name not found: {}", name})),
+        };
+
+        let mut key_lines = String::new();
+        for k in keys.iter() {
+            key_lines.push_str(&format! {
+                "\t\t<key row=\"{}\" value=\"{:.6}\" interpolation=\"{}\" />\n",
+                k.row, k.value, key_to_code(&k.key_type)
+            });
+        }
+
+        let open_tag = format! {"<track name=\"{}\"", name};
+        let (start, end, tag) = match text.find(&open_tag) {
+            Some(start) => {
+                let tag_end = match text[start..].find('>') {
+                    Some(x) => start + x,
+                    None => return Err(From::from(format! {"Unclosed track element: {}", name})),
+                };
+                if text[..tag_end].ends_with('/') {
+                    let tag = text[start..tag_end - 1].trim_end().to_owned();
+                    (start, tag_end + 1, tag)
+                } else {
+                    let end = match text[start..].find("</track>") {
+                        Some(x) => start + x + "</track>".len(),
+                        None => {
+                            return Err(From::from(format! {"Unclosed track element: {}", name}))
+                        }
+                    };
+                    (start, end, text[start..tag_end].to_owned())
+                }
+            }
+            None => {
+                let start = match text.rfind("</tracks>") {
+                    Some(x) => x,
+                    None => return Err(From::from("missing 'tracks'")),
+                };
+                text.insert(start, '\n');
+                (start, start, format! {"\t{}", open_tag})
+            }
+        };
+
+        let element = if key_lines.is_empty() {
+            format! {"{} />", tag}
+        } else {
+            format! {"{}>\n{}\t</track>", tag, key_lines}
+        };
+        text.replace_range(start..end, &element);
+    }
+
+    Ok(text)
+}
+
+/// Reduces the `(row, value)` samples to keys which reproduce them within the tolerance.
+///
+/// Between two keys the samples are compared against both linear and smooth interpolation, and
+/// the one with the smaller error is used. When neither is within the tolerance, the segment is
+/// split at the sample which is the furthest from the linear interpolation.
+pub fn simplify_keys(samples: &[(u32, f32)], tolerance: f32) -> Vec<TrackKey> {
+    let mut keys: Vec<TrackKey> = Vec::new();
+
+    let n = samples.len();
+    if n == 0 {
+        return keys;
+    }
+
+    let mut segments: Vec<(usize, usize)> = Vec::new();
+    if n > 1 {
+        segments.push((0, n - 1));
+    }
+
+    while let Some((a, b)) = segments.pop() {
+        let (linear_error, split_idx) = max_error(&samples[a..=b], &KeyType::Linear);
+        let (smooth_error, _) = max_error(&samples[a..=b], &KeyType::Smooth);
+
+        let key_type = if linear_error <= tolerance && linear_error <= smooth_error {
+            KeyType::Linear
+        } else if smooth_error <= tolerance {
+            KeyType::Smooth
+        } else {
+            segments.push((a, a + split_idx));
+            segments.push((a + split_idx, b));
+            continue;
+        };
+
+        keys.push(TrackKey {
+            row: samples[a].0,
+            value: samples[a].1,
+            key_type,
+        });
+    }
+
+    // The value stays after the last key.
+    keys.push(TrackKey {
+        row: samples[n - 1].0,
+        value: samples[n - 1].1,
+        key_type: KeyType::Step,
+    });
+
+    keys.sort_by_key(|k| k.row);
+    keys
+}
+
+/// The largest difference of the samples from the interpolation between the first and the last
+/// one, and the index of the sample where it is.
+fn max_error(samples: &[(u32, f32)], key_type: &KeyType) -> (f32, usize) {
+    let (row_a, a) = samples[0];
+    let (row_b, b) = samples[samples.len() - 1];
+
+    let mut error = 0.0;
+    let mut idx = 0;
+
+    for (i, &(row, value)) in samples.iter().enumerate().skip(1).take(samples.len() - 2) {
+        let t = (row - row_a) as f32 / (row_b - row_a) as f32;
+        let x = match *key_type {
+            KeyType::Smooth => a + (b - a) * (t * t * (3.0 - 2.0 * t)),
+            _ => a + (b - a) * t,
+        };
+        let e = (value - x).abs();
+        if e > error {
+            error = e;
+            idx = i;
+        }
+    }
+
+    (error, idx)
+}
@@ -1,2 +1,4 @@
+pub mod camera_recorder;
 pub mod client_actor;
 pub mod preview_state;
+pub mod trace_capture;
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::error::Error;
+use std::fs;
 use std::path::PathBuf;
 use std::str;
 use std::time::{Duration, Instant};
@@ -8,25 +9,42 @@ use serde_xml::value::{Content, Element};
 
 use glutin::{ElementState, MouseButton, VirtualKeyCode};
 
-use intro_3d::lib::Vector3;
-use intro_runtime::camera::Camera;
+use intro_3d::lib::{Aabb, Vector3};
+use intro_runtime::camera::{Camera, CameraControl};
+use intro_runtime::camera_path::{CameraMode, CameraPath};
 use intro_runtime::dmo_gfx::{DmoGfx, Settings};
 use intro_runtime::frame_buffer::{BufferKind, FrameBuffer};
+use intro_runtime::instances::Instances;
+use intro_runtime::lights::{Light, LightType, MAX_LIGHTS};
 use intro_runtime::mouse::MouseButton as Btn;
 use intro_runtime::polygon_context::PolygonContext;
 use intro_runtime::polygon_scene::{PolygonScene, SceneObject};
+use intro_runtime::shadow_map::ShadowMap;
 use intro_runtime::sync_vars::BuiltIn::*;
-use intro_runtime::timeline::{SceneBlock, TimeTrack, Timeline};
-use intro_runtime::types::{BufferMapping, PixelFormat, UniformMapping, ValueFloat, ValueVec3};
+use intro_runtime::sync_vars::BUILTIN_NAMES;
+use intro_runtime::timeline::{
+    Composition, CompositionInstance, LoopRegion, Marker, SceneBlock, TimeTrack, Timeline,
+    Transition, VarCondition,
+};
+use intro_runtime::types::{
+    BufferMapping, PixelFormat, UniformMapping, ValueFloat, ValueVec3, ValueVec4,
+};
 use intro_runtime::ERR_MSG_LEN;
 
 use rocket_client::SyncClient;
 use rocket_sync::{code_to_key, SyncDevice, SyncTrack, TrackKey};
 
+use crate::dmo_data::timeline as d_timeline;
 use crate::dmo_data::{DmoData, ProjectData};
 use crate::error::ToolError;
+use crate::preview_client::camera_recorder::{self, CameraRecorder};
+use crate::preview_client::trace_capture::{
+    DrawOpNames, TraceCapture, TRACE_CAT_ROCKET, TRACE_CAT_SHADER,
+};
 use crate::project_data::get_template_asset_string;
-use crate::server_actor::SetDmoMsg;
+use crate::server_actor::{
+    ProfileEventMsg, ProfileResultsMsg, SelectedObjectMsg, SetDmoMsg, SetTimelineFlagsMsg,
+};
 use crate::utils::file_to_string;
 
 pub struct PreviewState {
@@ -37,6 +55,11 @@ pub struct PreviewState {
 
     pub pressed_keys: [bool; 1024],
     pub explore_mode: bool,
+    /// The polygon scene index and scene object index of the object which the orbit camera
+    /// frames, see `select_next_object()` and `pick_object_at_mouse()`.
+    pub selected_object: Option<(usize, usize)>,
+    /// Set when `selected_object` changes, until the selection is sent to the GUI.
+    pub selection_changed: bool,
 
     pub draw_anyway: bool,
     pub should_recompile: bool,
@@ -51,9 +74,42 @@ pub struct PreviewState {
     /// Mapping the track names to variable indexes in `dmo_gfx.sync_vars`.
     pub track_name_to_idx: BTreeMap<String, usize>,
 
+    /// The labels of the Profile draw ops, indexed by the label index.
+    pub profile_names: Vec<String>,
+    /// The names of the timeline compositions, indexed by the composition index.
+    pub composition_names: Vec<String>,
+    /// The names of the quad scenes, indexed by the scene index.
+    pub quad_scene_names: Vec<String>,
+    /// The names of the polygon scenes, indexed by the scene index.
+    pub polygon_scene_names: Vec<String>,
+    /// The names of the frame buffers, indexed by the buffer index.
+    pub buffer_names: Vec<String>,
+    /// The `id` of each scene object, or the name of its model when it doesn't have one, indexed
+    /// by the scene index and the object index.
+    pub scene_object_names: Vec<Vec<String>>,
+    pub t_profile_last_sent: Instant,
+
+    /// The bpm which resolved the musical time of the timeline.
+    pub bpm: f64,
+
+    /// Set while a trace is being captured, see `start_trace_capture()`.
+    pub trace_capture: Option<TraceCapture>,
+
+    /// Set while the explore mode camera is being recorded, see `toggle_camera_recording()`.
+    pub camera_recorder: Option<CameraRecorder>,
+    /// The Rocket XML file of the sync tracks, when it is a file which the camera recording can
+    /// be saved to.
+    pub sync_tracks_path: Option<PathBuf>,
+
     pub project_data: ProjectData,
 }
 
+/// How often profiling results are sent to the server.
+const PROFILE_SEND_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The largest difference from the recorded camera which the written keys are allowed.
+const CAMERA_RECORD_TOLERANCE: f32 = 0.01;
+
 impl PreviewState {
     pub fn new(
         demo_yml_path: Option<PathBuf>,
@@ -73,6 +129,8 @@ impl PreviewState {
 
             pressed_keys: [false; 1024],
             explore_mode: false,
+            selected_object: None,
+            selection_changed: false,
 
             draw_anyway: false,
             should_recompile: false,
@@ -89,6 +147,21 @@ impl PreviewState {
             track_names: Vec::new(),
             track_name_to_idx: BTreeMap::new(),
 
+            profile_names: Vec::new(),
+            composition_names: Vec::new(),
+            quad_scene_names: Vec::new(),
+            polygon_scene_names: Vec::new(),
+            buffer_names: Vec::new(),
+            scene_object_names: Vec::new(),
+            t_profile_last_sent: Instant::now(),
+
+            bpm: 0.0,
+
+            trace_capture: None,
+
+            camera_recorder: None,
+            sync_tracks_path: None,
+
             project_data: ProjectData::new(demo_yml_path)?,
         };
 
@@ -179,10 +252,56 @@ impl PreviewState {
             &self.project_data.project_root,
             embedded,
         )?;
-        build_timeline(&mut dmo_gfx, &dmo_data)?;
+        build_timeline(&mut dmo_gfx, &dmo_data, &track_name_to_idx)?;
 
         self.track_names = track_names;
         self.track_name_to_idx = track_name_to_idx;
+        self.sync_tracks_path = match self.project_data.project_root {
+            Some(ref p) if !embedded && !dmo_data.context.sync_tracks_path.is_empty() => {
+                Some(p.join(PathBuf::from(&dmo_data.context.sync_tracks_path)))
+            }
+            _ => None,
+        };
+        self.profile_names = dmo_data.context.index.profile_names.clone();
+        self.composition_names = dmo_data
+            .timeline
+            .compositions
+            .iter()
+            .map(|c| c.name.clone())
+            .collect();
+        self.quad_scene_names = dmo_data
+            .context
+            .quad_scenes
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+        self.polygon_scene_names = dmo_data
+            .context
+            .polygon_scenes
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+        self.buffer_names = dmo_data
+            .context
+            .frame_buffers
+            .iter()
+            .map(|x| x.name.clone())
+            .collect();
+        self.scene_object_names = dmo_data
+            .context
+            .polygon_scenes
+            .iter()
+            .map(|x| {
+                x.scene_objects
+                    .iter()
+                    .map(|o| match o.id {
+                        Some(ref id) => id.clone(),
+                        None => o.name.clone(),
+                    })
+                    .collect()
+            })
+            .collect();
+        self.bpm = dmo_data.get_bpm(dmo_gfx.sync.device.bpm)?;
         self.dmo_gfx = dmo_gfx;
 
         self.should_recompile = true;
@@ -260,11 +379,18 @@ impl PreviewState {
         let mut err_msg_buf = [32 as u8; ERR_MSG_LEN];
 
         for scene_idx in 0..self.dmo_gfx.context.quad_scenes.len() {
+            let t_start = Instant::now();
             match self
                 .dmo_gfx
                 .compile_quad_scene(scene_idx as usize, &mut err_msg_buf)
             {
-                Ok(_) => {}
+                Ok(_) => {
+                    let name = match self.quad_scene_names.get(scene_idx) {
+                        Some(x) => format! {"Compile Quad Scene {}", x},
+                        None => format! {"Compile Quad Scene {}", scene_idx},
+                    };
+                    self.trace_span(&name, TRACE_CAT_SHADER, t_start);
+                }
                 Err(e) => {
                     let msg = String::from_utf8(err_msg_buf.to_vec())?;
                     return Err(Box::new(ToolError::Runtime(e, msg)));
@@ -275,11 +401,15 @@ impl PreviewState {
         // Compile shaders of all Meshes of all Models
 
         for model_idx in 0..self.dmo_gfx.context.polygon_context.models.len() {
+            let t_start = Instant::now();
             match self
                 .dmo_gfx
                 .compile_model_shaders(model_idx, &mut err_msg_buf)
             {
-                Ok(_) => {}
+                Ok(_) => {
+                    let name = format! {"Compile Model {}", model_idx};
+                    self.trace_span(&name, TRACE_CAT_SHADER, t_start);
+                }
                 Err(e) => {
                     let msg = String::from_utf8(err_msg_buf.to_vec())?;
                     return Err(Box::new(ToolError::Runtime(e, msg)));
@@ -296,6 +426,18 @@ impl PreviewState {
     }
 
     pub fn set_shader(&mut self, shader_idx: usize, content: &str) -> Result<(), ToolError> {
+        let t_start = Instant::now();
+        let res = self.set_shader_and_recompile(shader_idx, content);
+        let name = format! {"Set Shader {}", shader_idx};
+        self.trace_span(&name, TRACE_CAT_SHADER, t_start);
+        res
+    }
+
+    fn set_shader_and_recompile(
+        &mut self,
+        shader_idx: usize,
+        content: &str,
+    ) -> Result<(), ToolError> {
         // save a copy of the current shader to restore it if the new shader errors
         let prev_content = match self.dmo_gfx.get_shader_src(shader_idx) {
             Ok(x) => x,
@@ -412,17 +554,35 @@ impl PreviewState {
     }
 
     pub fn draw(&mut self) {
-        self.dmo_gfx.draw();
+        if let Some(ref mut trace) = self.trace_capture {
+            let mut spans = Vec::new();
+            self.dmo_gfx.draw_with_spans(&mut spans);
+            let names = DrawOpNames {
+                quad_scenes: &self.quad_scene_names,
+                polygon_scenes: &self.polygon_scene_names,
+                buffers: &self.buffer_names,
+                profiles: &self.profile_names,
+            };
+            trace.add_draw_op_spans(&spans, &names);
+        } else {
+            self.dmo_gfx.draw();
+        }
     }
 
     pub fn update_time_frame_start(&mut self) {
         self.t_frame_start = Instant::now();
         self.dmo_gfx.update_time_frame_start(self.t_frame_start);
 
+        if let Some(ref mut trace) = self.trace_capture {
+            trace.frame_start(self.t_frame_start);
+        }
+
         if !self.get_is_paused() {
+            let t_prev = self.get_time();
             let d = self.get_sync_device_mut();
             d.time += 16; // 1s / 60 frames
             d.set_row_from_time();
+            self.apply_loop_and_end(t_prev);
         }
     }
 
@@ -433,8 +593,173 @@ impl PreviewState {
         self.dmo_gfx.context.set_time(time);
     }
 
+    /// Jumps back to the start of the loop region when the time passed its end. At
+    /// `total_length`, loops to the start or stops.
+    fn apply_loop_and_end(&mut self, t_prev: f64) {
+        let time = self.get_time();
+
+        if let Some(r) = self.dmo_gfx.timeline.loop_region {
+            if r.start < r.end && t_prev < r.end && r.end <= time {
+                self.set_time(r.start);
+                return;
+            }
+        }
+
+        let total_length = self.dmo_gfx.settings.total_length;
+        if total_length <= time {
+            if self.dmo_gfx.settings.loop_at_end {
+                self.set_time(0.0);
+            } else {
+                self.set_time(total_length);
+                self.set_is_paused(true);
+            }
+        }
+    }
+
+    pub fn set_markers(&mut self, markers: &[d_timeline::Marker]) {
+        if self.bpm <= 0.0 && markers.iter().any(|m| m.time.is_musical()) {
+            error! {"🔥 Markers in bars and beats need a bpm above zero: {}", self.bpm};
+            return;
+        }
+        self.dmo_gfx
+            .timeline
+            .set_markers(build_markers(markers, self.bpm));
+    }
+
+    pub fn set_loop_region(&mut self, loop_region: &Option<d_timeline::LoopRegion>) {
+        if let Some(ref r) = loop_region {
+            if self.bpm <= 0.0 && (r.start.is_musical() || r.end.is_musical()) {
+                error! {"🔥 A loop region in bars and beats needs a bpm above zero: {}", self.bpm};
+                return;
+            }
+        }
+        self.dmo_gfx.timeline.loop_region = build_loop_region(loop_region, self.bpm);
+    }
+
+    /// Sets the flags of a timeline track or block, see `SetTimelineFlagsMsg`.
+    pub fn set_timeline_flags(&mut self, msg: &SetTimelineFlagsMsg) -> Result<(), Box<dyn Error>> {
+        let timeline = &mut self.dmo_gfx.timeline;
+
+        let compositions = self
+            .composition_names
+            .iter()
+            .zip(timeline.compositions.iter_mut().map(|c| &mut c.tracks));
+        d_timeline::set_track_flags(
+            &mut timeline.tracks,
+            compositions,
+            &msg.composition,
+            msg.track_idx,
+            msg.block_idx,
+            msg.flags,
+        )?;
+
+        timeline.build_index();
+        self.draw_anyway = true;
+
+        Ok(())
+    }
+
+    pub fn jump_to_marker(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
+        match self.dmo_gfx.timeline.get_marker_time(name) {
+            Some(time) => {
+                self.jump_to_time(time);
+                Ok(())
+            }
+            None => Err(From::from(format! {"no such marker: {}", name})),
+        }
+    }
+
+    pub fn jump_to_next_marker(&mut self) {
+        if let Some(time) = self.dmo_gfx.timeline.next_marker_time(self.get_time()) {
+            self.jump_to_time(time);
+        }
+    }
+
+    pub fn jump_to_prev_marker(&mut self) {
+        if let Some(time) = self.dmo_gfx.timeline.prev_marker_time(self.get_time()) {
+            self.jump_to_time(time);
+        }
+    }
+
+    fn jump_to_time(&mut self, time: f64) {
+        self.set_time(time);
+        self.draw_anyway = true;
+    }
+
     pub fn update_time_frame_end(&mut self) {
-        self.dmo_gfx.update_time_frame_end(Instant::now());
+        let t_frame_end = Instant::now();
+        self.dmo_gfx.update_time_frame_end(t_frame_end);
+
+        let is_done = match self.trace_capture {
+            Some(ref mut trace) => {
+                trace.frame_end(t_frame_end);
+                trace.is_done()
+            }
+            None => false,
+        };
+
+        if is_done {
+            if let Some(trace) = self.trace_capture.take() {
+                match trace.write_file() {
+                    Ok(_) => {
+                        info! {"🎀 Trace of {} frames saved to {:?}", trace.frame_count, &trace.path}
+                    }
+                    Err(e) => error! {"🔥 Can't write trace file {:?}: {:?}", &trace.path, e},
+                }
+            }
+        }
+    }
+
+    /// Starts capturing a trace of the next `frames` frames. When done, it is written to `path`,
+    /// or to `trace.json` in the project root (or the current folder) when `path` is `None`.
+    pub fn start_trace_capture(&mut self, frames: usize, path: Option<PathBuf>) {
+        let path = match path {
+            Some(x) => x,
+            None => match self.project_data.project_root {
+                Some(ref root) => root.join("trace.json"),
+                None => PathBuf::from("trace.json"),
+            },
+        };
+        info! {"Capturing a trace of {} frames to {:?}", frames, &path};
+        self.trace_capture = Some(TraceCapture::new(path, frames));
+    }
+
+    /// Adds a span from `t_start` until now to the trace, when capturing.
+    fn trace_span(&mut self, name: &str, cat: &str, t_start: Instant) {
+        if let Some(ref mut trace) = self.trace_capture {
+            trace.add_span(name, cat, t_start, Instant::now());
+        }
+    }
+
+    /// Returns the profiling results of a recent frame with the profile names, when there are
+    /// new results and `PROFILE_SEND_INTERVAL` has passed since the last time.
+    pub fn get_profile_results(&mut self) -> Option<ProfileResultsMsg> {
+        if self.t_profile_last_sent.elapsed() < PROFILE_SEND_INTERVAL {
+            return None;
+        }
+
+        let profile_names = &self.profile_names;
+        let profiler = &mut self.dmo_gfx.context.profiler;
+        if !profiler.has_new_results {
+            return None;
+        }
+        profiler.has_new_results = false;
+        self.t_profile_last_sent = Instant::now();
+
+        let events = profiler
+            .results
+            .iter()
+            .map(|r| ProfileEventMsg {
+                name: match profile_names.get(r.label_idx) {
+                    Some(x) => x.clone(),
+                    None => format! {"{}", r.label_idx},
+                },
+                gpu_ms: r.gpu_ms,
+                cpu_ms: r.cpu_ms,
+            })
+            .collect();
+
+        Some(ProfileResultsMsg { events })
     }
 
     pub fn move_time_ms(&mut self, ms: i32) {
@@ -452,6 +777,7 @@ impl PreviewState {
     }
 
     pub fn update_rocket(&mut self, rocket: &mut Option<SyncClient>) -> Result<(), Box<dyn Error>> {
+        let t_start = Instant::now();
         let mut set_rocket_none = false;
         if let Some(ref mut r) = *rocket {
             match r.update(self.get_sync_device_mut()) {
@@ -483,6 +809,10 @@ impl PreviewState {
             }
         }
 
+        if rocket.is_some() {
+            self.trace_span("Rocket Update", TRACE_CAT_ROCKET, t_start);
+        }
+
         Ok(())
     }
 
@@ -547,15 +877,30 @@ impl PreviewState {
             .mouse
             .update_mouse_moved(mouse_x, (wy as i32) - mouse_y);
 
-        if self.explore_mode && self.dmo_gfx.context.mouse.pressed[0] {
-            self.dmo_gfx
-                .context
-                .camera
-                .do_pitch_and_yaw_from_mouse_delta(
-                    self.dmo_gfx.context.mouse.delta_x,
-                    self.dmo_gfx.context.mouse.delta_y,
-                );
-            self.draw_anyway = true;
+        if !self.explore_mode {
+            return;
+        }
+
+        let mouse = &self.dmo_gfx.context.mouse;
+        let (dx, dy) = (mouse.delta_x, mouse.delta_y);
+        let camera = &mut self.dmo_gfx.context.camera;
+
+        match camera.control {
+            CameraControl::FreeFly => {
+                if mouse.pressed[0] {
+                    camera.do_pitch_and_yaw_from_mouse_delta(dx, dy);
+                    self.draw_anyway = true;
+                }
+            }
+            CameraControl::Orbit => {
+                if mouse.pressed[0] {
+                    camera.orbit_from_mouse_delta(dx, dy);
+                    self.draw_anyway = true;
+                } else if mouse.pressed[2] {
+                    camera.orbit_pan(dx, dy);
+                    self.draw_anyway = true;
+                }
+            }
         }
     }
 
@@ -571,6 +916,10 @@ impl PreviewState {
             _ => Btn::NoButton,
         };
         self.dmo_gfx.context.mouse.update_mouse_input(pressed, btn);
+
+        if pressed && button == MouseButton::Right {
+            self.pick_object_at_mouse();
+        }
     }
 
     pub fn callback_mouse_wheel(&mut self, dy: f32) {
@@ -578,6 +927,12 @@ impl PreviewState {
             return;
         }
 
+        if self.dmo_gfx.context.camera.control == CameraControl::Orbit {
+            self.dmo_gfx.context.camera.orbit_zoom(dy);
+            self.draw_anyway = true;
+            return;
+        }
+
         if self.dmo_gfx.context.camera.fovy_angle >= 1.0
             && self.dmo_gfx.context.camera.fovy_angle <= 45.0
         {
@@ -686,6 +1041,11 @@ impl PreviewState {
         self.dmo_gfx.context.camera.set_position(a);
         let a = self.get_context_camera_front();
         self.dmo_gfx.context.camera.set_front(a);
+
+        let camera = &mut self.dmo_gfx.context.camera;
+        if camera.control == CameraControl::Orbit {
+            camera.target = &camera.position + &(&camera.front * camera.distance);
+        }
     }
 
     pub fn get_context_camera_position(&self) -> Vector3 {
@@ -707,7 +1067,7 @@ impl PreviewState {
     }
 
     pub fn update_camera_from_keys(&mut self) {
-        if !self.explore_mode {
+        if !self.explore_mode || self.dmo_gfx.context.camera.control == CameraControl::Orbit {
             return;
         }
 
@@ -735,6 +1095,211 @@ impl PreviewState {
         }
         self.dmo_gfx.context.camera.update_view();
     }
+
+    /// Switches the explore mode camera between free-fly and orbit control.
+    pub fn toggle_orbit_camera(&mut self) {
+        let camera = &mut self.dmo_gfx.context.camera;
+        let control = match camera.control {
+            CameraControl::FreeFly => CameraControl::Orbit,
+            CameraControl::Orbit => CameraControl::FreeFly,
+        };
+        camera.set_control(control);
+        info! {"Camera control: {:?}", control};
+    }
+
+    /// Selects the next scene object, going through the objects of each polygon scene in order.
+    pub fn select_next_object(&mut self) {
+        let scenes = &self.dmo_gfx.context.polygon_scenes;
+
+        let mut next = match self.selected_object {
+            Some((scene_idx, object_idx)) => (scene_idx, object_idx + 1),
+            None => (0, 0),
+        };
+        // Skip to the next scene with objects, and wrap around to the first.
+        for _ in 0..=scenes.len() {
+            match scenes.get(next.0) {
+                Some(scene) if next.1 < scene.scene_objects.len() => break,
+                Some(_) => next = (next.0 + 1, 0),
+                None => next = (0, 0),
+            }
+        }
+
+        let selected = match scenes.get(next.0) {
+            Some(scene) if next.1 < scene.scene_objects.len() => Some(next),
+            _ => None,
+        };
+        self.set_selected_object(selected);
+    }
+
+    /// Selects the nearest scene object under the mouse cursor which the ray from the camera hits
+    /// a triangle of. Only the polygon scenes of the active timeline blocks are tested, which are
+    /// the ones on the screen.
+    pub fn pick_object_at_mouse(&mut self) {
+        let (wx, wy) = self.get_window_resolution();
+        let context = &self.dmo_gfx.context;
+
+        // The mouse position is in lower-left coords.
+        let ndc_x = 2.0 * context.mouse.last_x as f32 / wx as f32 - 1.0;
+        let ndc_y = 2.0 * context.mouse.last_y as f32 / wy as f32 - 1.0;
+        let ray = context.polygon_context.pick_ray(ndc_x, ndc_y);
+
+        let mut nearest: Option<((usize, usize), f32)> = None;
+        for scene_idx in self.dmo_gfx.active_polygon_scenes().into_iter() {
+            let scene = match context.polygon_scenes.get(scene_idx) {
+                Some(x) => x,
+                None => continue,
+            };
+            let hit = scene.intersect_ray(&ray, &context.polygon_context.models);
+            nearest = match (nearest, hit) {
+                (Some((_, x)), Some((object_idx, t))) if t < x => {
+                    Some(((scene_idx, object_idx), t))
+                }
+                (None, Some((object_idx, t))) => Some(((scene_idx, object_idx), t)),
+                _ => nearest,
+            };
+        }
+
+        self.set_selected_object(nearest.map(|x| x.0));
+    }
+
+    fn set_selected_object(&mut self, selected: Option<(usize, usize)>) {
+        self.selected_object = selected;
+        self.selection_changed = true;
+
+        match self.get_selected_object_msg() {
+            Some(x) => info! {"Selected {} in {}", x.object, x.scene},
+            None => info! {"Nothing is selected"},
+        }
+    }
+
+    /// The names of the selected scene object and its polygon scene.
+    pub fn get_selected_object_msg(&self) -> Option<SelectedObjectMsg> {
+        let (scene_idx, object_idx) = self.selected_object?;
+        Some(SelectedObjectMsg {
+            scene: self.polygon_scene_names.get(scene_idx)?.clone(),
+            object: self
+                .scene_object_names
+                .get(scene_idx)?
+                .get(object_idx)?
+                .clone(),
+        })
+    }
+
+    /// Switches to the orbit camera, and frames the bounding box of the selected scene object,
+    /// or of every object of the polygon scenes when none is selected.
+    pub fn frame_selected_object(&mut self) -> Result<(), Box<dyn Error>> {
+        let context = &mut self.dmo_gfx.context;
+        let models = &context.polygon_context.models;
+
+        let mut bounds: Option<Aabb> = None;
+        for (scene_idx, scene) in context.polygon_scenes.iter().enumerate() {
+            for (object_idx, o) in scene.scene_objects.iter().enumerate() {
+                if let Some(x) = self.selected_object {
+                    if x != (scene_idx, object_idx) {
+                        continue;
+                    }
+                }
+                let model = models
+                    .get(o.model_idx)
+                    .ok_or("Model index is out of bounds")?;
+                if let Some(a) = model.bounding_box(&o.model_matrix) {
+                    bounds = match bounds {
+                        None => Some(a),
+                        Some(b) => Some(b.union(&a)),
+                    };
+                }
+            }
+        }
+
+        match bounds {
+            Some(a) => {
+                context.camera.set_control(CameraControl::Orbit);
+                context.camera.frame_bounds(&a.min, &a.max);
+                self.draw_anyway = true;
+                Ok(())
+            }
+            None => Err(From::from(
+                "There is no scene object with vertices to frame",
+            )),
+        }
+    }
+
+    /// Starts recording the explore mode camera, or stops and writes the recording as keys on
+    /// the `Camera_Pos_*`, `Camera_Front_*` and `Camera_LookAt_*` tracks of the sync device. The
+    /// tracks are also saved next to the Rocket file of the project, see `save_camera_tracks()`.
+    pub fn toggle_camera_recording(&mut self) {
+        match self.camera_recorder.take() {
+            Some(recorder) => {
+                let device = &mut self.dmo_gfx.sync.device;
+                match recorder.write_keys(device, &self.track_name_to_idx) {
+                    Ok(0) => {
+                        info! {"🎥 Nothing was recorded"};
+                        return;
+                    }
+                    Ok(n) => {
+                        info! {"🎥 Camera recorded from {} rows to {} keys", recorder.samples.len(), n}
+                    }
+                    Err(e) => {
+                        error! {"🔥 Can't write the camera keys: {:?}", e};
+                        return;
+                    }
+                }
+                self.draw_anyway = true;
+                match self.save_camera_tracks() {
+                    Ok(p) => {
+                        info! {"🎥 Camera tracks saved to {:?}, open it in the Rocket editor to keep them", p}
+                    }
+                    Err(e) => error! {"🔥 Can't save the camera tracks: {:?}", e},
+                }
+            }
+            None => {
+                if !self.explore_mode {
+                    warn! {"The camera can only be recorded in explore mode"};
+                    return;
+                }
+                info! {"🎥 Recording the camera"};
+                self.camera_recorder = Some(CameraRecorder::new(CAMERA_RECORD_TOLERANCE));
+            }
+        }
+    }
+
+    /// Writes a copy of the Rocket XML file of the project with the keys of the camera tracks,
+    /// as `<name>.recorded.rocket` next to it. The Rocket editor only sends keys to the demo, and
+    /// it overwrites the project file when it saves, so the recording reaches it when the copy
+    /// is opened in it.
+    fn save_camera_tracks(&self) -> Result<PathBuf, Box<dyn Error>> {
+        let p = match self.sync_tracks_path {
+            Some(ref p) => p,
+            None => {
+                return Err(From::from(
+                    "The project doesn't have a Rocket file to save to",
+                ))
+            }
+        };
+
+        let text = camera_recorder::write_rocket_xml(
+            &file_to_string(p)?,
+            &self.dmo_gfx.sync.device,
+            &self.track_name_to_idx,
+        )?;
+
+        let recorded_path = p.with_extension("recorded.rocket");
+        fs::write(&recorded_path, text)?;
+
+        Ok(recorded_path)
+    }
+
+    /// Adds the explore mode camera at the current row to the recording, when recording.
+    pub fn record_camera_sample(&mut self) {
+        if let Some(ref mut recorder) = self.camera_recorder {
+            let camera = &self.dmo_gfx.context.camera;
+            recorder.add_sample(
+                self.dmo_gfx.sync.device.row,
+                camera.get_position(),
+                camera.get_front(),
+            );
+        }
+    }
 }
 
 fn builtin_to_idx(
@@ -785,6 +1350,17 @@ fn builtin_to_idx(
         Light_Quadratic_Falloff => Ok(29),
         Light_Cutoff_Angle => Ok(30),
 
+        Block_Time => Ok(31),
+        Block_Progress => Ok(32),
+        Block_Fade => Ok(33),
+
+        Beat => Ok(34),
+        Bar => Ok(35),
+        Beat_Phase => Ok(36),
+
+        Frame_Index => Ok(37),
+        Delta_Time => Ok(38),
+
         Custom(name) => match track_name_to_idx.get(name) {
             Some(n) => Ok(*n),
             None => Err(From::from(format! {"Track not found: {}", name})),
@@ -803,40 +1379,7 @@ fn build_track_names(
 
     // First, add the names of the builtin tracks and record their indexes.
 
-    // TODO produce this list from the sync mod where builtins are known
-    let builtin_names: Vec<String> = vec![
-        "Time".to_owned(),
-        "Window_Width".to_owned(),
-        "Window_Height".to_owned(),
-        "Screen_Width".to_owned(),
-        "Screen_Height".to_owned(),
-        "Camera_Pos_X".to_owned(),
-        "Camera_Pos_Y".to_owned(),
-        "Camera_Pos_Z".to_owned(),
-        "Camera_Front_X".to_owned(),
-        "Camera_Front_Y".to_owned(),
-        "Camera_Front_Z".to_owned(),
-        "Camera_Up_X".to_owned(),
-        "Camera_Up_Y".to_owned(),
-        "Camera_Up_Z".to_owned(),
-        "Camera_LookAt_X".to_owned(),
-        "Camera_LookAt_Y".to_owned(),
-        "Camera_LookAt_Z".to_owned(),
-        "Fovy".to_owned(),
-        "Znear".to_owned(),
-        "Zfar".to_owned(),
-        "Light_Pos_X".to_owned(),
-        "Light_Pos_Y".to_owned(),
-        "Light_Pos_Z".to_owned(),
-        "Light_Dir_X".to_owned(),
-        "Light_Dir_Y".to_owned(),
-        "Light_Dir_Z".to_owned(),
-        "Light_Strength".to_owned(),
-        "Light_Constant_Falloff".to_owned(),
-        "Light_Linear_Falloff".to_owned(),
-        "Light_Quadratic_Falloff".to_owned(),
-        "Light_Cutoff_Angle".to_owned(),
-    ];
+    let builtin_names: Vec<String> = BUILTIN_NAMES.iter().map(|n| n.to_string()).collect();
 
     let mut track_names: Vec<String> = Vec::new();
     let mut track_name_to_idx: BTreeMap<String, usize> = BTreeMap::new();
@@ -901,12 +1444,34 @@ fn build_track_names(
         _ => return Err(From::from("no members in 'tracks'")),
     }
 
-    // new sync device
+    // The Rocket device has a track for each name, in the order of the track names, so that the
+    // Rocket device track idx = Sync var idx. The builtin tracks keep the indexes of their sync
+    // vars wherever they are in the XML, and the custom tracks follow them in the XML order.
+
     let mut sync_device = SyncDevice::new(bpm, rpb);
+    for _ in track_names.iter() {
+        sync_device.tracks.push(SyncTrack::new());
+    }
 
-    // add tracks and keys
     for t in tracks.iter() {
-        let mut sync_track: SyncTrack = SyncTrack::new();
+        let n = t.attributes.get("name").ok_or("missing 'name'")?;
+        let name = n[0].clone();
+
+        let idx = match track_name_to_idx.get(&name) {
+            Some(idx) if *idx < BUILTIN_NAMES.len() => *idx,
+            Some(_) => {
+                return Err(From::from(format! {
+                    "Track name is used more than once: {}", name
+                }))
+            }
+            None => {
+                let idx = track_names.len();
+                track_names.push(name.clone());
+                track_name_to_idx.insert(name, idx);
+                sync_device.tracks.push(SyncTrack::new());
+                idx
+            }
+        };
 
         if let Content::Members(ref track) = t.members {
             let keys = track.get("key").ok_or("missing 'key'")?;
@@ -930,21 +1495,9 @@ fn build_track_names(
                     key_type: code_to_key(key_type),
                 };
 
-                sync_track.add_key(key);
+                sync_device.tracks[idx].add_key(key);
             }
         }
-
-        sync_device.tracks.push(sync_track);
-    }
-
-    // FIXME This only works without a start_idx when the Rocket XML already contains the builtin
-    // tracks. Recognize the case when these are missing and use start_idx = track_names.len().
-
-    // add track names to list and index
-    for (idx, track) in tracks.iter().enumerate() {
-        let n = track.attributes.get("name").ok_or("missing 'name'")?;
-        track_names.push(n[0].clone());
-        track_name_to_idx.insert(n[0].clone(), idx);
     }
 
     // Assign the new product
@@ -956,9 +1509,51 @@ fn build_track_names(
         .sync_vars
         .add_tracks_up_to(track_names.len());
 
+    check_track_indexes(&track_name_to_idx)?;
+
+    // Name the custom sync vars, so that shader uniforms can be bound to them by name. The
+    // builtin names are already set by SyncVars.
+    for (name, idx) in track_name_to_idx.iter() {
+        if BUILTIN_NAMES.contains(&name.as_str()) {
+            continue;
+        }
+        if let Err(e) = dmo_gfx.context.sync_vars.set_name(*idx, name) {
+            return Err(Box::new(ToolError::Runtime(e, "".to_owned())));
+        }
+    }
+
     Ok((track_names, track_name_to_idx))
 }
 
+/// Checks that the builtin tracks are at the indexes of their sync vars, and that no two tracks
+/// share an index, so that a Rocket track never drives a builtin or another track.
+fn check_track_indexes(track_name_to_idx: &BTreeMap<String, usize>) -> Result<(), Box<dyn Error>> {
+    let mut names_at_idx: BTreeMap<usize, &str> = BTreeMap::new();
+
+    for (name, idx) in track_name_to_idx.iter() {
+        let builtin = BUILTIN_NAMES.get(*idx);
+        if BUILTIN_NAMES.contains(&name.as_str()) {
+            if builtin != Some(&name.as_str()) {
+                return Err(From::from(format! {
+                    "The builtin track {} is at index {}", name, idx
+                }));
+            }
+        } else if let Some(builtin) = builtin {
+            return Err(From::from(format! {
+                "The track {} is at index {} of the builtin {}", name, idx, builtin
+            }));
+        }
+
+        if let Some(other) = names_at_idx.insert(*idx, name) {
+            return Err(From::from(format! {
+                "The tracks {} and {} are both at index {}", other, name, idx
+            }));
+        }
+    }
+
+    Ok(())
+}
+
 fn build_shader_sources(dmo_gfx: &mut DmoGfx, dmo_data: &DmoData) {
     for i in dmo_data.context.shader_sources.iter() {
         dmo_gfx.context.shader_sources.push(i.as_bytes().to_vec());
@@ -999,6 +1594,7 @@ fn build_settings(dmo_gfx: &mut DmoGfx, dmo_data: &DmoData) {
         mouse_sensitivity: dmo_data.settings.mouse_sensitivity,
         movement_sensitivity: dmo_data.settings.movement_sensitivity,
         total_length: dmo_data.settings.total_length,
+        loop_at_end: dmo_data.settings.loop_at_end,
     };
     dmo_gfx.settings = settings;
 }
@@ -1086,6 +1682,11 @@ fn build_quad_scenes(
                     builtin_to_idx(track_name_to_idx, c)? as u8,
                     builtin_to_idx(track_name_to_idx, d)? as u8,
                 ),
+
+                d::UniformMapping::LightSpaceMatrix(layout_idx, light_idx) => {
+                    check_shadow_light(dmo_data, *light_idx)?;
+                    UniformMapping::LightSpaceMatrix(*layout_idx, *light_idx)
+                }
             };
             layout_to_vars.push(a);
         }
@@ -1098,6 +1699,11 @@ fn build_quad_scenes(
                     let buffer_idx = dmo_data.context.index.get_buffer_index(&buffer_name)?;
                     BufferMapping::Sampler2D(*layout_idx, buffer_idx as u8)
                 }
+
+                d::BufferMapping::ShadowMap(layout_idx, light_idx) => {
+                    check_shadow_light(dmo_data, *light_idx)?;
+                    BufferMapping::ShadowMap(*layout_idx, *light_idx)
+                }
             };
             binding_to_buffers.push(a);
         }
@@ -1110,6 +1716,7 @@ fn build_quad_scenes(
             frag_src_idx,
             layout_to_vars,
             binding_to_buffers,
+            q.bind_uniforms_by_name,
         );
     }
 
@@ -1159,6 +1766,104 @@ fn build_polygon_context_and_scenes(
         }
     }
 
+    // Add the lights.
+
+    let lights = &dmo_data.context.polygon_context.lights;
+    if lights.len() > MAX_LIGHTS {
+        return Err(From::from(format! {
+            "There are {} lights, but the shaders support at most {}",
+            lights.len(),
+            MAX_LIGHTS
+        }));
+    }
+
+    for light_data in lights.iter() {
+        let mut light = Light::new(match light_data.light_type {
+            d::polygon_context::LightType::Point => LightType::Point,
+            d::polygon_context::LightType::Directional => LightType::Directional,
+            d::polygon_context::LightType::Spot => LightType::Spot,
+        });
+
+        let t = track_name_to_idx;
+        if let Some(ref x) = light_data.position {
+            light.position_var = to_value_vec3(x, t)?;
+        }
+        if let Some(ref x) = light_data.direction {
+            light.direction_var = to_value_vec3(x, t)?;
+        }
+        if let Some(ref x) = light_data.color {
+            light.color_var = to_value_vec3(x, t)?;
+        }
+        if let Some(ref x) = light_data.strength {
+            light.strength_var = to_value_float(x, t)?;
+        }
+        if let Some(ref x) = light_data.falloff {
+            light.falloff_var = to_value_vec3(x, t)?;
+        }
+        if let Some(ref x) = light_data.cutoff_angle {
+            light.cutoff_angle_var = to_value_float(x, t)?;
+        }
+        if let Some(ref x) = light_data.outer_cutoff_angle {
+            light.outer_cutoff_angle_var = to_value_float(x, t)?;
+        }
+
+        if let Some(ref shadow) = light_data.shadow {
+            if let LightType::Point = light.light_type {
+                return Err(From::from(
+                    "Shadows are only supported for Directional and Spot lights",
+                ));
+            }
+            if shadow.resolution == 0 {
+                return Err(From::from("The shadow map resolution must not be zero"));
+            }
+
+            let mut shadow_map = ShadowMap::new(shadow.resolution as i32, shadow.bias);
+            if let Some(x) = shadow.extent {
+                shadow_map.extent = x;
+            }
+            if let Some(x) = shadow.znear {
+                shadow_map.znear = x;
+            }
+            if let Some(x) = shadow.zfar {
+                shadow_map.zfar = x;
+            }
+            // Matrix4 panics when the opposite planes of the projection are superimposed.
+            if shadow_map.extent <= 0.0 {
+                return Err(From::from(
+                    "The shadow map extent must be greater than zero",
+                ));
+            }
+            if shadow_map.znear <= 0.0 || shadow_map.zfar <= shadow_map.znear {
+                return Err(From::from(format! {
+                    "The shadow map must have 0 < znear < zfar, but znear is {} and zfar is {}",
+                    shadow_map.znear, shadow_map.zfar
+                }));
+            }
+            light.shadow_map = Some(shadow_map);
+        }
+
+        dmo_gfx.context.polygon_context.lights.lights.push(light);
+    }
+
+    // Set the camera mode.
+
+    dmo_gfx.context.polygon_context.camera_mode = match dmo_data.context.polygon_context.camera {
+        d::polygon_context::CameraMode::Sync_Front => CameraMode::Sync_Front,
+        d::polygon_context::CameraMode::Sync_LookAt => CameraMode::Sync_LookAt,
+        d::polygon_context::CameraMode::Path(ref path) => {
+            if path.points.len() < 2 {
+                return Err(From::from("A camera path needs at least two points"));
+            }
+            let mut camera_path = CameraPath::new(
+                path.points.iter().map(|p| p.position).collect(),
+                path.points.iter().map(|p| p.look_at).collect(),
+                path.closed,
+            );
+            camera_path.progress_var = to_value_float(&path.progress, track_name_to_idx)?;
+            CameraMode::Path(camera_path)
+        }
+    };
+
     // PolygonContext is ready.
 
     // Add PolygonScenes.
@@ -1166,41 +1871,44 @@ fn build_polygon_context_and_scenes(
     for (_idx, scene) in dmo_data.context.polygon_scenes.iter().enumerate() {
         let mut polygon_scene = PolygonScene::default();
 
+        let mut id_to_idx: BTreeMap<&str, usize> = BTreeMap::new();
+        for (idx, obj_data) in scene.scene_objects.iter().enumerate() {
+            if let Some(ref id) = obj_data.id {
+                if id_to_idx.insert(id, idx).is_some() {
+                    return Err(From::from(format! {
+                        "Scene object id '{}' is used more than once in '{}'", id, scene.name
+                    }));
+                }
+            }
+        }
+
         for obj_data in scene.scene_objects.iter() {
             let mut scene_object = SceneObject::default();
             scene_object.model_idx = dmo_data.context.index.get_model_index(&obj_data.name)?;
 
-            scene_object.position_var = match &obj_data.position {
-                d::ValueVec3::NOOP => ValueVec3::NOOP,
-
-                d::ValueVec3::Fixed(a, b, c) => ValueVec3::Fixed(*a, *b, *c),
-
-                d::ValueVec3::Sync(a, b, c) => ValueVec3::Sync(
-                    builtin_to_idx(track_name_to_idx, &a)? as u8,
-                    builtin_to_idx(track_name_to_idx, &b)? as u8,
-                    builtin_to_idx(track_name_to_idx, &c)? as u8,
-                ),
-            };
+            if let Some(ref parent) = obj_data.parent {
+                match id_to_idx.get(parent.as_str()) {
+                    Some(idx) => scene_object.parent_idx = Some(*idx),
+                    None => {
+                        return Err(From::from(format! {
+                            "No scene object with the parent id '{}' in '{}'",
+                            parent,
+                            scene.name
+                        }))
+                    }
+                }
+            }
 
-            scene_object.euler_rotation_var = match &obj_data.euler_rotation {
-                d::ValueVec3::NOOP => ValueVec3::NOOP,
+            scene_object.position_var = to_value_vec3(&obj_data.position, track_name_to_idx)?;
 
-                d::ValueVec3::Fixed(a, b, c) => ValueVec3::Fixed(*a, *b, *c),
+            scene_object.euler_rotation_var =
+                to_value_vec3(&obj_data.euler_rotation, track_name_to_idx)?;
 
-                d::ValueVec3::Sync(a, b, c) => ValueVec3::Sync(
-                    builtin_to_idx(track_name_to_idx, &a)? as u8,
-                    builtin_to_idx(track_name_to_idx, &b)? as u8,
-                    builtin_to_idx(track_name_to_idx, &c)? as u8,
-                ),
-            };
+            if let Some(ref x) = obj_data.quaternion_rotation {
+                scene_object.quaternion_rotation_var = to_value_vec4(x, track_name_to_idx)?;
+            }
 
-            scene_object.scale_var = match &obj_data.scale {
-                d::ValueFloat::NOOP => ValueFloat::NOOP,
-                d::ValueFloat::Fixed(a) => ValueFloat::Fixed(*a),
-                d::ValueFloat::Sync(a) => {
-                    ValueFloat::Sync(builtin_to_idx(track_name_to_idx, &a)? as u8)
-                }
-            };
+            scene_object.scale_var = to_value_float(&obj_data.scale, track_name_to_idx)?;
 
             for i in obj_data.layout_to_vars.iter() {
                 let m = match i {
@@ -1230,6 +1938,11 @@ fn build_polygon_context_and_scenes(
                         builtin_to_idx(track_name_to_idx, &v_c)? as u8,
                         builtin_to_idx(track_name_to_idx, &v_d)? as u8,
                     ),
+
+                    d::UniformMapping::LightSpaceMatrix(layout_idx, light_idx) => {
+                        check_shadow_light(dmo_data, *light_idx)?;
+                        UniformMapping::LightSpaceMatrix(*layout_idx, *light_idx)
+                    }
                 };
 
                 scene_object.layout_to_vars.push(m);
@@ -1243,11 +1956,35 @@ fn build_polygon_context_and_scenes(
                         let buffer_idx = dmo_data.context.index.get_buffer_index(&name)?;
                         BufferMapping::Sampler2D(*layout_idx, buffer_idx as u8)
                     }
+
+                    d::BufferMapping::ShadowMap(layout_idx, light_idx) => {
+                        check_shadow_light(dmo_data, *light_idx)?;
+                        BufferMapping::ShadowMap(*layout_idx, *light_idx)
+                    }
                 };
 
                 scene_object.binding_to_buffers.push(m);
             }
 
+            scene_object.bind_uniforms_by_name = obj_data.bind_uniforms_by_name;
+            scene_object.casts_shadow = obj_data.casts_shadow;
+            scene_object.frustum_culling = obj_data.frustum_culling;
+
+            if let Some(ref instances_data) = obj_data.instances {
+                let mut instances =
+                    Instances::new(instances_data.layout.transforms(project_root, embedded)?);
+
+                if let Some(ref count) = instances_data.count {
+                    instances.count_var = to_value_float(count, track_name_to_idx)?;
+                }
+
+                if let Err(e) = instances.create_buffer() {
+                    return Err(Box::new(ToolError::Runtime(e, "".to_owned())));
+                }
+
+                scene_object.instances = Some(instances);
+            }
+
             match dmo_gfx.compile_model_shaders(scene_object.model_idx, &mut err_msg_buf) {
                 Ok(_) => {}
                 Err(e) => {
@@ -1259,71 +1996,307 @@ fn build_polygon_context_and_scenes(
             polygon_scene.scene_objects.push(scene_object);
         }
 
+        if let Err(e) = polygon_scene.set_parents() {
+            return Err(Box::new(ToolError::Runtime(
+                e,
+                format! {"The scene object parents are not valid in '{}'", scene.name},
+            )));
+        }
+
         dmo_gfx.context.polygon_scenes.push(polygon_scene);
     }
 
     Ok(())
 }
 
-fn build_timeline(dmo_gfx: &mut DmoGfx, dmo_data: &DmoData) -> Result<(), Box<dyn Error>> {
-    use crate::dmo_data::timeline::DrawOp as D;
-    use intro_runtime::timeline::DrawOp as G;
+/// Checks that a light space matrix or shadow map mapping refers to a light with a shadow map.
+fn check_shadow_light(dmo_data: &DmoData, light_idx: u8) -> Result<(), Box<dyn Error>> {
+    match dmo_data
+        .context
+        .polygon_context
+        .lights
+        .get(light_idx as usize)
+    {
+        Some(light) if light.shadow.is_some() => Ok(()),
+        Some(_) => Err(From::from(format! {
+            "Light {} doesn't have a shadow map", light_idx
+        })),
+        None => Err(From::from(format! {
+            "Light index doesn't exist: {}", light_idx
+        })),
+    }
+}
 
+fn to_value_vec3(
+    value: &crate::dmo_data::ValueVec3,
+    track_name_to_idx: &BTreeMap<String, usize>,
+) -> Result<ValueVec3, Box<dyn Error>> {
+    use crate::dmo_data as d;
+    let v = match value {
+        d::ValueVec3::NOOP => ValueVec3::NOOP,
+        d::ValueVec3::Fixed(a, b, c) => ValueVec3::Fixed(*a, *b, *c),
+        d::ValueVec3::Sync(a, b, c) => ValueVec3::Sync(
+            builtin_to_idx(track_name_to_idx, &a)? as u8,
+            builtin_to_idx(track_name_to_idx, &b)? as u8,
+            builtin_to_idx(track_name_to_idx, &c)? as u8,
+        ),
+    };
+    Ok(v)
+}
+
+fn to_value_vec4(
+    value: &crate::dmo_data::ValueVec4,
+    track_name_to_idx: &BTreeMap<String, usize>,
+) -> Result<ValueVec4, Box<dyn Error>> {
+    use crate::dmo_data as d;
+    let v = match value {
+        d::ValueVec4::NOOP => ValueVec4::NOOP,
+        d::ValueVec4::Fixed(a, b, c, d) => ValueVec4::Fixed(*a, *b, *c, *d),
+        d::ValueVec4::Sync(a, b, c, d) => ValueVec4::Sync(
+            builtin_to_idx(track_name_to_idx, &a)? as u8,
+            builtin_to_idx(track_name_to_idx, &b)? as u8,
+            builtin_to_idx(track_name_to_idx, &c)? as u8,
+            builtin_to_idx(track_name_to_idx, &d)? as u8,
+        ),
+    };
+    Ok(v)
+}
+
+fn to_value_float(
+    value: &crate::dmo_data::ValueFloat,
+    track_name_to_idx: &BTreeMap<String, usize>,
+) -> Result<ValueFloat, Box<dyn Error>> {
+    use crate::dmo_data as d;
+    let v = match value {
+        d::ValueFloat::NOOP => ValueFloat::NOOP,
+        d::ValueFloat::Fixed(a) => ValueFloat::Fixed(*a),
+        d::ValueFloat::Sync(a) => ValueFloat::Sync(builtin_to_idx(track_name_to_idx, &a)? as u8),
+    };
+    Ok(v)
+}
+
+fn build_timeline(
+    dmo_gfx: &mut DmoGfx,
+    dmo_data: &DmoData,
+    track_name_to_idx: &BTreeMap<String, usize>,
+) -> Result<(), Box<dyn Error>> {
     dmo_gfx.timeline = Timeline::new();
 
+    let bpm = dmo_data.get_bpm(dmo_gfx.sync.device.bpm)?;
+
+    let mut composition_name_to_idx: BTreeMap<String, usize> = BTreeMap::new();
+    for (idx, composition) in dmo_data.timeline.compositions.iter().enumerate() {
+        if composition_name_to_idx
+            .insert(composition.name.clone(), idx)
+            .is_some()
+        {
+            return Err(From::from(
+                format! {"duplicate composition name: {}", composition.name},
+            ));
+        }
+    }
+
+    for composition in dmo_data.timeline.compositions.iter() {
+        let mut tracks: Vec<TimeTrack> = Vec::new();
+        for track in composition.tracks.iter() {
+            tracks.push(build_track(
+                track,
+                dmo_data,
+                track_name_to_idx,
+                &composition_name_to_idx,
+                bpm,
+            )?);
+        }
+        dmo_gfx.timeline.compositions.push(Composition { tracks });
+    }
+
+    for idx in 0..dmo_gfx.timeline.compositions.len() {
+        if composition_contains(&dmo_gfx.timeline, idx, idx, 0) {
+            let name = &dmo_data.timeline.compositions[idx].name;
+            return Err(From::from(
+                format! {"composition instantiates itself: {}", name},
+            ));
+        }
+    }
+
     for track in dmo_data.timeline.tracks.iter() {
-        let mut track_gfx = TimeTrack {
-            scene_blocks: Vec::new(),
-        };
+        let track_gfx = build_track(
+            track,
+            dmo_data,
+            track_name_to_idx,
+            &composition_name_to_idx,
+            bpm,
+        )?;
+        dmo_gfx.timeline.tracks.push(track_gfx);
+    }
 
-        for block in track.scene_blocks.iter() {
-            let mut ops: Vec<G> = Vec::new();
+    dmo_gfx.timeline.build_index();
 
-            for i in block.draw_ops.iter() {
-                let o = match i {
-                    D::NOOP => G::NOOP,
+    dmo_gfx
+        .timeline
+        .set_markers(build_markers(&dmo_data.timeline.markers, bpm));
+    dmo_gfx.timeline.loop_region = build_loop_region(&dmo_data.timeline.loop_region, bpm);
 
-                    D::Draw_Quad_Scene(name) => {
-                        let idx = dmo_data.context.index.get_quad_scene_index(name)?;
-                        G::Draw_Quad_Scene(idx)
-                    }
+    Ok(())
+}
 
-                    D::Draw_Poly_Scene(name) => {
-                        let idx = dmo_data.context.index.get_polygon_scene_index(name)?;
-                        G::Draw_Poly_Scene(idx)
-                    }
+fn build_track(
+    track: &d_timeline::TimeTrack,
+    dmo_data: &DmoData,
+    track_name_to_idx: &BTreeMap<String, usize>,
+    composition_name_to_idx: &BTreeMap<String, usize>,
+    bpm: f64,
+) -> Result<TimeTrack, Box<dyn Error>> {
+    use crate::dmo_data::timeline::DrawOp as D;
+    use crate::dmo_data::timeline::Transition as D_Transition;
+    use intro_runtime::timeline::DrawOp as G;
 
-                    D::Clear(r, g, b, a) => G::Clear(*r, *g, *b, *a),
+    let mut track_gfx = TimeTrack {
+        scene_blocks: Vec::new(),
+        instances: Vec::new(),
+        enabled: track.enabled,
+        muted: track.muted,
+        solo: track.solo,
+    };
 
-                    D::Target_Buffer(name) => {
-                        let idx = dmo_data.context.index.get_buffer_index(name)?;
-                        G::Target_Buffer(idx)
-                    }
+    for block in track.scene_blocks.iter() {
+        let mut ops: Vec<G> = Vec::new();
 
-                    D::Target_Buffer_Default => G::Target_Buffer_Default,
+        for i in block.draw_ops.iter() {
+            let o = match i {
+                D::NOOP => G::NOOP,
 
-                    D::Profile(name) => {
-                        let idx = dmo_data.context.index.get_profile_index(name)?;
-                        G::Profile(idx)
-                    }
-                };
+                D::Draw_Quad_Scene(name) => {
+                    let idx = dmo_data.context.index.get_quad_scene_index(name)?;
+                    G::Draw_Quad_Scene(idx)
+                }
 
-                ops.push(o);
-            }
+                D::Draw_Poly_Scene(name) => {
+                    let idx = dmo_data.context.index.get_polygon_scene_index(name)?;
+                    G::Draw_Poly_Scene(idx)
+                }
 
-            let block_gfx = SceneBlock {
-                start: block.start,
-                end: block.end,
-                draw_ops: ops,
+                D::Clear(r, g, b, a) => G::Clear(*r, *g, *b, *a),
+
+                D::Target_Buffer(name) => {
+                    let idx = dmo_data.context.index.get_buffer_index(name)?;
+                    G::Target_Buffer(idx)
+                }
+
+                D::Target_Buffer_Default => G::Target_Buffer_Default,
+
+                D::Profile(name) => {
+                    let idx = dmo_data.context.index.get_profile_index(name)?;
+                    G::Profile(idx)
+                }
+
+                D::If_Var_Draw_Quad_Scene(var, cond, value, name) => {
+                    let var_idx = builtin_to_idx(track_name_to_idx, var)?;
+                    let idx = dmo_data.context.index.get_quad_scene_index(name)?;
+                    G::If_Var_Draw_Quad_Scene(var_idx, to_var_condition(*cond), *value, idx)
+                }
+
+                D::If_Var_Draw_Poly_Scene(var, cond, value, name) => {
+                    let var_idx = builtin_to_idx(track_name_to_idx, var)?;
+                    let idx = dmo_data.context.index.get_polygon_scene_index(name)?;
+                    G::If_Var_Draw_Poly_Scene(var_idx, to_var_condition(*cond), *value, idx)
+                }
             };
 
-            track_gfx.scene_blocks.push(block_gfx);
+            ops.push(o);
         }
 
-        dmo_gfx.timeline.tracks.push(track_gfx);
+        let transition = match &block.transition {
+            D_Transition::Crossfade => Transition::Crossfade,
+            D_Transition::Wipe => Transition::Wipe,
+            D_Transition::Custom(name) => {
+                let idx = dmo_data.context.index.get_quad_scene_index(name)?;
+                Transition::Custom(idx)
+            }
+        };
+
+        let block_gfx = SceneBlock {
+            start: block.start.to_seconds(bpm),
+            end: block.end.to_seconds(bpm),
+            draw_ops: ops,
+            fade_in: block.fade_in,
+            fade_out: block.fade_out,
+            transition,
+            enabled: block.enabled,
+            muted: block.muted,
+            solo: block.solo,
+        };
+
+        track_gfx.scene_blocks.push(block_gfx);
     }
 
-    Ok(())
+    for instance in track.instances.iter() {
+        let composition_idx = match composition_name_to_idx.get(&instance.composition) {
+            Some(x) => *x,
+            None => {
+                return Err(From::from(
+                    format! {"no such composition: {}", instance.composition},
+                ))
+            }
+        };
+
+        let time_scale = instance.time_scale.unwrap_or(1.0);
+        if time_scale <= 0.0 {
+            return Err(From::from(format! {
+                "time_scale must be above zero: {} ({})", time_scale, instance.composition
+            }));
+        }
+
+        track_gfx.instances.push(CompositionInstance {
+            composition_idx,
+            start: instance.start.to_seconds(bpm),
+            time_scale,
+            duration: instance.duration.map(|d| d.to_seconds(bpm)),
+        });
+    }
+
+    Ok(track_gfx)
+}
+
+/// Whether the composition instantiates the target composition, directly or through others.
+fn composition_contains(timeline: &Timeline, idx: usize, target_idx: usize, depth: usize) -> bool {
+    if depth > timeline.compositions.len() {
+        return false;
+    }
+    timeline.compositions[idx]
+        .tracks
+        .iter()
+        .flat_map(|t| t.instances.iter())
+        .any(|i| {
+            i.composition_idx == target_idx
+                || composition_contains(timeline, i.composition_idx, target_idx, depth + 1)
+        })
+}
+
+fn build_markers(markers: &[d_timeline::Marker], bpm: f64) -> Vec<Marker> {
+    markers
+        .iter()
+        .map(|m| Marker {
+            name: m.name.clone(),
+            time: m.time.to_seconds(bpm),
+        })
+        .collect()
+}
+
+fn build_loop_region(loop_region: &Option<d_timeline::LoopRegion>, bpm: f64) -> Option<LoopRegion> {
+    loop_region.as_ref().map(|r| LoopRegion {
+        start: r.start.to_seconds(bpm),
+        end: r.end.to_seconds(bpm),
+    })
+}
+
+fn to_var_condition(cond: crate::dmo_data::timeline::VarCondition) -> VarCondition {
+    use crate::dmo_data::timeline::VarCondition as D;
+    match cond {
+        D::Equal => VarCondition::Equal,
+        D::Above => VarCondition::Above,
+        D::Below => VarCondition::Below,
+    }
 }
 
 const EMPTY_ROCKET: &str = r#"
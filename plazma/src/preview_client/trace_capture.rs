@@ -0,0 +1,155 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use intro_runtime::dmo_gfx::DrawOpSpan;
+use intro_runtime::timeline::DrawOp;
+
+/// Records a number of preview frames and writes them as a trace in the Chrome Trace Event
+/// format, which can be opened in `chrome://tracing` or in Perfetto (https://ui.perfetto.dev).
+pub struct TraceCapture {
+    /// The file to write when the capture is done.
+    pub path: PathBuf,
+    /// Number of frames to capture.
+    pub frames: usize,
+    pub frame_count: usize,
+    t_capture_start: Instant,
+    t_frame_start: Option<Instant>,
+    events: Vec<TraceEvent>,
+}
+
+/// A complete event (phase `X`). Timestamps and durations are in microseconds.
+#[derive(Serialize, Debug)]
+pub struct TraceEvent {
+    pub name: String,
+    pub cat: String,
+    pub ph: String,
+    pub ts: f64,
+    pub dur: f64,
+    pub pid: u32,
+    pub tid: u32,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TraceFile<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: String,
+}
+
+/// The names of the scenes, frame buffers and profile labels, indexed as in the draw ops, to name
+/// the draw op spans by.
+pub struct DrawOpNames<'a> {
+    pub quad_scenes: &'a [String],
+    pub polygon_scenes: &'a [String],
+    pub buffers: &'a [String],
+    pub profiles: &'a [String],
+}
+
+impl<'a> DrawOpNames<'a> {
+    pub fn span_name(&self, span: &DrawOpSpan) -> String {
+        use DrawOp::*;
+
+        let op = match span.op {
+            Some(x) => x,
+            None => return format! {"Transition {}", span.name},
+        };
+        match op {
+            Draw_Quad_Scene(x) => format! {"Quad Scene {}", name_or_idx(self.quad_scenes, x)},
+            Draw_Poly_Scene(x) => {
+                format! {"Polygon Scene {}", name_or_idx(self.polygon_scenes, x)}
+            }
+            If_Var_Draw_Quad_Scene(_, _, _, x) => {
+                format! {"If Quad Scene {}", name_or_idx(self.quad_scenes, x)}
+            }
+            If_Var_Draw_Poly_Scene(_, _, _, x) => {
+                format! {"If Polygon Scene {}", name_or_idx(self.polygon_scenes, x)}
+            }
+            Target_Buffer(x) => format! {"Target {}", name_or_idx(self.buffers, x)},
+            Profile(x) => format! {"Profile {}", name_or_idx(self.profiles, x)},
+            Target_Buffer_Default | Clear(..) | NOOP => span.name.clone(),
+        }
+    }
+}
+
+fn name_or_idx(names: &[String], idx: usize) -> String {
+    match names.get(idx) {
+        Some(x) => x.clone(),
+        None => idx.to_string(),
+    }
+}
+
+pub const TRACE_CAT_FRAME: &str = "frame";
+pub const TRACE_CAT_DRAW: &str = "draw";
+pub const TRACE_CAT_SHADER: &str = "shader";
+pub const TRACE_CAT_ROCKET: &str = "rocket";
+
+impl TraceCapture {
+    pub fn new(path: PathBuf, frames: usize) -> TraceCapture {
+        TraceCapture {
+            path,
+            frames,
+            frame_count: 0,
+            t_capture_start: Instant::now(),
+            t_frame_start: None,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn frame_start(&mut self, t_frame_start: Instant) {
+        self.t_frame_start = Some(t_frame_start);
+    }
+
+    pub fn frame_end(&mut self, t_frame_end: Instant) {
+        if let Some(t_start) = self.t_frame_start.take() {
+            let name = format! {"Frame {}", self.frame_count};
+            self.add_span(&name, TRACE_CAT_FRAME, t_start, t_frame_end);
+            self.frame_count += 1;
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frame_count >= self.frames
+    }
+
+    pub fn add_span(&mut self, name: &str, cat: &str, t_start: Instant, t_end: Instant) {
+        let ts = self.micros_since_start(t_start);
+        let dur = duration_to_micros(t_end.saturating_duration_since(t_start));
+        self.events.push(TraceEvent {
+            name: name.to_owned(),
+            cat: cat.to_owned(),
+            ph: "X".to_owned(),
+            ts,
+            dur,
+            pid: std::process::id(),
+            tid: 0,
+        });
+    }
+
+    pub fn add_draw_op_spans(&mut self, spans: &[DrawOpSpan], names: &DrawOpNames) {
+        for s in spans.iter() {
+            self.add_span(&names.span_name(s), TRACE_CAT_DRAW, s.t_start, s.t_end);
+        }
+    }
+
+    pub fn write_file(&self) -> Result<(), Box<dyn Error>> {
+        let trace = TraceFile {
+            trace_events: &self.events,
+            display_time_unit: "ms".to_owned(),
+        };
+        let text = serde_json::to_string(&trace)?;
+        let mut file = File::create(&self.path)?;
+        file.write_all(text.as_bytes())?;
+        Ok(())
+    }
+
+    fn micros_since_start(&self, t: Instant) -> f64 {
+        duration_to_micros(t.saturating_duration_since(self.t_capture_start))
+    }
+}
+
+fn duration_to_micros(t: Duration) -> f64 {
+    (t.as_secs() as f64) * 1_000_000.0 + f64::from(t.subsec_nanos()) / 1_000.0
+}
@@ -13,6 +13,7 @@ use actix_web::actix::*;
 use actix_web::ws;
 
 use crate::app::{AppInfo, AppStartParams};
+use crate::dmo_data::timeline::{LoopRegion, Marker, TimelineFlags};
 use crate::project_data::{NewProjectTemplate, ProjectData};
 use crate::utils::clean_windows_str_path;
 
@@ -23,8 +24,8 @@ const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// `preview_client` is running the render loop. On start, it builds a Dmo using a
-/// json blob from the Server. `Dmo.timeline.draw_ops_at_time(x)` returns a
-/// `Vec<DrawOp>` which is used to draw the current frame.
+/// json blob from the Server. `Dmo.timeline.update_active_blocks(x)` selects the
+/// scene blocks whose draw ops are used to draw the current frame.
 ///
 /// `preview_client` has paused or playing state. When playing, it updates the
 /// time and sends it to Server.
@@ -298,6 +299,56 @@ impl ServerActor {
         }
     }
 
+    /// Client is sending the timeline markers. Repeat the message to the other clients and update
+    /// DmoData in the server state.
+    fn set_markers(&self, ctx: &<ServerActor as Actor>::Context, message: &Receiving) {
+        self.repeat_message_to_others(&ctx, &message);
+
+        match serde_json::from_str::<Vec<Marker>>(&message.data) {
+            Ok(markers) => {
+                let mut state = ctx.state().lock().expect("👿 Can't lock ServerState.");
+                state.project_data.dmo_data.timeline.markers = markers;
+            }
+            Err(e) => error!("🔥 Error deserializing markers: {:?}", e),
+        }
+    }
+
+    /// Client is sending the loop region, or null to clear it. Repeat the message to the other
+    /// clients and update DmoData in the server state.
+    fn set_loop_region(&self, ctx: &<ServerActor as Actor>::Context, message: &Receiving) {
+        self.repeat_message_to_others(&ctx, &message);
+
+        match serde_json::from_str::<Option<LoopRegion>>(&message.data) {
+            Ok(loop_region) => {
+                let mut state = ctx.state().lock().expect("👿 Can't lock ServerState.");
+                state.project_data.dmo_data.timeline.loop_region = loop_region;
+            }
+            Err(e) => error!("🔥 Error deserializing loop region: {:?}", e),
+        }
+    }
+
+    /// Client is toggling the flags of a track or a block. Repeat the message to the other clients
+    /// and update DmoData in the server state.
+    fn set_timeline_flags(&self, ctx: &<ServerActor as Actor>::Context, message: &Receiving) {
+        self.repeat_message_to_others(&ctx, &message);
+
+        match serde_json::from_str::<SetTimelineFlagsMsg>(&message.data) {
+            Ok(msg) => {
+                let mut state = ctx.state().lock().expect("👿 Can't lock ServerState.");
+                match state.project_data.dmo_data.timeline.set_flags(
+                    &msg.composition,
+                    msg.track_idx,
+                    msg.block_idx,
+                    msg.flags,
+                ) {
+                    Ok(_) => {}
+                    Err(e) => error!("🔥 Can't set timeline flags: {:?}", e),
+                }
+            }
+            Err(e) => error!("🔥 Error deserializing timeline flags: {:?}", e),
+        }
+    }
+
     fn set_settings(&self, ctx: &<ServerActor as Actor>::Context, message: &Receiving) {
         match serde_json::from_str(&message.data) {
             Ok(settings) => {
@@ -771,6 +822,13 @@ pub enum MsgDataType {
     SaveProject,
     NewProject,
     DeleteMessageFile,
+    ProfileResults,
+    SelectedObject,
+    StartTraceCapture,
+    SetMarkers,
+    SetLoopRegion,
+    JumpToMarker,
+    SetTimelineFlags,
     ExitApp,
 }
 
@@ -803,6 +861,53 @@ pub struct ShaderCompilationSuccessMsg {
     pub idx: usize,
 }
 
+/// Message with the timings of the `Profile` draw ops in a recent frame.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfileResultsMsg {
+    pub events: Vec<ProfileEventMsg>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProfileEventMsg {
+    pub name: String,
+    /// GPU time since the previous profile event in the frame.
+    pub gpu_ms: f32,
+    /// CPU time since the frame start.
+    pub cpu_ms: f32,
+}
+
+/// Message with the scene object which is selected in the preview, by clicking it or with Tab.
+/// The message data is `null` when nothing is selected.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SelectedObjectMsg {
+    /// Name of the polygon scene.
+    pub scene: String,
+    /// The `id` of the scene object, or the name of its model when it doesn't have one.
+    pub object: String,
+}
+
+/// Message to start capturing a trace of the preview frames. The trace is written to `path`, or to
+/// `trace.json` in the project root when it is not given.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StartTraceCaptureMsg {
+    pub frames: usize,
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+/// Message to set the flags of a timeline track, or of a block on the track when `block_idx` is
+/// given. `composition` names the composition which has the track, or is `None` for the tracks of
+/// the timeline.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SetTimelineFlagsMsg {
+    #[serde(default)]
+    pub composition: Option<String>,
+    pub track_idx: usize,
+    #[serde(default)]
+    pub block_idx: Option<usize>,
+    pub flags: TimelineFlags,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct NewProjectMsg {
     pub template: NewProjectTemplate,
@@ -931,6 +1036,20 @@ impl StreamHandler<ws::Message, ws::ProtocolError> for ServerActor {
 
                     DeleteMessageFile => self.delete_message_file(&message),
 
+                    ProfileResults => self.repeat_message_to_others(&ctx, &message),
+
+                    SelectedObject => self.repeat_message_to_others(&ctx, &message),
+
+                    StartTraceCapture => self.repeat_message_to_others(&ctx, &message),
+
+                    SetMarkers => self.set_markers(&ctx, &message),
+
+                    SetLoopRegion => self.set_loop_region(&ctx, &message),
+
+                    JumpToMarker => self.repeat_message_to_others(&ctx, &message),
+
+                    SetTimelineFlags => self.set_timeline_flags(&ctx, &message),
+
                     ExitApp => self.exit_app(ctx, &message, pid),
                 }
             }
@@ -0,0 +1,184 @@
+extern crate plazma;
+extern crate rocket_sync;
+
+use std::collections::BTreeMap;
+
+use plazma::preview_client::camera_recorder::{
+    simplify_keys, write_rocket_xml, CAMERA_RECORD_TRACKS,
+};
+use rocket_sync::{KeyType, SyncDevice, SyncTrack, TrackKey};
+
+const ROCKET_XML: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<rootElement>
+<tracks rows="10000" startRow="0" endRow="10000" rowsPerBeat="8" beatsPerMin="125">
+	<track name="Time" folded="0" muteKeyCount="0" color="ffb27474" />
+	<track name="Camera_Pos_X" folded="0" muteKeyCount="0"
+color="ff4f71b2">
+		<key row="0" value="-3.400000" interpolation="1" />
+		<key row="39" value="-20.000000" interpolation="1" />
+	</track>
+	<track name="Camera_Pos_Y" folded="0" muteKeyCount="0" color="ff8850b2" />
+	<track name="Camera_Pos_Z" folded="0" muteKeyCount="0" color="ffb25091" />
+	<track name="Camera_Front_X" folded="0" muteKeyCount="0" color="ffb27474" />
+	<track name="Camera_Front_Y" folded="0" muteKeyCount="0" color="ffb28050" />
+	<track name="Camera_Front_Z" folded="0" muteKeyCount="0" color="ffa9b250" />
+	<track name="Camera_LookAt_X" folded="0" muteKeyCount="0" color="ff8850b2" />
+	<track name="Camera_LookAt_Y" folded="0" muteKeyCount="0" color="ffb25091" />
+	<track name="Fovy" folded="0" muteKeyCount="0" color="ffb28050">
+		<key row="0" value="45.000000" interpolation="0" />
+	</track>
+</tracks>
+</rootElement>
+"#;
+
+/// A device with a track for each camera track, and one key on each except the ones in `empty`.
+fn camera_device(empty: &[&str]) -> (SyncDevice, BTreeMap<String, usize>) {
+    let mut device = SyncDevice::new(125.0, 8);
+    let mut track_name_to_idx: BTreeMap<String, usize> = BTreeMap::new();
+
+    for (idx, name) in CAMERA_RECORD_TRACKS.iter().enumerate() {
+        track_name_to_idx.insert(name.to_string(), idx);
+        let mut track = SyncTrack::new();
+        if !empty.contains(name) {
+            track.add_key(TrackKey {
+                row: 8,
+                value: idx as f32 + 0.5,
+                key_type: KeyType::Linear,
+            });
+        }
+        device.tracks.push(track);
+    }
+
+    (device, track_name_to_idx)
+}
+
+#[test]
+fn write_rocket_xml_replaces_open_track_element() {
+    let (device, track_name_to_idx) = camera_device(&[]);
+    let text = write_rocket_xml(ROCKET_XML, &device, &track_name_to_idx).unwrap();
+
+    let expect = r#"	<track name="Camera_Pos_X" folded="0" muteKeyCount="0"
+color="ff4f71b2">
+		<key row="8" value="0.500000" interpolation="1" />
+	</track>
+"#;
+    assert!(text.contains(expect), "{}", text);
+    assert!(!text.contains("-20.000000"));
+}
+
+#[test]
+fn write_rocket_xml_opens_self_closing_track_element() {
+    let (device, track_name_to_idx) = camera_device(&[]);
+    let text = write_rocket_xml(ROCKET_XML, &device, &track_name_to_idx).unwrap();
+
+    let expect = r#"	<track name="Camera_Pos_Y" folded="0" muteKeyCount="0" color="ff8850b2">
+		<key row="8" value="1.500000" interpolation="1" />
+	</track>
+"#;
+    assert!(text.contains(expect), "{}", text);
+}
+
+#[test]
+fn write_rocket_xml_closes_track_element_without_keys() {
+    let (device, track_name_to_idx) = camera_device(&["Camera_Pos_X", "Camera_Pos_Y"]);
+    let text = write_rocket_xml(ROCKET_XML, &device, &track_name_to_idx).unwrap();
+
+    let expect =
+        "\t<track name=\"Camera_Pos_X\" folded=\"0\" muteKeyCount=\"0\"\ncolor=\"ff4f71b2\" />\n";
+    assert!(text.contains(expect), "{}", text);
+    let expect =
+        "\t<track name=\"Camera_Pos_Y\" folded=\"0\" muteKeyCount=\"0\" color=\"ff8850b2\" />\n";
+    assert!(text.contains(expect), "{}", text);
+}
+
+#[test]
+fn write_rocket_xml_appends_missing_track() {
+    let (device, track_name_to_idx) = camera_device(&[]);
+    let text = write_rocket_xml(ROCKET_XML, &device, &track_name_to_idx).unwrap();
+
+    let expect = r#"	<track name="Camera_LookAt_Z">
+		<key row="8" value="8.500000" interpolation="1" />
+	</track>
+</tracks>
+</rootElement>
+"#;
+    assert!(text.ends_with(expect), "{}", text);
+    assert_eq!(text.matches("<track name=\"Camera_LookAt_Z\"").count(), 1);
+}
+
+#[test]
+fn write_rocket_xml_keeps_other_tracks() {
+    let (device, track_name_to_idx) = camera_device(&[]);
+    let text = write_rocket_xml(ROCKET_XML, &device, &track_name_to_idx).unwrap();
+
+    let head = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<rootElement>\n<tracks rows=\"10000\" startRow=\"0\" endRow=\"10000\" rowsPerBeat=\"8\" beatsPerMin=\"125\">\n\t<track name=\"Time\" folded=\"0\" muteKeyCount=\"0\" color=\"ffb27474\" />\n\t<track name=\"Camera_Pos_X\"";
+    assert!(text.starts_with(head), "{}", text);
+
+    let fovy = r#"	<track name="Fovy" folded="0" muteKeyCount="0" color="ffb28050">
+		<key row="0" value="45.000000" interpolation="0" />
+	</track>
+"#;
+    assert!(text.contains(fovy), "{}", text);
+
+    // Writing again gives the same text.
+    let again = write_rocket_xml(&text, &device, &track_name_to_idx).unwrap();
+    assert_eq!(again, text);
+}
+
+#[test]
+fn write_rocket_xml_missing_tracks_element() {
+    let (device, track_name_to_idx) = camera_device(&[]);
+    assert!(write_rocket_xml("<rootElement />", &device, &track_name_to_idx).is_err());
+}
+
+#[test]
+fn simplify_keys_within_tolerance() {
+    let tolerance = 0.01;
+    let samples: Vec<(u32, f32)> = (10..200)
+        .map(|row| (row, (row as f32 * 0.05).sin() * 3.0 + row as f32 * 0.02))
+        .collect();
+
+    let keys = simplify_keys(&samples, tolerance);
+
+    assert!(keys.len() > 1);
+    assert!(keys.len() < samples.len() / 2);
+
+    let first = keys.first().unwrap();
+    let last = keys.last().unwrap();
+    assert_eq!(first.row, 10);
+    assert_eq!(first.value, samples[0].1);
+    assert_eq!(last.row, 199);
+    assert_eq!(last.value, samples[samples.len() - 1].1);
+
+    let mut track = SyncTrack::new();
+    for k in keys.into_iter() {
+        track.add_key(k);
+    }
+    for &(row, value) in samples.iter() {
+        let e = (track.value_at(row) as f32 - value).abs();
+        assert!(e <= tolerance + 1e-4, "row {}: error {}", row, e);
+    }
+}
+
+#[test]
+fn simplify_keys_straight_line() {
+    let samples: Vec<(u32, f32)> = (0..50).map(|row| (row, row as f32 * 0.5)).collect();
+
+    let keys = simplify_keys(&samples, 0.01);
+
+    assert_eq!(keys.len(), 2);
+    assert_eq!(keys[0].row, 0);
+    assert_eq!(keys[1].row, 49);
+    assert_eq!(keys[1].value, 24.5);
+}
+
+#[test]
+fn simplify_keys_single_sample() {
+    let keys = simplify_keys(&[(5, 1.5)], 0.01);
+
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].row, 5);
+    assert_eq!(keys[0].value, 1.5);
+
+    assert!(simplify_keys(&[], 0.01).is_empty());
+}
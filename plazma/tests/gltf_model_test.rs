@@ -0,0 +1,49 @@
+extern crate plazma;
+
+use plazma::dmo_data::gltf_model::percent_decode;
+
+#[test]
+fn percent_decode_plain_uri() {
+    assert_eq!(
+        percent_decode("textures/wood.png").unwrap(),
+        "textures/wood.png"
+    );
+    assert_eq!(percent_decode("").unwrap(), "");
+}
+
+#[test]
+fn percent_decode_space() {
+    assert_eq!(
+        percent_decode("old%20wood%20(2).png").unwrap(),
+        "old wood (2).png"
+    );
+    assert_eq!(percent_decode("%2Fwood%2f").unwrap(), "/wood/");
+}
+
+#[test]
+fn percent_decode_multi_byte_utf8() {
+    assert_eq!(percent_decode("caf%C3%A9.bin").unwrap(), "café.bin");
+    assert_eq!(percent_decode("%E6%9C%A8.png").unwrap(), "木.png");
+    // Characters which are not encoded are kept.
+    assert_eq!(percent_decode("木%20.png").unwrap(), "木 .png");
+}
+
+#[test]
+fn percent_decode_truncated_sequence() {
+    assert!(percent_decode("wood%2").is_err());
+    assert!(percent_decode("wood%").is_err());
+}
+
+#[test]
+fn percent_decode_invalid_sequence() {
+    assert!(percent_decode("wood%zz.png").is_err());
+    assert!(percent_decode("wood%+1.png").is_err());
+    // The sequence ends inside a multi-byte character.
+    assert!(percent_decode("wood%木.png").is_err());
+}
+
+#[test]
+fn percent_decode_invalid_utf8() {
+    assert!(percent_decode("caf%C3.bin").is_err());
+    assert!(percent_decode("%FF").is_err());
+}